@@ -0,0 +1,53 @@
+use super::*;
+use super::util::*;
+
+use std::time::SystemTime;
+use util;
+
+fn do_list_flags(ctx: &CommandContext, guild_id: GuildId) -> Result<()> {
+    let flags = ctx.core.alt_detector().list_flags(guild_id)?;
+    if flags.is_empty() {
+        return ctx.respond("There are no flagged users waiting for review.")
+    }
+    let now = SystemTime::now();
+    let mut buffer = String::new();
+    writeln!(buffer, "Flagged users:")?;
+    for flag in flags {
+        let roblox_username = flag.roblox_id.lookup_username()
+            .unwrap_or_else(|_| format!("(roblox uid #{})", flag.roblox_id.0));
+        writeln!(buffer, "• {} (`{}`) as `{}`, flagged {} ago{}:",
+                 get_discord_username(flag.discord_id), flag.discord_id.0, roblox_username,
+                 util::english_time_diff(flag.created, now),
+                 if flag.holds_roles { " (roles on hold)" } else { "" })?;
+        for reason in flag.reasons {
+            writeln!(buffer, "    {}", reason)?;
+        }
+    }
+    ctx.respond(buffer)
+}
+fn do_clear_flag(ctx: &CommandContext, guild_id: GuildId) -> Result<()> {
+    let discord_id = find_user(ctx.arg(0)?)?.to_cmd_err(|| "Could not parse user id.")?;
+    cmd_ensure!(ctx.core.alt_detector().clear_flag(guild_id, discord_id)?,
+                "{} has not been flagged.", get_discord_username(discord_id));
+    if let Some((_, msg)) = ctx.discord_context() {
+        info!("{} (`{}`) cleared the alt account flag on {} (`{}`) in {}.",
+              msg.author.tag(), msg.author.id.0,
+              get_discord_username(discord_id), discord_id.0, guild_id);
+    }
+    ctx.core.roles().update_user(guild_id, discord_id, false).drop_nonfatal()?;
+    ctx.respond(format!("The flag on {} has been cleared.", get_discord_username(discord_id)))
+}
+
+crate const COMMANDS: &[Command] = &[
+    Command::new("list_flags")
+        .help(None, "Lists users flagged as possible alt accounts in this server.")
+        .required_permissions(enum_set!(BotPermission::ReviewAltFlags))
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| do_list_flags(ctx, ctx.get_guild()?.unwrap())),
+    Command::new("clear_flag")
+        .help(Some("<discord mention or user id>"),
+              "Marks a flagged user as reviewed, releasing any hold on their roles.")
+        .required_permissions(enum_set!(BotPermission::ReviewAltFlags))
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| do_clear_flag(ctx, ctx.get_guild()?.unwrap())),
+];
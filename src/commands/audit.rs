@@ -0,0 +1,124 @@
+use super::*;
+use super::util::*;
+
+use chrono::{DateTime, Utc};
+use std::time::SystemTime;
+
+const PAGE_SIZE: u32 = 10;
+const MAX_PAYLOAD_LENGTH: usize = 150;
+
+fn parse_filter(
+    ctx: &CommandContext, guild_id: Option<GuildId>,
+) -> Result<(AuditFilter, u32)> {
+    let mut filter = AuditFilter { guild_id, ..AuditFilter::default() };
+    let mut page = 1;
+    let mut i = 0;
+    while let Some(arg) = ctx.arg_opt(i) {
+        let mut split = arg.splitn(2, ':');
+        let (key, value) = match (split.next(), split.next()) {
+            (Some(key), Some(value)) if !value.is_empty() => (key, value),
+            _ => cmd_error!("Filters must look like `key:value`, e.g. `type:verify`."),
+        };
+        match key {
+            "user" => filter.discord_id = Some(
+                find_user(value)?.to_cmd_err(|| "Could not parse user id.")?
+            ),
+            "roblox" => filter.roblox_id = Some(find_roblox_user(value)?),
+            "type" => filter.event_type = Some(
+                AuditEventType::from_name(value).to_cmd_err(|| format!(
+                    "Unknown event type. Event types are: {}",
+                    AuditEventType::ALL.iter().map(|x| x.name()).collect::<Vec<_>>().join(", ")
+                ))?
+            ),
+            "since" | "until" => {
+                let duration = parse_duration(value)
+                    .to_cmd_err(|| "Durations look like `7d`, `12h` or `30m`.")?;
+                let time = SystemTime::now().checked_sub(duration)
+                    .to_cmd_err(|| "That duration is too long.")?;
+                if key == "since" {
+                    filter.since = Some(time);
+                } else {
+                    filter.until = Some(time);
+                }
+            }
+            "server" if guild_id.is_none() => filter.guild_id = Some(GuildId(
+                value.parse::<u64>().to_cmd_err(|| "Could not parse server id.")?
+            )),
+            "page" => {
+                page = value.parse::<u32>().to_cmd_err(|| "Could not parse page number.")?;
+                cmd_ensure!(page >= 1, "Page numbers start at 1.");
+            }
+            _ => cmd_error!("Unknown filter: {}", key),
+        }
+        i += 1;
+    }
+    Ok((filter, page))
+}
+
+fn do_audit(ctx: &CommandContext, guild_id: Option<GuildId>) -> Result<()> {
+    let (filter, page) = parse_filter(ctx, guild_id)?;
+    let offset = (page - 1).saturating_mul(PAGE_SIZE);
+    let events = ctx.core.audit().query(&filter, offset, PAGE_SIZE)?;
+    if events.is_empty() {
+        return ctx.respond(if page == 1 {
+            "No matching events were found."
+        } else {
+            "There are no more matching events."
+        })
+    }
+
+    let mut buffer = String::new();
+    writeln!(buffer, "Audit log, page {}:", page)?;
+    for event in &events {
+        let date: DateTime<Utc> = event.created.into();
+        write!(buffer, "`#{}` `[{}]` **{}**",
+               event.id, date.format("%Y-%m-%d %H:%M:%S"), event.event_type.name())?;
+        if guild_id.is_none() {
+            if let Some(event_guild) = event.guild_id {
+                write!(buffer, " in server #{}", event_guild.0)?;
+            }
+        }
+        if let Some(discord_id) = event.discord_id {
+            write!(buffer, " for {} (`{}`)", get_discord_username(discord_id), discord_id.0)?;
+        }
+        if let Some(roblox_id) = event.roblox_id {
+            write!(buffer, " (roblox uid #{})", roblox_id.0)?;
+        }
+        match event.actor {
+            Some(actor) if Some(actor) != event.discord_id =>
+                write!(buffer, " by {} (`{}`)", get_discord_username(actor), actor.0)?,
+            _ => { }
+        }
+        let payload = event.payload.to_string();
+        if payload != "{}" {
+            if payload.chars().count() > MAX_PAYLOAD_LENGTH {
+                let truncated: String = payload.chars().take(MAX_PAYLOAD_LENGTH).collect();
+                write!(buffer, "\n    `{}…`", truncated)?;
+            } else {
+                write!(buffer, "\n    `{}`", payload)?;
+            }
+        }
+        writeln!(buffer)?;
+    }
+    if events.len() as u32 == PAGE_SIZE {
+        write!(buffer, "Add `page:{}` to see older events.", page + 1)?;
+    }
+    ctx.respond(buffer)
+}
+
+crate const COMMANDS: &[Command] = &[
+    Command::new("audit")
+        .help(Some("[user:<user>] [roblox:<username>] [type:<event type>] [since:<duration>] \
+                    [until:<duration>] [page:<number>]"),
+              "Searches this server's audit log. Durations look like `7d`, and are measured \
+               back from the current time.")
+        .required_permissions(enum_set!(BotPermission::ViewAuditLog))
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| do_audit(ctx, Some(ctx.get_guild()?.unwrap()))),
+    Command::new("audit_global")
+        .help(Some("[server:<id>] [user:<user>] [roblox:<username>] [type:<event type>] \
+                    [since:<duration>] [until:<duration>] [page:<number>]"),
+              "Searches the audit log for all servers.")
+        .required_permissions(enum_set!(BotPermission::ViewGlobalAuditLog))
+        .exec(|ctx| do_audit(ctx, None)),
+];
@@ -0,0 +1,153 @@
+use super::*;
+use super::util::*;
+
+use roblox::*;
+use std::time::SystemTime;
+use util;
+
+/// Parses a ban target. Discord users are given by mention or as `discord:<id>`, and Roblox
+/// accounts as `roblox:<username>`, as Roblox usernames can look like Discord ids.
+fn parse_target(target: &str) -> Result<BanTarget> {
+    let mut split = target.splitn(2, ':');
+    match (split.next(), split.next()) {
+        (Some("discord"), Some(id)) => Ok(BanTarget::Discord(
+            find_user(id)?.to_cmd_err(|| "Could not parse Discord user id.")?
+        )),
+        (Some("roblox"), Some(name)) => Ok(BanTarget::Roblox(RobloxUserID::for_username(name)?)),
+        _ if target.starts_with("<@") => Ok(BanTarget::Discord(
+            find_user(target)?.to_cmd_err(|| "Could not parse Discord mention.")?
+        )),
+        _ => cmd_error!("Please give a Discord mention, `discord:<user id>` or \
+                         `roblox:<username>`."),
+    }
+}
+fn target_name(target: BanTarget) -> String {
+    match target {
+        BanTarget::Discord(discord_id) =>
+            format!("{} (Discord)", get_discord_username(discord_id)),
+        BanTarget::Roblox(roblox_id) => match roblox_id.lookup_username() {
+            Ok(name) => format!("{} (Roblox)", name),
+            Err(_) => format!("(roblox uid #{}) (Roblox)", roblox_id.0),
+        },
+    }
+}
+
+fn scope_name(guild_id: Option<GuildId>) -> String {
+    match guild_id {
+        Some(guild_id) => format!("server #{}", guild_id.0),
+        None => "all servers".to_owned(),
+    }
+}
+
+/// Updates the roles of the Discord account affected by a ban list change.
+fn refresh_roles(
+    core: &VerifierCore, guild_id: Option<GuildId>, target: BanTarget,
+) -> Result<()> {
+    let verifier = core.verifier();
+    let discord_id = match target {
+        BanTarget::Discord(discord_id) => discord_id,
+        BanTarget::Roblox(roblox_id) => match verifier.get_verified_discord_user(roblox_id)? {
+            Some(discord_id) => discord_id,
+            None => return Ok(()),
+        },
+    };
+    let roblox_id = verifier.get_verified_roblox_user(discord_id)?;
+    match guild_id {
+        Some(guild_id) => if roblox_id.is_some() {
+            core.roles().assign_roles(guild_id, discord_id, roblox_id).drop_nonfatal()?;
+        },
+        None => {
+            let results = core.roles().assign_roles_all(discord_id, roblox_id, None)?;
+            for (guild_id, result) in results {
+                if let Err(e) = result.drop_nonfatal() {
+                    warn!("Could not update roles in server #{} after a ban list change: {}",
+                          guild_id.0, e);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn do_ban(ctx: &CommandContext, guild_id: Option<GuildId>) -> Result<()> {
+    let target = parse_target(ctx.arg(0)?)?;
+    let (expires, reason) = match ctx.arg_opt(1).and_then(parse_duration) {
+        Some(duration) => (Some(SystemTime::now() + duration), ctx.rest(2)?),
+        None => (None, ctx.rest(1)?),
+    };
+    cmd_ensure!(!reason.trim().is_empty(), "Please give a reason for this ban.");
+    let author = ctx.discord_context().map(|(_, msg)| msg.author.id);
+
+    ctx.core.bans().add(guild_id, target, reason.trim(), author, expires)?;
+    info!("{} was added to the ban list for {} (reason: {}).",
+          target_name(target), scope_name(guild_id), reason.trim());
+    refresh_roles(ctx.core, guild_id, target)?;
+
+    let until = match expires {
+        Some(expires) => format!(" for {}", util::english_time_diff(SystemTime::now(), expires)),
+        None => String::new(),
+    };
+    ctx.respond(format!("{} has been banned from verifying{}.", target_name(target), until))
+}
+fn do_unban(ctx: &CommandContext, guild_id: Option<GuildId>) -> Result<()> {
+    let target = parse_target(ctx.arg(0)?)?;
+    cmd_ensure!(ctx.core.bans().remove(guild_id, target)?,
+                "{} is not on the ban list.", target_name(target));
+    info!("{} was removed from the ban list for {}.", target_name(target), scope_name(guild_id));
+    refresh_roles(ctx.core, guild_id, target)?;
+    ctx.respond(format!("{} has been removed from the ban list.", target_name(target)))
+}
+fn do_list_bans(ctx: &CommandContext, guild_id: Option<GuildId>) -> Result<()> {
+    let bans = ctx.core.bans().list(guild_id)?;
+    if bans.is_empty() {
+        return ctx.respond("The ban list is empty.")
+    }
+    let now = SystemTime::now();
+    let mut buffer = String::new();
+    writeln!(buffer, "Ban list:")?;
+    for ban in bans {
+        write!(buffer, "• {} - {}", target_name(ban.target), ban.reason)?;
+        if let Some(author) = ban.author {
+            write!(buffer, " (by {})", get_discord_username(author))?;
+        }
+        if let Some(expires) = ban.expires {
+            write!(buffer, " (expires in {})", util::english_time_diff(now, expires))?;
+        }
+        writeln!(buffer)?;
+    }
+    ctx.respond(buffer)
+}
+
+crate const COMMANDS: &[Command] = &[
+    Command::new("ban")
+        .help(Some("<discord mention, discord:<user id> or roblox:<username>> [duration] <reason>"),
+              "Prevents an account from verifying in this server. Durations look like `7d`.")
+        .required_permissions(enum_set!(BotPermission::ManageBans))
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| do_ban(ctx, Some(ctx.get_guild()?.unwrap()))),
+    Command::new("unban")
+        .help(Some("<discord mention, discord:<user id> or roblox:<username>>"),
+              "Removes an account from this server's ban list.")
+        .required_permissions(enum_set!(BotPermission::ManageBans))
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| do_unban(ctx, Some(ctx.get_guild()?.unwrap()))),
+    Command::new("list_bans")
+        .help(None, "Lists the accounts on this server's ban list.")
+        .required_permissions(enum_set!(BotPermission::ManageBans))
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .exec(|ctx| do_list_bans(ctx, Some(ctx.get_guild()?.unwrap()))),
+    Command::new("ban_global")
+        .help(Some("<discord mention, discord:<user id> or roblox:<username>> [duration] <reason>"),
+              "Prevents an account from verifying with this bot in any server.")
+        .required_permissions(enum_set!(BotPermission::ManageGlobalBans))
+        .exec(|ctx| do_ban(ctx, None)),
+    Command::new("unban_global")
+        .help(Some("<discord mention, discord:<user id> or roblox:<username>>"),
+              "Removes an account from the global ban list.")
+        .required_permissions(enum_set!(BotPermission::ManageGlobalBans))
+        .exec(|ctx| do_unban(ctx, None)),
+    Command::new("list_bans_global")
+        .help(None, "Lists the accounts on the global ban list.")
+        .required_permissions(enum_set!(BotPermission::ManageGlobalBans))
+        .exec(|ctx| do_list_bans(ctx, None)),
+];
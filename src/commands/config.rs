@@ -1,6 +1,8 @@
 use super::*;
+use super::util::*;
 
-use core::VerifierCore;
+use core::{VerifierCore, MIN_TOKEN_LENGTH, MAX_TOKEN_LENGTH};
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use util;
 
@@ -33,6 +35,35 @@ fn parse_u32(s: &str) -> Result<u32> {
 fn parse_u64(s: &str) -> Result<u64> {
     parse_as(s, "Setting must be a non-negative number.")
 }
+fn parse_channel(s: &str) -> Result<u64> {
+    let id = s.trim_start_matches("<#").trim_end_matches('>');
+    parse_as(id, "Setting must be a channel mention or channel ID.")
+}
+fn parse_token_length(s: &str) -> Result<u32> {
+    let len = parse_u32(s)?;
+    cmd_ensure!(len >= MIN_TOKEN_LENGTH && len <= MAX_TOKEN_LENGTH,
+                "Token length must be between {} and {} characters.",
+                MIN_TOKEN_LENGTH, MAX_TOKEN_LENGTH);
+    Ok(len)
+}
+fn parse_log_event_list(s: &str) -> Result<String> {
+    match s.trim() {
+        "all" => return Ok(LogEvent::ALL.iter().map(|x| x.name()).collect::<Vec<_>>().join(",")),
+        "none" => return Ok(String::new()),
+        _ => { }
+    }
+    let mut events = Vec::new();
+    for name in s.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()) {
+        let event = LogEvent::from_name(name).to_cmd_err(|| format!("Unknown event: {}", name))?;
+        if !events.contains(&event.name()) {
+            events.push(event.name());
+        }
+    }
+    Ok(events.join(","))
+}
+fn print_retention(_: &VerifierCore, secs: u64) -> Result<String> {
+    Ok(if secs == 0 { "*(kept forever)*".to_owned() } else { util::to_english_time_precise(secs) })
+}
 fn print_display(_: &VerifierCore, t: impl Display) -> Result<String> {
     Ok(format!("{}", t))
 }
@@ -40,14 +71,60 @@ fn print_quoted(_: &VerifierCore, t: impl Display) -> Result<String> {
     Ok(format!("\"{}\"", t))
 }
 
+lazy_static! {
+    static ref LOCALE_REGEX: Regex = Regex::new("^[a-z]{2,3}(-[a-z0-9]{2,8})*$").unwrap();
+}
+fn print_localized(
+    core: &VerifierCore, value: String, key: ConfigKey<BTreeMap<String, String>>,
+) -> Result<String> {
+    let mut str = format!("\"{}\"", value);
+    let locales = core.config().get(None, key)?;
+    if !locales.is_empty() {
+        let mut is_first = true;
+        write!(str, " *(translations: ")?;
+        for (locale, text) in locales {
+            if !is_first { write!(str, ", ")?; }
+            is_first = false;
+            write!(str, "{} = \"{}\"", locale, text)?;
+        }
+        write!(str, ")*")?;
+    }
+    Ok(str)
+}
+fn set_localized_config(
+    core: &VerifierCore, guild: Option<GuildId>, key: &str, value: Option<&str>
+) -> Result<()> {
+    let (name, locale) = match key.find('.') {
+        Some(pos) => (&key[..pos], key[pos + 1..].to_lowercase()),
+        None => cmd_error!("No such configuration option '{}'.", key),
+    };
+    let config_key = match name {
+        "place_ui_title" => ConfigKeys::PlaceUITitleLocales,
+        "place_ui_instructions" => ConfigKeys::PlaceUIInstructionsLocales,
+        _ => cmd_error!("No such configuration option '{}'.", key),
+    };
+    cmd_ensure!(guild.is_none(), "This option cannot be set per-server.");
+    cmd_ensure!(LOCALE_REGEX.is_match(&locale),
+                "'{}' is not a valid locale. Locales look like `pt-br` or `de`.", locale);
+
+    let mut locales = core.config().get(None, config_key)?;
+    match value {
+        Some(str) => { locales.insert(locale, str.to_owned()); }
+        None => { locales.remove(&locale); }
+    }
+    core.config().set(core, None, config_key, locales)
+}
+
 macro_rules! config_values {
     ($($config_name:ident<$tp:ty>(
         $config_key:ident, $allow_guild:expr, $show_type:expr,
         $help:expr, $from_str:expr, $to_str:expr $(,)*
     );)*) => {
+        /// Sets a configuration value, returning the new value as it is shown by
+        /// `print_config`, so secrets are redacted.
         fn set_config(
             core: &VerifierCore, guild: Option<GuildId>, key: &str, value: Option<&str>
-        ) -> Result<()> {
+        ) -> Result<Option<String>> {
             match key {
                 $(
                     stringify!($config_name) => {
@@ -56,16 +133,24 @@ macro_rules! config_values {
                         match value {
                             Some(str) => {
                                 let from_str: fn(&str) -> Result<$tp> = $from_str;
-                                core.config().set(core, guild,
-                                                  ConfigKeys::$config_key, from_str(str)?)?;
+                                let to_str: fn(&VerifierCore, $tp) -> Result<String> = $to_str;
+                                let value = from_str(str)?;
+                                let printed = to_str(core, value.clone())?;
+                                core.config().set(core, guild, ConfigKeys::$config_key, value)?;
+                                Ok(Some(printed))
+                            }
+                            None => {
+                                core.config().reset(core, guild, ConfigKeys::$config_key)?;
+                                Ok(None)
                             }
-                            None => core.config().reset(core, guild, ConfigKeys::$config_key)?,
                         }
                     }
                 )*
-                name => cmd_error!("No such configuration option '{}'.", name),
+                name => {
+                    set_localized_config(core, guild, name, value)?;
+                    Ok(value.map(|x| x.to_owned()))
+                }
             }
-            Ok(())
         }
 
         fn print_config(core: &VerifierCore, guild: Option<GuildId>) -> Result<String> {
@@ -100,10 +185,42 @@ config_values! {
         |x|    Ok(Some(x.to_owned())),
         |_, x| Ok(x.map_or("(not set)", |_| "<token redacted>").to_owned()));
 
+    web_server_address<Option<String>>(
+        WebServerAddress, false, GuildShowType::OnlyInTerminal,
+        "The address the bot's web server listens on, such as `0.0.0.0:8080`. Changes take \
+         effect when the bot is restarted.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.unwrap_or_else(|| "*(disabled)*".to_owned())));
+    web_server_public_url<Option<String>>(
+        WebServerPublicUrl, false, GuildShowType::OnlyInTerminal,
+        "The URL users can reach the bot's web server at, such as `https://example.com`.",
+        |x|    Ok(Some(x.trim_end_matches('/').to_owned())),
+        |_, x| Ok(x.unwrap_or_else(|| "*(none set)*".to_owned())));
+
+    oauth_issuer_url<String>(
+        OAuthIssuerUrl, false, GuildShowType::OnlyInTerminal,
+        "The URL of the OpenID Connect server used for Roblox OAuth verification.",
+        |x| Ok(x.to_owned()), print_quoted);
+    oauth_client_id<Option<String>>(
+        OAuthClientId, false, GuildShowType::OnlyInTerminal,
+        "The client ID of the Roblox OAuth application used for verification.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.unwrap_or_else(|| "*(none set)*".to_owned())));
+    oauth_client_secret<Option<String>>(
+        OAuthClientSecret, false, GuildShowType::OnlyInTerminal,
+        "The client secret of the Roblox OAuth application used for verification.",
+        |x|    Ok(Some(x.to_owned())),
+        |_, x| Ok(x.map_or("(not set)", |_| "<secret redacted>").to_owned()));
+
     set_nickname<bool>(
         SetNickname, true, GuildShowType::OnlyInGuild,
         "Whether to set a user's nickname to their Roblox username while updating their roles.",
         parse_bool, print_display);
+    match_any_linked_account<bool>(
+        MatchAnyLinkedAccount, true, GuildShowType::OnlyInGuild,
+        "Whether a rule matches if any Roblox account linked to a user matches it, rather than \
+         only their primary account.",
+        parse_bool, print_display);
     set_roles_on_join<bool>(
         SetRolesOnJoin, true, GuildShowType::OnlyInGuild,
         "Whether to set a user's roles on server join based on an existing verification.",
@@ -134,12 +251,16 @@ config_values! {
 
     place_ui_title<String>(
         PlaceUITitle, false, GuildShowType::OnlyInTerminal,
-        "The title of the verification place UI.",
-        |x| Ok(x.to_owned()), print_quoted);
+        "The title of the verification place UI. Use `place_ui_title.<locale>` to set a \
+         translation for players using that locale.",
+        |x| Ok(x.to_owned()),
+        |core, x| print_localized(core, x, ConfigKeys::PlaceUITitleLocales));
     place_ui_instructions<String>(
         PlaceUIInstructions, false, GuildShowType::OnlyInTerminal,
-        "The instructions shown in the verification place UI.",
-        |x| Ok(x.to_owned()), print_quoted);
+        "The instructions shown in the verification place UI. Use \
+         `place_ui_instructions.<locale>` to set a translation for players using that locale.",
+        |x| Ok(x.to_owned()),
+        |core, x| print_localized(core, x, ConfigKeys::PlaceUIInstructionsLocales));
     place_ui_background<Option<String>>(
         PlaceUIBackground, false, GuildShowType::OnlyInTerminal,
         "The Asset ID shown in the verification place UI background.",
@@ -151,13 +272,37 @@ config_values! {
         |x| parse_u64(x).map(Some),
         |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("{}", x))));
 
+    allow_place_verification<bool>(
+        AllowPlaceVerification, true, GuildShowType::AlwaysShow,
+        "Whether users can verify using the verification place.",
+        parse_bool, print_display);
+    allow_profile_verification<bool>(
+        AllowProfileVerification, true, GuildShowType::AlwaysShow,
+        "Whether users can verify by adding a phrase to their Roblox profile description.",
+        parse_bool, print_display);
+
+    allow_oauth_verification<bool>(
+        AllowOAuthVerification, true, GuildShowType::AlwaysShow,
+        "Whether users can verify by logging in to Roblox. This requires OAuth and the web \
+         server to be configured.",
+        parse_bool, print_display);
+
+    allow_push_verification<bool>(
+        AllowPushVerification, true, GuildShowType::AlwaysShow,
+        "Whether users can verify by entering a pairing code in the verification place. This \
+         requires the web server to be configured, and HTTP requests to be enabled for the \
+         verification place.",
+        parse_bool, print_display);
+
     verification_attempt_limit<u32>(
-        VerificationAttemptLimit, false, GuildShowType::OnlyInTerminal,
-        "How many times a user can verify in a row before they must wait a period of time.",
+        VerificationAttemptLimit, true, GuildShowType::AlwaysShow,
+        "How many times a user can verify in a row before they must wait a period of time. A \
+         server can only lower this below the global setting.",
         parse_u32, print_display);
     verification_cooldown<u64>(
-        VerificationCooldownSeconds, false, GuildShowType::OnlyInTerminal,
-        "How many seconds a user must wait to attempt to verify after using up the attempt limit.",
+        VerificationCooldownSeconds, true, GuildShowType::AlwaysShow,
+        "How many seconds a user must wait to attempt to verify after using up the attempt \
+         limit. A server can only raise this above the global setting.",
         parse_u64, |_, x| Ok(util::to_english_time_precise(x)));
 
     verification_channel_intro<Option<String>>(
@@ -184,25 +329,202 @@ config_values! {
         TokenValiditySeconds, false, GuildShowType::OnlyInTerminal,
         "How many seconds a verification token is valid for.",
         parse_u32, |_, x| Ok(util::to_english_time_precise(x as u64)));
+    token_length<u32>(
+        TokenLength, false, GuildShowType::OnlyInTerminal,
+        "How many characters a verification token contains. Changing this requires the \
+         verification place to be updated.",
+        parse_token_length, print_display);
+    token_exclude_confusable<bool>(
+        TokenExcludeConfusable, false, GuildShowType::OnlyInTerminal,
+        "Whether verification tokens avoid letters that are easily confused with each other \
+         or with numbers. Changing this requires the verification place to be updated.",
+        parse_bool, print_display);
+    token_rotation_interval<u64>(
+        TokenRotationSeconds, false, GuildShowType::OnlyInTerminal,
+        "How many seconds each token key is used for before it is automatically replaced. \
+         0 disables automatic rotation.",
+        parse_u64, |_, x| Ok(if x == 0 {
+            "disabled".to_owned()
+        } else {
+            util::to_english_time_precise(x)
+        }));
+    token_rotation_lead_time<u64>(
+        TokenRotationLeadSeconds, false, GuildShowType::OnlyInTerminal,
+        "How many seconds before a scheduled rotation the next key is added to the \
+         verification place. The place must be updated within this time.",
+        parse_u64, |_, x| Ok(util::to_english_time_precise(x)));
+    token_grace_period<u64>(
+        TokenGracePeriodSeconds, false, GuildShowType::OnlyInTerminal,
+        "How many seconds the previous key is still accepted after a scheduled rotation.",
+        parse_u64, |_, x| Ok(util::to_english_time_precise(x)));
 
     allow_reverify_discord_account<bool>(
-        AllowReverifyDiscord, false, GuildShowType::OnlyInTerminal,
-        "Whether a user can reverify a Discord account that is already verified.",
+        AllowReverifyDiscord, true, GuildShowType::AlwaysShow,
+        "Whether a user can reverify a Discord account that is already verified. A server can \
+         only disallow this, not allow it when it is disallowed globally.",
         parse_bool, print_display);
     allow_reverify_roblox_account<bool>(
-        AllowReverifyRoblox, false, GuildShowType::OnlyInTerminal,
-        "Whether a user can reverify a Roblox account that is already verified.",
+        AllowReverifyRoblox, true, GuildShowType::AlwaysShow,
+        "Whether a user can reverify a Roblox account that is already verified. A server can \
+         only disallow this, not allow it when it is disallowed globally.",
         parse_bool, print_display);
     reverification_cooldown<u64>(
-        ReverificationCooldownSeconds, false, GuildShowType::OnlyInTerminal,
-        "How many seconds a user must wait after verifying before they can reverify.",
+        ReverificationCooldownSeconds, true, GuildShowType::AlwaysShow,
+        "How many seconds a user must wait after verifying before they can reverify. A server \
+         can only raise this above the global setting.",
+        parse_u64, |_, x| Ok(util::to_english_time_precise(x)));
+    max_linked_accounts<u32>(
+        MaxLinkedAccounts, false, GuildShowType::OnlyInTerminal,
+        "How many Roblox accounts a user can link to their Discord account, including their \
+         primary account.",
+        |x| {
+            let count = parse_u32(x)?;
+            cmd_ensure!(count >= 1, "At least one account must be allowed.");
+            Ok(count)
+        },
+        print_display);
+
+    verification_max_age<u64>(
+        VerificationMaxAgeSeconds, true, GuildShowType::OnlyInGuild,
+        "How many seconds a verification is trusted for before the user must verify again. 0 \
+         disables verification expiry.",
+        parse_u64,
+        |_, x| Ok(if x == 0 {
+            "*(disabled)*".to_owned()
+        } else {
+            util::to_english_time_precise(x)
+        }));
+    verification_expiry_warning<u64>(
+        VerificationExpiryWarningSeconds, true, GuildShowType::OnlyInGuild,
+        "How many seconds before a verification expires the user is warned about it. 0 disables \
+         the warning.",
+        parse_u64, |_, x| Ok(util::to_english_time_precise(x)));
+
+    alt_detection_channel<Option<u64>>(
+        AltDetectionChannel, true, GuildShowType::OnlyInGuild,
+        "The channel verifications flagged as possible alt accounts are reported to. Alt account \
+         detection is disabled if this is not set.",
+        |x| parse_channel(x).map(Some),
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("<#{}>", x))));
+    alt_detection_discord_accounts<u32>(
+        AltDetectionDiscordAccounts, true, GuildShowType::OnlyInGuild,
+        "Flags a Roblox account once this many different Discord accounts have verified as it \
+         within the detection window. 0 disables this check.",
+        parse_u32, print_display);
+    alt_detection_roblox_accounts<u32>(
+        AltDetectionRobloxAccounts, true, GuildShowType::OnlyInGuild,
+        "Flags a Discord account once it has verified as this many different Roblox accounts \
+         within the detection window. 0 disables this check.",
+        parse_u32, print_display);
+    alt_detection_window<u64>(
+        AltDetectionWindowSeconds, true, GuildShowType::OnlyInGuild,
+        "How many seconds of verification history alt account detection looks at.",
+        parse_u64, |_, x| Ok(util::to_english_time_precise(x)));
+    alt_detection_hold_roles<bool>(
+        AltDetectionHoldRoles, true, GuildShowType::OnlyInGuild,
+        "Whether flagged users are not given roles until a moderator clears the flag.",
+        parse_bool, print_display);
+    alt_flag_expiry<u64>(
+        AltFlagExpirySeconds, true, GuildShowType::OnlyInGuild,
+        "How many seconds alt account flags are kept for if no moderator reviews them. Roles \
+         on hold are given once the flag expires. 0 keeps flags until they are reviewed.",
+        parse_u64, |_, x| Ok(util::to_english_time_precise(x)));
+
+    require_verification_approval<bool>(
+        RequireVerificationApproval, true, GuildShowType::OnlyInGuild,
+        "Whether verifications started in this server must be approved by a moderator before \
+         the account is linked.",
+        parse_bool, print_display);
+    verification_review_channel<Option<u64>>(
+        VerificationReviewChannel, true, GuildShowType::OnlyInGuild,
+        "The channel verifications waiting for approval are posted to.",
+        |x| parse_channel(x).map(Some),
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("<#{}>", x))));
+    verification_approval_expiry<u64>(
+        VerificationApprovalSeconds, true, GuildShowType::OnlyInGuild,
+        "How many seconds a verification can wait for approval before it expires.",
         parse_u64, |_, x| Ok(util::to_english_time_precise(x)));
+    verification_approval_reactions<bool>(
+        VerificationApprovalReactions, true, GuildShowType::OnlyInGuild,
+        "Whether moderators can approve or deny verifications by reacting to them in the review \
+         channel.",
+        parse_bool, print_display);
+
+    trust_imported_verifications<bool>(
+        TrustImportedVerifications, true, GuildShowType::OnlyInGuild,
+        "Whether verifications imported from other bots are trusted. If not, imported users \
+         are not given roles until they verify again.",
+        parse_bool, print_display);
 
     global_verification_log_channel<Option<u64>>(
         GlobalVerificationLogChannel, false, GuildShowType::OnlyInTerminal,
         "The channel to globally log verification information to.",
         |x| parse_u64(x).map(Some),
         |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("{}", x))));
+    verification_log_channel<Option<u64>>(
+        VerificationLogChannel, true, GuildShowType::OnlyInGuild,
+        "The channel verification events in this server are logged to.",
+        |x| parse_channel(x).map(Some),
+        |_, x| Ok(x.map_or_else(|| "*(none set)*".to_owned(), |x| format!("<#{}>", x))));
+    verification_log_events<String>(
+        VerificationLogEvents, true, GuildShowType::OnlyInGuild,
+        "A comma separated list of the events logged to the verification log channel. Events \
+         are `verify`, `failure`, `reverify`, `unverify`, `role_change` and `legacy_unverify`. \
+         `all` or `none` may also be used.",
+        parse_log_event_list, |_, x| Ok(if x.is_empty() { "*(none)*".to_owned() } else { x }));
+
+    user_history_retention<u64>(
+        UserHistoryRetentionSeconds, false, GuildShowType::OnlyInTerminal,
+        "How many seconds verification history is kept for. 0 keeps it forever. Alt account \
+         detection cannot see history older than this.",
+        parse_u64, print_retention);
+    verification_cooldown_retention<u64>(
+        VerificationCooldownRetentionSeconds, false, GuildShowType::OnlyInTerminal,
+        "How many seconds verification attempt counters are kept for after the last attempt. \
+         0 keeps them forever. Counters are always kept until every server's verification \
+         cooldown has ended.",
+        parse_u64, print_retention);
+    roles_last_updated_retention<u64>(
+        RolesLastUpdatedRetentionSeconds, false, GuildShowType::OnlyInTerminal,
+        "How many seconds the time of a user's last role update is kept for. 0 keeps it \
+         forever. This should be longer than any server's update cooldowns.",
+        parse_u64, print_retention);
+    audit_log_retention<u64>(
+        AuditLogRetentionSeconds, false, GuildShowType::OnlyInTerminal,
+        "How many seconds audit log events are kept for. 0 keeps them forever.",
+        parse_u64, print_retention);
+    guild_data_grace_period<u64>(
+        GuildDataGracePeriodSeconds, false, GuildShowType::OnlyInTerminal,
+        "How many seconds a server's data is kept for after the bot is removed from it. 0 keeps \
+         it forever.",
+        parse_u64, print_retention);
+
+    backup_directory<String>(
+        BackupDirectory, false, GuildShowType::OnlyInTerminal,
+        "The directory database backups are written to. Relative paths are relative to the \
+         directory the bot is installed in.",
+        |x| Ok(x.to_owned()), print_quoted);
+    backup_interval<u64>(
+        BackupIntervalSeconds, false, GuildShowType::OnlyInTerminal,
+        "How many seconds to wait between automatic database backups. 0 disables them.",
+        parse_u64, |_, x| Ok(if x == 0 {
+            "*(disabled)*".to_owned()
+        } else {
+            util::to_english_time_precise(x)
+        }));
+    backup_compression<bool>(
+        BackupCompression, false, GuildShowType::OnlyInTerminal,
+        "Whether to compress database backups with LZ4.",
+        parse_bool, print_display);
+    backup_retention_count<u32>(
+        BackupRetentionCount, false, GuildShowType::OnlyInTerminal,
+        "How many database backups are kept. 0 keeps any number of backups.",
+        parse_u32, print_display);
+    backup_retention<u64>(
+        BackupRetentionSeconds, false, GuildShowType::OnlyInTerminal,
+        "How many seconds database backups are kept for. 0 keeps them forever. The newest \
+         backup is never removed.",
+        parse_u64, print_retention);
 }
 
 fn set(ctx: &CommandContext, guild: Option<GuildId>) -> Result<()> {
@@ -211,11 +533,13 @@ fn set(ctx: &CommandContext, guild: Option<GuildId>) -> Result<()> {
     } else {
         let key = ctx.arg(0)?;
         let value = ctx.rest(1)?;
-        if value.trim().is_empty() {
-            set_config(ctx.core, guild, key, None)?;
+        let value = if value.trim().is_empty() { None } else { Some(value) };
+        let printed = set_config(ctx.core, guild, key, value)?;
+        ctx.core.audit().record(AuditEventType::ConfigChange, guild, command_author(ctx),
+                                None, None, json!({ "key": key, "value": printed }));
+        if value.is_none() {
             ctx.respond("Configuration option reset to default.")?;
         } else {
-            set_config(ctx.core, guild, key, Some(value))?;
             ctx.respond("Configuration option set.")?;
         }
         Ok(())
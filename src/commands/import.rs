@@ -0,0 +1,78 @@
+use super::*;
+use super::util::*;
+
+use std::fs;
+
+const MAX_LISTED_ENTRIES: usize = 10;
+
+fn read_import_file(ctx: &CommandContext) -> Result<String> {
+    let data = match ctx.discord_context() {
+        Some((_, msg)) => {
+            let attachment = msg.attachments.first()
+                .to_cmd_err(|| "Please attach a CSV or JSON file to import.")?;
+            attachment.download()?
+        }
+        None => {
+            let path = ctx.rest(1)?.trim();
+            cmd_ensure!(!path.is_empty(), ctx.not_enough_arguments());
+            fs::read(path)?
+        }
+    };
+    String::from_utf8(data).ok().to_cmd_err(|| "The import file must be UTF-8 text.")
+}
+
+fn write_list(buffer: &mut String, title: &str, entries: &[String]) -> Result<()> {
+    if entries.is_empty() { return Ok(()) }
+    writeln!(buffer, "{}", title)?;
+    for entry in entries.iter().take(MAX_LISTED_ENTRIES) {
+        writeln!(buffer, "• {}", entry)?;
+    }
+    if entries.len() > MAX_LISTED_ENTRIES {
+        writeln!(buffer, "• *...and {} more.*", entries.len() - MAX_LISTED_ENTRIES)?;
+    }
+    Ok(())
+}
+
+fn do_import(ctx: &CommandContext) -> Result<()> {
+    let policy = ImportConflictPolicy::from_name(ctx.arg(0)?)
+        .to_cmd_err(|| "The conflict policy must be `skip` or `overwrite`.")?;
+    let data = parse_import(&read_import_file(ctx)?)?;
+    cmd_ensure!(!data.links.is_empty() || !data.invalid.is_empty(),
+                "The import file does not contain any links.");
+
+    let actor = command_author(ctx);
+    let report = ctx.core.verifier().import_links(&data.links, policy, actor)?;
+    ctx.core.audit().record(AuditEventType::Import, ctx.get_guild()?, actor, None, None, json!({
+        "policy": ctx.arg(0)?, "imported": report.imported, "unchanged": report.unchanged,
+        "conflicts": report.conflicts.len(), "busy": report.busy,
+        "invalid": data.invalid.len(),
+    }));
+    info!("Imported {} verifications ({} unchanged, {} conflicts, {} invalid).",
+          report.imported, report.unchanged, report.conflicts.len(), data.invalid.len());
+
+    let mut buffer = String::new();
+    writeln!(buffer, "Imported {} link(s). {} link(s) already existed.",
+             report.imported, report.unchanged)?;
+    if report.busy != 0 {
+        writeln!(buffer, "{} link(s) were skipped as the user was verifying at the time. Run \
+                          the import again to retry them.", report.busy)?;
+    }
+    let conflicts: Vec<_> = report.conflicts.iter()
+        .map(|(discord_id, roblox_id)| format!("<@{}> (`{}`) as roblox uid #{}",
+                                               discord_id.0, discord_id.0, roblox_id.0))
+        .collect();
+    write_list(&mut buffer, "These links conflict with existing verifications, and were kept \
+                             as they were:", &conflicts)?;
+    write_list(&mut buffer, "These entries could not be imported:", &data.invalid)?;
+    ctx.respond(buffer)
+}
+
+crate const COMMANDS: &[Command] = &[
+    Command::new("import_verifications")
+        .help(Some("<skip|overwrite> [path]"),
+              "Imports Discord and Roblox account links from another bot's CSV or JSON export. \
+               Attach the file when using this command on Discord. `skip` keeps existing \
+               verifications that conflict with the import, and `overwrite` replaces them.")
+        .required_permissions(enum_set!(BotPermission::ImportVerifications))
+        .exec(do_import),
+];
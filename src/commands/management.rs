@@ -1,10 +1,160 @@
 use super::*;
 
 use roblox::*;
+use std::fs::File;
+use std::io::Write as IoWrite;
 use std::process::exit;
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
+use util;
+
+fn do_get_place(ctx: &CommandContext) -> Result<()> {
+    let (place_data, hash) = ctx.core.current_place()?;
+    let description = format!("Template version: {}\nSHA-256: `{}`",
+                              TEMPLATE_VERSION, util::to_hex(&hash));
+    match ctx.discord_context() {
+        Some((_, msg)) => {
+            warn!("Sending the verification place file to {} (`{}`).",
+                  msg.author.tag(), msg.author.id.0);
+            msg.channel_id.send_files(vec![(place_data.as_slice(), "Sylph-Verifier.rbxl")], |m|
+                m.content(format_args!("The current verification place file. This file contains \
+                                        server secrets. Do not share it with anyone.\n{}",
+                                       description))
+            )?;
+            Ok(())
+        }
+        None => {
+            let path = ctx.rest(0)?;
+            cmd_ensure!(!path.is_empty(), ctx.not_enough_arguments());
+            let mut handle = File::create(path)?;
+            handle.write_all(&place_data)?;
+            ctx.respond(format!("The verification place file has been written to '{}'.\n{}",
+                                path, description.replace('`', "")))
+        }
+    }
+}
+
+fn do_key_status(ctx: &CommandContext) -> Result<()> {
+    let now = SystemTime::now();
+    let keys = ctx.core.verifier().key_status()?;
+
+    let mut status = String::new();
+    writeln!(status, "Verification keys:")?;
+    for key in &keys {
+        match key.state {
+            KeyState::Pending =>
+                writeln!(status, "• Key #{} is staged, and becomes current in {}.",
+                         key.id, util::english_time_diff(now, key.activates_at))?,
+            KeyState::Current =>
+                writeln!(status, "• Key #{} is current.", key.id)?,
+            KeyState::GracePeriod { ends } =>
+                writeln!(status, "• Key #{} is still accepted for {}.",
+                         key.id, util::english_time_diff(now, ends))?,
+            KeyState::Retired =>
+                writeln!(status, "• Key #{} is retired.", key.id)?,
+        }
+    }
+
+    let place_keys: Vec<_> = keys.iter()
+        .filter(|x| x.state == KeyState::Pending || x.state == KeyState::Current)
+        .map(|x| format!("#{}", x.id))
+        .collect();
+    writeln!(status, "The generated place file contains key(s) {}.", place_keys.join(", "))?;
+    match ctx.core.verifier().last_used_key()? {
+        Some((id, time)) =>
+            write!(status, "The last key seen from the live place was #{}, used by a \
+                            verification {} ago. The place may have been updated since.",
+                   id, util::english_time_diff(time, now))?,
+        None =>
+            write!(status, "No successful verifications have been recorded yet.")?,
+    }
+    ctx.respond(status)
+}
+
+fn do_prune(ctx: &CommandContext) -> Result<()> {
+    let dry_run = match ctx.arg_opt(0) {
+        Some("--dry-run") => true,
+        None => false,
+        Some(_) => cmd_error!("Unknown argument. Use `--dry-run` to preview what would be pruned."),
+    };
+    let report = ctx.core.retention().prune(dry_run)?;
+    if report.is_empty() {
+        return ctx.respond("There is no data to prune.")
+    }
+
+    let mut buffer = String::new();
+    writeln!(buffer, "The following data {} pruned:", if dry_run { "would be" } else { "was" })?;
+    for (table, rows) in &report.tables {
+        writeln!(buffer, "• {} row(s) from `{}`", rows, table)?;
+    }
+    if !report.guilds.is_empty() {
+        let guilds: Vec<_> = report.guilds.iter().map(|x| x.0.to_string()).collect();
+        write!(buffer, "This includes all data of {} departed server(s): {}",
+               guilds.len(), guilds.join(", "))?;
+    }
+    ctx.respond(buffer)
+}
+
+fn do_export(ctx: &CommandContext) -> Result<()> {
+    let args = (0..ctx.argc()).map(|i| ctx.arg(i)).collect::<Result<Vec<_>>>()?;
+    let options = ExportOptions::parse(&args)?;
+    let written = export_data(ctx.core.database(), &options)?;
+
+    let mut buffer = String::new();
+    writeln!(buffer, "Exported {} file(s):", written.len())?;
+    for (path, rows) in &written {
+        writeln!(buffer, "• {} row(s) to `{}`", rows, path.display())?;
+    }
+    ctx.respond(buffer)
+}
+
+fn do_backup(ctx: &CommandContext) -> Result<()> {
+    let info = ctx.core.backups().backup()?;
+    let mut buffer = String::new();
+    write!(buffer, "Wrote a {} byte backup to `{}`.", info.size, info.path.display())?;
+    if info.pruned != 0 {
+        write!(buffer, " {} old backup(s) were removed.", info.pruned)?;
+    }
+    ctx.respond(buffer)
+}
+
+const MAX_LISTED_ISSUES: usize = 10;
+
+fn do_check_database(ctx: &CommandContext) -> Result<()> {
+    let fix = match ctx.arg_opt(0) {
+        Some("fix") => true,
+        None => false,
+        Some(_) => cmd_error!("Unknown argument. Use `fix` to repair the issues found."),
+    };
+    let report = ctx.core.consistency().check(fix)?;
+
+    let mut buffer = String::new();
+    if report.issues.is_empty() {
+        writeln!(buffer, "No problems were found in the database.")?;
+    }
+    for &category in IssueCategory::ALL {
+        let issues: Vec<_> = report.issues.iter().filter(|x| x.category == category).collect();
+        if issues.is_empty() { continue }
+        let fixable = issues.iter().filter(|x| x.is_fixable()).count();
+        writeln!(buffer, "**{}** ({} found, {} {}):", category.description(), issues.len(),
+                 fixable, if fix { "repaired" } else { "can be repaired" })?;
+        for issue in issues.iter().take(MAX_LISTED_ISSUES) {
+            writeln!(buffer, "• {}", issue.description)?;
+        }
+        if issues.len() > MAX_LISTED_ISSUES {
+            writeln!(buffer, "• *...and {} more.*", issues.len() - MAX_LISTED_ISSUES)?;
+        }
+    }
+    if report.skipped_guild_checks {
+        writeln!(buffer, "Server and role checks were skipped, as the bot is not connected to \
+                          Discord, or is still loading its servers.")?;
+    }
+    if !fix && report.issues.iter().any(|x| x.is_fixable()) {
+        writeln!(buffer, "Use `check_database fix` to repair the issues that can be repaired.")?;
+    }
+    ctx.respond(buffer)
+}
 
 crate const COMMANDS: &[Command] = &[
     Command::new("shutdown")
@@ -20,14 +170,66 @@ crate const COMMANDS: &[Command] = &[
         }),
 
     // Configuration
+    Command::new("get_place")
+        .help(Some("[output path]"),
+              "Sends you the current verification place file, or writes it to a path when used \
+               from the terminal.")
+        .required_permissions(enum_set!(BotPermission::ManageBot))
+        .allowed_contexts(enum_set!(CommandTarget::Terminal | CommandTarget::PrivateMessage))
+        .exec(do_get_place),
     Command::new("rekey")
-        .help(None, "Changes the shared key used by the verifier.")
+        .help(None, "Changes the shared key used by the verifier. The previous key stops \
+                     working immediately.")
         .required_permissions(enum_set!(BotPermission::ManageVerification))
         .exec(|ctx| {
             ctx.core.verifier().rekey(true)?;
             ctx.core.refresh_place()?;
             Ok(())
         }),
+    Command::new("rotate_master_key")
+        .help(None, "Encrypts the token keys in the database with a new master key.")
+        .required_permissions(enum_set!(BotPermission::ManageBot))
+        .allowed_contexts(enum_set!(CommandTarget::Terminal))
+        .exec(|ctx| {
+            let key_file = ctx.core.verifier().rotate_master_key()?;
+            ctx.respond(format!("The new master key has been written to '{}'. Keep a copy of it \
+                                 somewhere safe and separate from database backups, as the \
+                                 database cannot be used without it.", key_file.display()))
+        }),
+    Command::new("key_status")
+        .help(None, "Shows which verification keys are staged, current, and still accepted, \
+                     and the last key seen in a verification from the live place.")
+        .required_permissions(enum_set!(BotPermission::ManageVerification))
+        .exec(do_key_status),
+
+    // Data management
+    Command::new("prune_data")
+        .help(Some("[--dry-run]"),
+              "Removes data older than its retention period, and data of servers the bot has \
+               left. This also runs automatically every hour.")
+        .required_permissions(enum_set!(BotPermission::ManageBot))
+        .exec(do_prune),
+    Command::new("export_data")
+        .help(Some(ExportOptions::USAGE),
+              "Exports verification data, history, rules or server settings to CSV or NDJSON \
+               files in a directory. Exports can be limited to the members of one server, or \
+               to a date range. The bot keeps running normally while the export is written.")
+        .required_permissions(enum_set!(BotPermission::ManageBot))
+        .allowed_contexts(enum_set!(CommandTarget::Terminal))
+        .exec(do_export),
+    Command::new("backup")
+        .help(None,
+              "Writes a backup of the database to the backup directory, and removes old backups. \
+               Backups are also made automatically according to the `backup_interval` setting.")
+        .required_permissions(enum_set!(BotPermission::ManageBot))
+        .allowed_contexts(enum_set!(CommandTarget::Terminal))
+        .exec(do_backup),
+    Command::new("check_database")
+        .help(Some("[fix]"),
+              "Checks the database for orphaned rows and inconsistencies. With `fix`, any issues \
+               that can be safely repaired are repaired in a single transaction.")
+        .required_permissions(enum_set!(BotPermission::ManageBot))
+        .exec(do_check_database),
 
     // Discord management
     Command::new("connect")
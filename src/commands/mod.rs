@@ -295,11 +295,18 @@ pub trait CommandContextData {
     fn discord_context(&self) -> Option<(&Context, &Message)> { None }
 }
 
+mod alt_detector;
+mod audit;
+mod bans;
 mod config;
+mod import;
 mod management;
 mod permissions;
+mod privacy;
 mod verifier;
 
+pub use self::verifier::review_reaction;
+
 static CORE_COMMANDS: &'static [Command] = &[
     Command::new("help")
         .help(Some("<command>"),
@@ -335,8 +342,9 @@ static CORE_COMMANDS: &'static [Command] = &[
 ];
 lazy_static! {
     static ref COMMANDS: CommandList = CommandList::new(&[
-        CORE_COMMANDS, config::COMMANDS, management::COMMANDS,
-        permissions::COMMANDS, verifier::COMMANDS,
+        CORE_COMMANDS, alt_detector::COMMANDS, audit::COMMANDS, bans::COMMANDS,
+        config::COMMANDS, import::COMMANDS, management::COMMANDS, permissions::COMMANDS,
+        privacy::COMMANDS, verifier::COMMANDS,
     ]);
 }
 pub fn get_command(msg: &str) -> Option<&'static Command> {
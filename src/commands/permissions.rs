@@ -29,12 +29,20 @@ config_values! {
     manage_bot             => ManageBot,
     manage_global_settings => ManageGlobalSetings,
     manage_verification    => ManageVerification,
+    manage_global_bans     => ManageGlobalBans,
+    view_global_audit_log  => ViewGlobalAuditLog,
+    import_verifications   => ImportVerifications,
+    cmd_force_verify       => ForceVerify,
 
     // Guild permissions
     bypass_nickname_update => BypassNicknameUpdate,
     bypass_hierarchy       => BypassHierarchy,
     manage_server_settings => ManageGuildSettings,
     manage_roles           => ManageRoles,
+    manage_bans            => ManageBans,
+    review_alt_flags       => ReviewAltFlags,
+    approve_verification   => ApproveVerification,
+    view_audit_log         => ViewAuditLog,
 
     // Command permissions
     cmd_unverify           => Unverify,
@@ -79,6 +87,14 @@ fn set_perms_for_scope(ctx: &CommandContext, scope: Scope, mut last_arg: usize)
         scope_perms |= add_perms;
         scope_perms -= sub_perms;
         ctx.core.permissions().set_scope(scope, scope_perms)?;
+        ctx.core.audit().record(AuditEventType::ConfigChange, ctx.get_guild()?,
+                                command_author(ctx), None, None, json!({
+                                    "permissions": format!("{:?}", scope),
+                                    "added": add_perms.iter().map(perm_to_name)
+                                        .collect::<Vec<_>>(),
+                                    "removed": sub_perms.iter().map(perm_to_name)
+                                        .collect::<Vec<_>>(),
+                                }));
     }
 
     let mut result = String::new();
@@ -0,0 +1,115 @@
+use super::*;
+use super::util::*;
+
+use serde_json;
+
+fn export_json(ctx: &CommandContext, discord_id: UserId) -> Result<String> {
+    Ok(serde_json::to_string_pretty(&ctx.core.privacy().export_user_data(discord_id)?)?)
+}
+fn send_export(target: UserId, discord_id: UserId, json: &str, description: &str) -> Result<()> {
+    let file_name = format!("user-data-{}.json", discord_id.0);
+    let result = target.create_dm_channel().and_then(|channel|
+        channel.send_files(vec![(json.as_bytes(), file_name.as_str())], |m|
+            m.content(description)
+        )
+    ).map_err(Error::from);
+    match result {
+        Ok(_) => Ok(()),
+        Err(match_err!(ErrorKind::SerenityPermissionError)) =>
+            cmd_error!("I could not send you a direct message. Please check your privacy \
+                        settings and try again."),
+        Err(e) => Err(e),
+    }
+}
+
+fn do_mydata(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    let json = export_json(ctx, msg.author.id)?;
+    send_export(msg.author.id, msg.author.id, &json,
+                "All data stored about your Discord account by this bot.")?;
+    if msg.guild_id.is_some() {
+        ctx.respond("Your data has been sent to you in a direct message.")?;
+    }
+    Ok(())
+}
+fn do_export_user_data(ctx: &CommandContext) -> Result<()> {
+    let discord_id = find_user(ctx.arg(0)?)?.to_cmd_err(|| "Could not parse user id.")?;
+    let json = export_json(ctx, discord_id)?;
+    match ctx.discord_context() {
+        Some((_, msg)) => {
+            send_export(msg.author.id, discord_id, &json,
+                        &format!("All data stored about {} (`{}`).",
+                                 get_discord_username(discord_id), discord_id.0))?;
+            if msg.guild_id.is_some() {
+                ctx.respond("The data has been sent to you in a direct message.")?;
+            }
+            Ok(())
+        }
+        None => ctx.respond(json),
+    }
+}
+
+fn forget(
+    ctx: &CommandContext, discord_id: UserId, actor: Option<UserId>, confirm_arg: usize,
+    confirm_cmd: &str,
+) -> Result<()> {
+    if ctx.arg_opt(confirm_arg) != Some("confirm") {
+        return ctx.respond(format!(
+            "This will unverify {0}, remove the roles this bot manages in every server, and \
+             delete {1} verification history, cooldowns, and pending and approved \
+             verifications. Bans, alt account flags and permissions set by moderators are kept. \
+             **This cannot be undone.**\n\
+             Use `{2}{3} confirm` to continue.",
+            if actor.is_some() { "this user" } else { "you" },
+            if actor.is_some() { "their" } else { "your" },
+            ctx.prefix(), confirm_cmd,
+        ))
+    }
+
+    let (linked, results) = ctx.core.privacy().forget_user(discord_id)?;
+    ctx.core.audit().record(AuditEventType::Forget, ctx.get_guild()?, actor, None, None,
+                            json!({ "linked_accounts": linked.len() }));
+    info!("Deleted the data of a user on {}.",
+          if actor.is_some() { "a bot admin's request" } else { "their own request" });
+
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    if failed == 0 {
+        ctx.respond("The data has been deleted.")
+    } else {
+        ctx.respond(format!("The data has been deleted, but roles could not be removed in {} \
+                             server(s). Please ask a moderator to remove them.", failed))
+    }
+}
+fn do_forget_me(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    forget(ctx, msg.author.id, None, 0, "forget_me")
+}
+fn do_forget_user(ctx: &CommandContext) -> Result<()> {
+    let target = ctx.arg(0)?;
+    let discord_id = find_user(target)?.to_cmd_err(|| "Could not parse user id.")?;
+    let actor = command_author(ctx);
+    cmd_ensure!(actor != Some(discord_id),
+                "Use the forget_me command to delete your own data.");
+    let confirm_cmd = format!("forget_user {}", target);
+    forget(ctx, discord_id, actor, 1, &confirm_cmd)
+}
+
+crate const COMMANDS: &[Command] = &[
+    Command::new("mydata")
+        .help(None, "Sends you a copy of all data this bot stores about you.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage | CommandTarget::PrivateMessage))
+        .exec_discord(do_mydata),
+    Command::new("forget_me")
+        .help(Some("[confirm]"),
+              "Unverifies you, removes your roles, and deletes your verification data.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage | CommandTarget::PrivateMessage))
+        .exec_discord(do_forget_me),
+    Command::new("export_user_data")
+        .help(Some("<discord mention or user id>"),
+              "Exports all data this bot stores about a user.")
+        .required_permissions(enum_set!(BotPermission::BotAdmin))
+        .exec(do_export_user_data),
+    Command::new("forget_user")
+        .help(Some("<discord mention or user id> [confirm]"),
+              "Unverifies a user, removes their roles, and deletes their verification data.")
+        .required_permissions(enum_set!(BotPermission::BotAdmin))
+        .exec(do_forget_user),
+];
@@ -2,10 +2,13 @@ use super::*;
 
 use crate::util;
 use regex::Regex;
+use roblox::RobloxUserID;
+use std::time::Duration;
 
 lazy_static! {
     static ref MENTION_REGEX: Regex = Regex::new("^<@!?([0-9]+)>$").unwrap();
     static ref SNOWFLAKE_REGEX: Regex = Regex::new("^([0-9]+)$").unwrap();
+    static ref DURATION_REGEX: Regex = Regex::new("^([0-9]+)([smhdw])$").unwrap();
 }
 
 crate fn find_role(guild_id: GuildId, role_name: &str) -> Result<RoleId> {
@@ -46,4 +49,35 @@ crate fn find_user(user_name: &str) -> Result<Option<UserId>> {
     } else {
         Ok(None)
     }
-}
\ No newline at end of file
+}
+
+/// Returns the Discord user who ran a command, or `None` if it was run from the terminal.
+crate fn command_author(ctx: &CommandContext) -> Option<UserId> {
+    ctx.discord_context().map(|(_, msg)| msg.author.id)
+}
+
+/// Parses a duration such as `30m` or `7d`.
+crate fn parse_duration(duration: &str) -> Option<Duration> {
+    let captures = DURATION_REGEX.captures(duration)?;
+    let count: u64 = captures.get(1)?.as_str().parse().ok()?;
+    let unit = match captures.get(2)?.as_str() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        _ => return None,
+    };
+    Some(Duration::from_secs(count.checked_mul(unit)?))
+}
+
+/// Finds a Roblox account by username, or by user id if no account has that username.
+crate fn find_roblox_user(name: &str) -> Result<RobloxUserID> {
+    match RobloxUserID::for_username(name) {
+        Ok(roblox_id) => Ok(roblox_id),
+        Err(e) => match name.parse() {
+            Ok(id) if RobloxUserID(id).lookup_username_opt()?.is_some() => Ok(RobloxUserID(id)),
+            _ => Err(e),
+        },
+    }
+}
@@ -4,188 +4,287 @@ use super::util::*;
 use chrono::{DateTime, Utc};
 use roblox::*;
 use serenity;
-use std::borrow::Cow;
 use std::time::SystemTime;
 use util;
 
 // TODO: Check role existence.
 // TODO: Take care of massive code redundancy here.
 
-fn get_discord_username(discord_id: UserId) -> String {
-    match discord_id.to_user_cached() {
-        Some(x) => x.read().tag(),
-        None => match discord_id.to_user() {
-            Ok(x) => x.tag(),
-            Err(_) => format!("(discord uid #{})", discord_id.0),
-        }
-    }
-}
-fn verify_status_str(prefix: &str, result: SetRolesStatus) -> Cow<'static, str> {
-    match result {
-        SetRolesStatus::Success {
-            nickname_admin_error, determine_roles_error, set_roles_error, ..
-        } => {
-            if determine_roles_error || set_roles_error {
-                format!(
-                    "An error occurred while {}, and some of your roles may not have been set. \
-                     Please wait a while then use the '{}update' command.",
-                    if determine_roles_error {
-                        "looking up your Roblox account"
-                    } else {
-                        "setting your Discord roles"
-                    },
-                    prefix,
-                ).into()
-            } else {
-                format!(
-                    "Your roles have been updated.{}",
-                    if nickname_admin_error {
-                        " Your nickname was not set as this bot does not have the permissions \
-                          needed to edit it."
-                    } else { "" }
-                ).into()
-            }
-        }
-        SetRolesStatus::NotVerified =>
-            "Your roles were not updated as you are not verified.".into(),
-    }
-}
-fn reverify_help(
-    ctx: &CommandContext, discord_id: UserId, roblox_id: RobloxUserID,
-) -> Result<String> {
-    if ctx.core.verifier().get_verified_roblox_user(discord_id)? == Some(roblox_id) {
-        Ok(format!(" If you only want to update your roles, use the '{}update' command.",
-                   ctx.prefix()))
-    } else {
-        Ok(String::new())
-    }
-}
 fn log_unverify(
     ctx: &CommandContext, discord_id: UserId, roblox_id: RobloxUserID,
 ) -> Result<()> {
-    let log_channel = ctx.core.config().get(None, ConfigKeys::GlobalVerificationLogChannel)?;
-    if let Some(log_channel_id) = log_channel {
-        let log_channel_id = ChannelId(log_channel_id);
-        let discord_username = discord_id.to_user()?.tag();
-        let roblox_username = roblox_id.lookup_username()?;
-        log_channel_id.send_message(|m|
-            m.content(format_args!("`[{}]` ⚠ {} (`{}`) has been unverified.\n\
-                                    Old Roblox account: `{}` (https://www.roblox.com/users/{}/profile)",
-                                  Utc::now().format("%H:%M:%S"),
-                                  discord_username, discord_id.0, roblox_username, roblox_id.0))
-        )?;
-    }
+    let discord_username = discord_id.to_user()?.tag();
+    let roblox_username = roblox_id.lookup_username()?;
+    ctx.core.verification_log().log(
+        ctx.get_guild()?, LogEvent::Unverify, Some(discord_id), Some(roblox_id),
+        &format!("{} (`{}`) has been unverified.\n\
+                  Old Roblox account: `{}` (https://www.roblox.com/users/{}/profile)",
+                 discord_username, discord_id.0, roblox_username, roblox_id.0),
+    );
     Ok(())
 }
 fn do_verify(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
-    cmd_ensure!(ctx.argc() >= 2, ctx.core.verify_channel().verify_instructions()?);
+    let guild_id = msg.guild_id;
+    cmd_ensure!(ctx.argc() >= 2, ctx.core.verify_channel().verify_instructions(guild_id)?);
+    cmd_ensure!(ctx.core.config().get(guild_id, ConfigKeys::AllowPlaceVerification)?,
+                "Verification using the verification place is disabled here. {}",
+                ctx.core.verify_channel().verify_instructions(guild_id)?);
 
     let roblox_username = ctx.arg(0)?;
     let token = ctx.arg(1)?;
 
     let roblox_id = RobloxUserID::for_username(roblox_username)?;
-    let discord_username = msg.author.tag();
-    let discord_id = msg.author.id;
+    let result = ctx.core.verifier().try_verify(guild_id, msg.author.id, roblox_id, token)?;
+    ctx.respond(finish_verify(ctx.core, ctx.prefix(), guild_id, &msg.author,
+                              roblox_username, roblox_id, result, "")?)
+}
+fn do_link(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    let guild_id = msg.guild_id;
+    cmd_ensure!(ctx.argc() >= 2, ctx.core.verify_channel().verify_instructions(guild_id)?);
+    cmd_ensure!(ctx.core.config().get(guild_id, ConfigKeys::AllowPlaceVerification)?,
+                "Verification using the verification place is disabled here. {}",
+                ctx.core.verify_channel().verify_instructions(guild_id)?);
 
-    let discord_display = format!("{} (`{}`)", discord_username, discord_id.0);
-    let roblox_display = format!("`{}` (https://www.roblox.com/users/{}/profile)",
-                                 roblox_username, roblox_id.0);
+    let roblox_username = ctx.arg(0)?;
+    let token = ctx.arg(1)?;
 
-    let guild_id = msg.guild_id.ok_or_else(Error::none)?;
+    let roblox_id = RobloxUserID::for_username(roblox_username)?;
+    let result = ctx.core.verifier().try_link(guild_id, msg.author.id, roblox_id, token)?;
+    ctx.respond(finish_verify(ctx.core, ctx.prefix(), guild_id, &msg.author,
+                              roblox_username, roblox_id, result, "")?)
+}
+fn do_unlink(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    let roblox_username = ctx.arg(0)?;
+    let roblox_id = RobloxUserID::for_username(roblox_username)?;
+    ctx.core.verifier().unlink_account(msg.author.id, roblox_id)?;
+    ctx.core.audit().record(AuditEventType::Unlink, msg.guild_id, Some(msg.author.id),
+                            Some(msg.author.id), Some(roblox_id), json!({}));
+    info!("{} (`{}`) unlinked {}.", msg.author.tag(), msg.author.id.0, roblox_username);
+
+    let primary_id = ctx.core.verifier().get_verified_roblox_user(msg.author.id)?;
+    ctx.respond(update_linked_roles(ctx.core, ctx.prefix(), msg.guild_id, msg.author.id,
+                                    primary_id,
+                                    &format!("{} has been unlinked from your Discord account.",
+                                             roblox_username))?)
+}
+fn do_set_primary(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    let roblox_username = ctx.arg(0)?;
+    let roblox_id = RobloxUserID::for_username(roblox_username)?;
+    ctx.core.verifier().set_primary_account(msg.author.id, roblox_id)?;
+    ctx.respond(update_linked_roles(ctx.core, ctx.prefix(), msg.guild_id, msg.author.id,
+                                    Some(roblox_id),
+                                    &format!("{} is now your primary account.",
+                                             roblox_username))?)
+}
+fn do_verify_profile(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    let guild_id = msg.guild_id;
+    cmd_ensure!(ctx.argc() >= 1, ctx.core.verify_channel().verify_instructions(guild_id)?);
+    cmd_ensure!(ctx.core.config().get(guild_id, ConfigKeys::AllowProfileVerification)?,
+                "Verification using your Roblox profile is disabled here. {}",
+                ctx.core.verify_channel().verify_instructions(guild_id)?);
 
-    debug!("Beginning verification attempt: {} -> {}", discord_display, roblox_display);
+    let roblox_username = ctx.arg(0)?;
+    let roblox_id = RobloxUserID::for_username(roblox_username)?;
+    let verifier = ctx.core.verifier();
+    if verifier.profile_phrase(msg.author.id, roblox_id).is_none() {
+        let (phrase, expires) = verifier.issue_profile_phrase(msg.author.id, roblox_id);
+        let instructions = format!("Please add the following phrase to the profile description \
+                                    of {} at <https://www.roblox.com/users/{}/profile>, then use \
+                                    `{}verify_profile {}` again within {}:\n`{}`",
+                                   roblox_username, roblox_id.0, ctx.prefix(), roblox_username,
+                                   util::english_time_diff(SystemTime::now(), expires), phrase);
 
-    let log_channel = ctx.core.config().get(None, ConfigKeys::GlobalVerificationLogChannel)?;
-    macro_rules! verify_status {
-        ($prefix_log:expr, $($format:tt)*) => {
-            let buffer = format!($($format)*);
-            info!("{}", buffer);
-            if let Some(log_channel_id) = log_channel {
-                let log_channel_id = ChannelId(log_channel_id);
-                log_channel_id.send_message(|m|
-                    m.content(format_args!(concat!("`[{}]` ", $prefix_log, "{}"),
-                                           Utc::now().format("%H:%M:%S"), buffer))
-                )?;
-            }
+        // Responses in verification channels are deleted quickly, so try to send the phrase
+        // somewhere the user can still see it while editing their profile.
+        let sent_dm = guild_id.is_some() && msg.author.id.create_dm_channel()
+            .and_then(|channel| channel.say(&instructions)).is_ok();
+        if sent_dm {
+            ctx.respond("You have been sent a direct message with further instructions.")
+        } else {
+            ctx.respond(instructions)
         }
+    } else {
+        let result = verifier.try_verify_profile(guild_id, msg.author.id, roblox_id)?;
+        ctx.respond(finish_verify(ctx.core, ctx.prefix(), guild_id, &msg.author,
+                                  roblox_username, roblox_id, result,
+                                  " using their profile description")?)
     }
-    match ctx.core.verifier().try_verify(discord_id, roblox_id, token)? {
-        VerifyResult::VerificationOk => {
-            verify_status!("ℹ ", "{} successfully verified as {}",
-                           discord_display, roblox_display);
-        }
-        VerifyResult::ReverifyOk { discord_link, roblox_link } => {
-            let discord_link_display = if let Some(discord_id) = discord_link {
-                let discord_username = discord_id.to_user()?.tag();
-                format!("Old Discord account: {} (`{}`)", discord_username, discord_id.0)
-            } else {
-                format!("No old Discord account")
-            };
-            let roblox_link_display = if let Some(roblox_id) = roblox_link {
-                let roblox_username = roblox_id.lookup_username()?;
-                format!("Old Roblox account: `{}` (https://www.roblox.com/users/{}/profile)",
-                        roblox_username, roblox_id.0)
-            } else {
-                format!("No old Roblox account")
-            };
-            verify_status!("⚠ ", "{} successfully reverified as {}\n{}; {}",
-                           discord_display, roblox_display,
-                           discord_link_display, roblox_link_display);
-        }
-        VerifyResult::TokenAlreadyUsed => {
-            verify_status!("🛑 ", "{} failed to verify as {}: Token already used.",
-                           discord_display, roblox_display);
-            cmd_error!("Someone has already used that verification code. Please wait for a \
-                        new code to be generated, then try again.")
-        }
-        VerifyResult::VerificationPlaceOutdated => {
-            verify_status!("⚠⚠⚠⚠⚠ ", "{} failed to verify as {}: Outdated verification place.",
-                           discord_display, roblox_display);
-            cmd_error!("The verification place is outdated, and has not been updated with the \
-                        verification bot. Please contact the bot owner.")
-        }
-        VerifyResult::InvalidToken => {
-            verify_status!("🛑 ", "{} failed to verify as {}: Invalid token.",
-                           discord_display, roblox_display);
-            cmd_error!("The verification code you used is not valid. Please check the code \
-                        you entered and try again.")
-        }
-        VerifyResult::TooManyAttempts { max_attempts, cooldown, cooldown_ends } => {
-            verify_status!("🛑 ", "{} failed to verify as {}: Too many attempts.",
-                           discord_display, roblox_display);
-            cmd_error!("You can only try to verify {} times every {}. \
-                        Please try again in {}.{}",
-                       max_attempts, util::to_english_time(cooldown),
-                       util::english_time_diff(SystemTime::now(), cooldown_ends),
-                       reverify_help(ctx, discord_id, roblox_id)?)
-        }
-        VerifyResult::SenderVerifiedAs { other_roblox_id } => {
-            let other_roblox_username = other_roblox_id.lookup_username()?;
-            verify_status!("🛑 ", "{} failed to verify as {}: Already verified as {}.",
-                           discord_display, roblox_display, other_roblox_username);
-            cmd_error!("You are already verified as {}.{}",
-                       other_roblox_username, reverify_help(ctx, discord_id, roblox_id)?)
-        }
-        VerifyResult::RobloxAccountVerifiedTo { other_discord_id } => {
-            let other_discord_username = get_discord_username(other_discord_id);
-            verify_status!("🛑 ", "{} failed to verify as {}: Roblox account already verified to {}.",
-                           discord_display, roblox_display, other_discord_username);
-            cmd_error!("{} has already verified as {}.",
-                       other_discord_username, roblox_username)
-        }
-        VerifyResult::ReverifyOnCooldown { cooldown, cooldown_ends } => {
-            verify_status!("🛑 ", "{} failed to verify as {}: Reverified too soon.",
-                           discord_display, roblox_display);
-            cmd_error!("You can only reverify once every {}. Please try again in {}.{}",
-                       util::to_english_time(cooldown),
-                       util::english_time_diff(SystemTime::now(), cooldown_ends),
-                       reverify_help(ctx, discord_id, roblox_id)?)
+}
+fn do_verify_oauth(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    let guild_id = msg.guild_id;
+    cmd_ensure!(ctx.core.config().get(guild_id, ConfigKeys::AllowOAuthVerification)? &&
+                ctx.core.oauth().is_configured()?,
+                "Verification by logging in to Roblox is disabled here. {}",
+                ctx.core.verify_channel().verify_instructions(guild_id)?);
+
+    let (url, expires) = ctx.core.oauth().start_login(guild_id, msg.author.id)?;
+    let instructions = format!("Please open the following link and log in to Roblox using the \
+                                account you want to verify as. The link can only be used once, \
+                                and expires in {}. Do not share it with anyone.\n<{}>",
+                               util::english_time_diff(SystemTime::now(), expires), url);
+
+    // The link is tied to this user, so it must not be posted where others can see it.
+    if guild_id.is_none() {
+        return ctx.respond(instructions)
+    }
+    msg.author.id.create_dm_channel().and_then(|channel| channel.say(&instructions))
+        .to_cmd_err(|| "Could not send you a direct message. Please allow direct messages \
+                        from server members, then try again.")?;
+    ctx.respond("You have been sent a direct message with further instructions.")
+}
+fn do_verify_in_game(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    let guild_id = msg.guild_id;
+    cmd_ensure!(ctx.argc() >= 1, ctx.core.verify_channel().verify_instructions(guild_id)?);
+    cmd_ensure!(ctx.core.config().get(guild_id, ConfigKeys::AllowPushVerification)? &&
+                ctx.core.verify_channel().push_configured()?,
+                "Verification using a pairing code is disabled here. {}",
+                ctx.core.verify_channel().verify_instructions(guild_id)?);
+    let place_id = ctx.core.config().get(None, ConfigKeys::PlaceID)?
+        .to_cmd_err(|| "No place ID set. Please ask the bot owner to fix this.")?;
+
+    let roblox_username = ctx.arg(0)?;
+    let roblox_id = RobloxUserID::for_username(roblox_username)?;
+    let (code, expires) =
+        ctx.core.verifier().issue_pairing_code(guild_id, msg.author.id, roblox_id);
+    let instructions = format!("Please join the place at <https://roblox.com/games/{}> as {}, \
+                                then type the following pairing code into the chat within {}:\n\
+                                `{}`",
+                               place_id, roblox_username,
+                               util::english_time_diff(SystemTime::now(), expires), code);
+
+    // The code only works for the given Roblox account, but responses in verification channels
+    // are deleted quickly, so try to send it somewhere the user can still see it.
+    let sent_dm = guild_id.is_some() && msg.author.id.create_dm_channel()
+        .and_then(|channel| channel.say(&instructions)).is_ok();
+    if sent_dm {
+        ctx.respond("You have been sent a direct message with further instructions.")
+    } else {
+        ctx.respond(instructions)
+    }
+}
+/// Adds the outcome of a review to the message posted in the review channel.
+fn mark_reviewed(pending: &PendingVerification, outcome: &str) {
+    if let Some((channel_id, message_id)) = pending.review_message {
+        let result = channel_id.message(message_id).and_then(|message|
+            channel_id.edit_message(message_id, |m|
+                m.content(format_args!("{}\n{}", message.content, outcome))
+            )
+        );
+        if let Err(e) = result {
+            debug!("Could not update verification review message: {}", e);
         }
     }
+}
+fn approve_verification(
+    core: &VerifierCore, prefix: &str, guild_id: GuildId, discord_id: UserId, actor: UserId,
+) -> Result<String> {
+    let (pending, _) = core.verifier().approve_verification(guild_id, discord_id, actor)?;
+    core.audit().record(AuditEventType::Approve, Some(guild_id), Some(actor), Some(discord_id),
+                        Some(pending.roblox_id), json!({ "is_link": pending.is_link }));
+    let roblox_username = format_roblox_id(pending.roblox_id);
+    info!("{} approved the verification of {} as {} in {}.",
+          format_discord_id(actor), format_discord_id(discord_id), roblox_username,
+          guild_name(guild_id));
+    mark_reviewed(&pending, &format!("{} Approved by {}.",
+                                     APPROVE_EMOJI, get_discord_username(actor)));
+    check_alts(core, discord_id, pending.roblox_id);
 
-    let status = ctx.core.roles().assign_roles(guild_id, msg.author.id, Some(roblox_id))?;
-    ctx.respond(verify_status_str(ctx.prefix(), status))?;
-    Ok(())
+    let primary_id = core.verifier().get_verified_roblox_user(discord_id)?;
+    let results = core.roles().assign_roles_all(discord_id, primary_id, None)?;
+    if let Ok(channel) = discord_id.create_dm_channel() {
+        channel.say(format!("Your verification as {} in **{}** has been approved.\n{}",
+                            roblox_username, guild_name(guild_id),
+                            roles_summary(prefix, &results)?)).ok();
+    }
+    Ok(format!("The verification of {} as {} has been approved.",
+               get_discord_username(discord_id), roblox_username))
+}
+fn deny_verification(
+    core: &VerifierCore, guild_id: GuildId, discord_id: UserId, actor: UserId,
+    reason: Option<&str>,
+) -> Result<String> {
+    let pending = core.verifier().deny_verification(guild_id, discord_id, actor)?;
+    core.audit().record(AuditEventType::Deny, Some(guild_id), Some(actor), Some(discord_id),
+                        Some(pending.roblox_id),
+                        json!({ "is_link": pending.is_link, "reason": reason }));
+    let roblox_username = format_roblox_id(pending.roblox_id);
+    let reason_str = reason.map_or(String::new(), |x| format!(" (reason: {})", x));
+    info!("{} denied the verification of {} as {} in {}{}.",
+          format_discord_id(actor), format_discord_id(discord_id), roblox_username,
+          guild_name(guild_id), reason_str);
+    mark_reviewed(&pending, &format!("{} Denied by {}{}.",
+                                     DENY_EMOJI, get_discord_username(actor), reason_str));
+    if let Ok(channel) = discord_id.create_dm_channel() {
+        channel.say(format!("Your verification as {} in **{}** has been denied{}.",
+                            roblox_username, guild_name(guild_id), reason_str)).ok();
+    }
+    Ok(format!("The verification of {} as {} has been denied.",
+               get_discord_username(discord_id), roblox_username))
+}
+
+/// Approves or denies a verification when a moderator reacts to its message in a review
+/// channel.
+pub fn review_reaction(core: &VerifierCore, reaction: &Reaction) -> Result<()> {
+    let approve = match reaction.emoji {
+        ReactionType::Unicode(ref emoji) if emoji == APPROVE_EMOJI => true,
+        ReactionType::Unicode(ref emoji) if emoji == DENY_EMOJI => false,
+        _ => return Ok(()),
+    };
+    if reaction.user_id == serenity::CACHE.read().user.id {
+        return Ok(())
+    }
+    let verifier = core.verifier();
+    let pending = match verifier.find_pending_by_message(reaction.channel_id,
+                                                         reaction.message_id)? {
+        Some(pending) => pending,
+        None => return Ok(()),
+    };
+    let guild_id = pending.guild_id;
+    if !core.config().get(Some(guild_id), ConfigKeys::VerificationApprovalReactions)? ||
+       !core.permissions().get_user_perms(guild_id, reaction.user_id)?
+           .contains(BotPermission::ApproveVerification) {
+        return Ok(())
+    }
+
+    let result = if approve {
+        let prefix = core.config().get(None, ConfigKeys::CommandPrefix)?;
+        approve_verification(core, &prefix, guild_id, pending.discord_id, reaction.user_id)
+    } else {
+        deny_verification(core, guild_id, pending.discord_id, reaction.user_id, None)
+    };
+    result.drop_nonfatal()
+}
+
+fn do_approve(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    let guild_id = msg.guild_id.ok_or_else(Error::none)?;
+    let discord_id = find_user(ctx.arg(0)?)?.to_cmd_err(|| "Could not parse user id.")?;
+    ctx.respond(approve_verification(ctx.core, ctx.prefix(), guild_id, discord_id,
+                                     msg.author.id)?)
+}
+fn do_deny(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    let guild_id = msg.guild_id.ok_or_else(Error::none)?;
+    let discord_id = find_user(ctx.arg(0)?)?.to_cmd_err(|| "Could not parse user id.")?;
+    let reason = ctx.rest(1)?.trim();
+    let reason = if reason.is_empty() { None } else { Some(reason) };
+    ctx.respond(deny_verification(ctx.core, guild_id, discord_id, msg.author.id, reason)?)
+}
+fn do_list_pending(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    let guild_id = msg.guild_id.ok_or_else(Error::none)?;
+    let pending = ctx.core.verifier().get_pending_verifications(guild_id)?;
+    if pending.is_empty() {
+        return ctx.respond("There are no verifications waiting for approval.")
+    }
+    let now = SystemTime::now();
+    let mut buffer = String::new();
+    writeln!(buffer, "Verifications waiting for approval:")?;
+    for pending in pending {
+        writeln!(buffer, "• {} wants to {} {} (expires in {})",
+                 format_discord_id(pending.discord_id),
+                 if pending.is_link { "link" } else { "verify as" },
+                 format_roblox_id(pending.roblox_id),
+                 util::english_time_diff(now, pending.expires))?;
+    }
+    ctx.respond(buffer)
 }
 
 fn check_configuration(ctx: &CommandContext, guild_id: GuildId) -> Result<()> {
@@ -197,25 +296,32 @@ fn check_configuration(ctx: &CommandContext, guild_id: GuildId) -> Result<()> {
     }
 }
 
-fn whois_msg(
-    ctx: &CommandContext, user: User, roblox_id: RobloxUserID, roblox_name: &str
-) -> Result<()> {
-    ctx.respond(format!("{} (`{}`) is verified as {} (https://www.roblox.com/users/{}/profile)",
-                        user.tag(), user.id.0, roblox_name, roblox_id.0))
+fn whois_msg(ctx: &CommandContext, user: User, accounts: &[LinkedAccount]) -> Result<()> {
+    let mut msg = String::new();
+    let mut is_first_additional = true;
+    for account in accounts {
+        let roblox_id = account.roblox_id;
+        let roblox_display = format_roblox_id(roblox_id);
+        if account.is_primary {
+            write!(msg, "{} (`{}`) is verified as {} (https://www.roblox.com/users/{}/profile)",
+                   user.tag(), user.id.0, roblox_display, roblox_id.0)?;
+        } else {
+            if is_first_additional {
+                write!(msg, "\nAdditional linked accounts:")?;
+                is_first_additional = false;
+            }
+            write!(msg, "\n• {} (https://www.roblox.com/users/{}/profile)",
+                   roblox_display, roblox_id.0)?;
+        }
+    }
+    ctx.respond(msg.trim())
 }
 fn whois_discord(ctx: &CommandContext, discord_user_id: UserId) -> Result<()> {
     let user = discord_user_id.to_user().map_err(Error::from)
         .status_to_cmd(StatusCode::NotFound, || "That Discord account does not exist.")?;
-    let roblox_user_id = ctx.core.verifier().get_verified_roblox_user(discord_user_id)?;
-    if let Some(roblox_user_id) = roblox_user_id {
-        let roblox_name = roblox_user_id.lookup_username_opt()?;
-        if let Some(roblox_name) = roblox_name {
-            whois_msg(ctx, user, roblox_user_id, &roblox_name)
-        } else {
-            cmd_error!("{} (`{}`) is verified as Roblox User ID #{}, which no longer exists. \
-                        (https://www.roblox.com/users/{}/profile)",
-                       user.tag(), user.id.0, roblox_user_id.0, roblox_user_id.0)
-        }
+    let accounts = ctx.core.verifier().get_linked_accounts(discord_user_id)?;
+    if !accounts.is_empty() {
+        whois_msg(ctx, user, &accounts)
     } else {
         cmd_error!("{} (`{}`) isn't verified.", user.tag(), user.id.0)
     }
@@ -228,7 +334,7 @@ fn whois_roblox(ctx: &CommandContext, roblox_name: &str) -> Result<()> {
             .status_to_cmd(StatusCode::NotFound, ||
                 format!("The Discord account verified with '{}' no longer exists.", roblox_name)
             )?;
-        whois_msg(ctx, user, roblox_user_id, roblox_name)
+        whois_msg(ctx, user, &ctx.core.verifier().get_linked_accounts(discord_user_id)?)
     } else {
         cmd_error!("No Discord user has verified as {} (https://www.roblox.com/users/{}/profile)",
                    roblox_name, roblox_user_id.0)
@@ -243,41 +349,54 @@ fn do_whois(ctx: &CommandContext) -> Result<()> {
     }
 }
 
-fn format_discord_id(id: UserId) -> String {
-    match id.to_user() {
-        Ok(user) => format!("{} (`{}`)", user.tag(), id.0),
-        Err(_) => format!("*(non-existent Discord id #{})*", id.0),
-    }
-}
-fn format_roblox_id(id: RobloxUserID) -> String {
-    match id.lookup_username() {
-        Ok(username) => username,
-        Err(_) => format!("*(non-existent Roblox id #{})*", id.0),
-    }
-}
 fn display_history<T>(
      ctx: &CommandContext, entries: Vec<HistoryEntry<T>>,
-     header_name: &str, to_string: impl Fn(T) -> String,
+     header_name: &str, current_links: &str, to_string: impl Fn(T) -> String,
 ) -> Result<()> {
     let mut history = String::new();
     writeln!(history, "History for {}:", header_name)?;
     for entry in entries {
         let date: DateTime<Utc> = entry.last_updated.into();
-        writeln!(history, "• Account was {} with {} on {} UTC",
-                 if entry.is_unverify { "unverified" } else { "verified" },
-                 to_string(entry.id), date.format("%Y-%m-%d %H:%M:%S"))?;
+        write!(history, "• Account was {} with {} on {} UTC",
+               if entry.is_unverify { "unverified" } else { "verified" },
+               to_string(entry.id), date.format("%Y-%m-%d %H:%M:%S"))?;
+        if let Some(actor) = entry.actor {
+            write!(history, " by {}", format_discord_id(actor))?;
+        }
+        if let Some(note) = entry.note {
+            write!(history, " ({})", note)?;
+        }
+        writeln!(history)?;
     }
+    writeln!(history, "{}", current_links)?;
     ctx.respond(history)
 }
 fn do_whowas(ctx: &CommandContext) -> Result<()> {
     let target_name = ctx.arg(0)?;
     if let Some(user_id) = find_user(target_name)? {
+        let accounts = ctx.core.verifier().get_linked_accounts(user_id)?;
+        let current_links = if accounts.is_empty() {
+            "Currently not verified.".to_owned()
+        } else {
+            let names: Vec<String> = accounts.iter().map(|account| if account.is_primary {
+                format!("{} (primary)", format_roblox_id(account.roblox_id))
+            } else {
+                format_roblox_id(account.roblox_id)
+            }).collect();
+            format!("Currently linked to: {}", names.join(", "))
+        };
         display_history(ctx, ctx.core.verifier().get_discord_user_history(user_id, 10)?,
-                        &format!("Discord user {}", format_discord_id(user_id)), format_roblox_id)
+                        &format!("Discord user {}", format_discord_id(user_id)),
+                        &current_links, format_roblox_id)
     } else {
         let roblox_id = RobloxUserID::for_username(target_name)?;
+        let current_links = match ctx.core.verifier().get_verified_discord_user(roblox_id)? {
+            Some(discord_id) => format!("Currently linked to: {}", format_discord_id(discord_id)),
+            None => "Currently not linked to any Discord account.".to_owned(),
+        };
         display_history(ctx, ctx.core.verifier().get_roblox_user_history(roblox_id, 10)?,
-                        &format!("Roblox user {}", format_roblox_id(roblox_id)), format_discord_id)
+                        &format!("Roblox user {}", format_roblox_id(roblox_id)),
+                        &current_links, format_discord_id)
     }
 }
 
@@ -287,6 +406,8 @@ fn force_unverify(
     let user = discord_id.to_user().map_err(Error::from)
         .status_to_cmd(StatusCode::NotFound, || "That Discord account does not exist.")?;
     ctx.core.verifier().unverify(discord_id)?;
+    ctx.core.audit().record(AuditEventType::ForceUnverify, ctx.get_guild()?, command_author(ctx),
+                            Some(discord_id), Some(roblox_id), json!({}));
     ctx.respond(format!("User {} has been unverified with {}.",
                         user.tag(), roblox_id.lookup_username()?))?;
     log_unverify(ctx, discord_id, roblox_id)?;
@@ -311,13 +432,85 @@ fn do_force_unverify(ctx: &CommandContext) -> Result<()> {
         }
     }
 }
+fn do_force_verify(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    let discord_id = find_user(ctx.arg(0)?)?.to_cmd_err(|| "Could not parse user id.")?;
+    let user = discord_id.to_user().map_err(Error::from)
+        .status_to_cmd(StatusCode::NotFound, || "That Discord account does not exist.")?;
+    let roblox_id = find_roblox_user(ctx.arg(1)?)?;
+    let reason = ctx.rest(2)?.trim();
+    cmd_ensure!(!reason.is_empty(), "Please give a reason for manually verifying this user.");
+
+    let (roblox_link, discord_link) =
+        ctx.core.verifier().force_verify(discord_id, roblox_id, msg.author.id, reason)?;
+    ctx.core.audit().record(AuditEventType::ForceVerify, msg.guild_id, Some(msg.author.id),
+                            Some(discord_id), Some(roblox_id), json!({
+                                "reason": reason,
+                                "old_discord_id": discord_link.map(|x| x.0),
+                                "old_roblox_id": roblox_link.map(|x| x.0),
+                            }));
+    let roblox_username = roblox_id.lookup_username()?;
+
+    let mut buffer = format!("{} (`{}`) was manually verified as `{}` \
+                              (https://www.roblox.com/users/{}/profile) by {} (`{}`).\n\
+                              Reason: {}",
+                             user.tag(), discord_id.0, roblox_username, roblox_id.0,
+                             msg.author.tag(), msg.author.id.0, reason);
+    if let Some(old_id) = roblox_link {
+        write!(buffer, "\nOld Roblox account: {}", format_roblox_id(old_id))?;
+    }
+    if let Some(old_id) = discord_link {
+        write!(buffer, "\nOld Discord account: {}", format_discord_id(old_id))?;
+    }
+    info!("{}", buffer);
+    ctx.core.verification_log().log(msg.guild_id, LogEvent::Verify, Some(discord_id),
+                                    Some(roblox_id), &buffer);
+    check_alts(ctx.core, discord_id, roblox_id);
+
+    let results = ctx.core.roles().assign_roles_all(discord_id, Some(roblox_id), None)?;
+    if let Ok(channel) = discord_id.create_dm_channel() {
+        channel.say(format!("{} has manually verified you as {}.\n{}",
+                            msg.author.tag(), roblox_username,
+                            roles_summary(ctx.prefix(), &results)?)).ok();
+    }
+    ctx.respond(format!("{} has been manually verified as {}.", user.tag(), roblox_username))
+}
+fn do_update(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
+    cmd_ensure!(ctx.core.verifier().get_verified_roblox_user(msg.author.id)?.is_some(),
+                "You are not verified with this bot. {}",
+                ctx.core.verify_channel().verify_instructions(msg.guild_id)?);
+
+    let update = |guild_id: GuildId| -> Result<SetRolesStatus> {
+        let cooldown = ctx.core.config().get(Some(guild_id), ConfigKeys::UpdateCooldownSeconds)?;
+        ctx.core.roles().update_user_with_cooldown(guild_id, msg.author.id, cooldown, true, false)
+    };
+    match msg.guild_id {
+        Some(guild_id) => ctx.respond(verify_status_str(ctx.prefix(), update(guild_id)?)),
+        None => {
+            let results: Vec<_> = ctx.core.roles().mutual_guilds(msg.author.id)?.into_iter()
+                .map(|guild_id| (guild_id, update(guild_id)))
+                .collect();
+            ctx.respond(roles_summary(ctx.prefix(), &results)?)
+        }
+    }
+}
 fn do_unverify(ctx: &CommandContext, _: &Context, msg: &Message) -> Result<()> {
-    let guild_id = msg.guild_id.ok_or_else(Error::none)?;
     let roblox_id = ctx.core.verifier().get_verified_roblox_user(msg.author.id)?;
     if let Some(roblox_id) = roblox_id {
         ctx.core.verifier().unverify(msg.author.id)?;
-        let status = ctx.core.roles().assign_roles(guild_id, msg.author.id, None)?;
-        ctx.respond(verify_status_str(ctx.prefix(), status))?;
+        ctx.core.audit().record(AuditEventType::Unverify, msg.guild_id, Some(msg.author.id),
+                                Some(msg.author.id), Some(roblox_id), json!({}));
+        match msg.guild_id {
+            Some(guild_id) => {
+                let status = ctx.core.roles().assign_roles(guild_id, msg.author.id, None)?;
+                ctx.core.roles().propagate_roles(guild_id, msg.author.id, None);
+                ctx.respond(verify_status_str(ctx.prefix(), status))?;
+            }
+            None => {
+                let results = ctx.core.roles().assign_roles_all(msg.author.id, None, None)?;
+                ctx.respond(format!("You have been unverified.\n{}",
+                                    roles_summary(ctx.prefix(), &results)?))?;
+            }
+        }
         log_unverify(ctx, msg.author.id, roblox_id)?;
         Ok(())
     } else {
@@ -388,7 +581,7 @@ crate const COMMANDS: &[Command] = &[
             let role_name = ctx.rest(1)?.trim();
             let guild_id = msg.guild_id.ok_or_else(Error::none)?;
             let my_id = serenity::CACHE.read().user.id;
-            if !role_name.is_empty() {
+            let role_id = if !role_name.is_empty() {
                 let role_id = find_role(guild_id, role_name)?;
                 if !ctx.has_permissions(BotPermission::BypassHierarchy.into()) &&
                    !util::can_member_access_role(guild_id, msg.author.id, role_id)? {
@@ -397,10 +590,14 @@ crate const COMMANDS: &[Command] = &[
                 if !util::can_member_access_role(guild_id, my_id, role_id)? {
                     cmd_error!("This bot does not have permission to modify that role.")
                 }
-                ctx.core.roles().set_active_role(guild_id, rule_name, Some(role_id))?;
+                Some(role_id)
             } else {
-                ctx.core.roles().set_active_role(guild_id, rule_name, None)?;
-            }
+                None
+            };
+            ctx.core.roles().set_active_role(guild_id, rule_name, role_id)?;
+            ctx.core.audit().record(AuditEventType::ConfigChange, Some(guild_id),
+                                    Some(msg.author.id), None, None,
+                                    json!({ "rule": rule_name, "role_id": role_id.map(|x| x.0) }));
             check_configuration(ctx, guild_id)
         }),
     Command::new("set_custom_rule")
@@ -412,11 +609,11 @@ crate const COMMANDS: &[Command] = &[
             let rule_name = ctx.arg(0)?;
             let definition = ctx.rest(1)?.trim();
             let guild_id = msg.guild_id.ok_or_else(Error::none)?;
-            if !definition.is_empty() {
-                ctx.core.roles().set_custom_rule(guild_id, rule_name, Some(definition))?;
-            } else {
-                ctx.core.roles().set_custom_rule(guild_id, rule_name, None)?;
-            }
+            let definition = if definition.is_empty() { None } else { Some(definition) };
+            ctx.core.roles().set_custom_rule(guild_id, rule_name, definition)?;
+            ctx.core.audit().record(AuditEventType::ConfigChange, Some(guild_id),
+                                    Some(msg.author.id), None, None,
+                                    json!({ "custom_rule": rule_name, "condition": definition }));
             check_configuration(ctx, guild_id)
         }),
     Command::new("test_verify")
@@ -429,7 +626,7 @@ crate const COMMANDS: &[Command] = &[
             let guild_id = msg.guild_id.ok_or_else(Error::none)?;
 
             let mut roles = String::new();
-            for role in ctx.core.roles().get_assigned_roles(guild_id, roblox_id)? {
+            for role in ctx.core.roles().get_assigned_roles(guild_id, &[roblox_id])? {
                 writeln!(roles, "• {}{} {} **{}**",
                          if role.is_assigned == RuleResult::Error {
                              "An error occurred while determining if "
@@ -451,24 +648,12 @@ crate const COMMANDS: &[Command] = &[
         }),
     Command::new("update")
         .help(None, "Updates your roles according to your Roblox account.")
-        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
-        .exec_discord(|ctx, _, msg| {
-            cmd_ensure!(ctx.core.verifier().get_verified_roblox_user(msg.author.id)?.is_some(),
-                        "You are not verified with this bot. {}",
-                        ctx.core.verify_channel().verify_instructions()?);
-
-            let guild_id = msg.guild_id.ok_or_else(Error::none)?;
-            let cooldown =
-                ctx.core.config().get(Some(guild_id), ConfigKeys::UpdateCooldownSeconds)?;
-            let status = ctx.core.roles().update_user_with_cooldown(
-                guild_id, msg.author.id, cooldown, true, false,
-            )?;
-            ctx.respond(verify_status_str(ctx.prefix(), status))?;
-            Ok(())
-        }),
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage |
+                                    CommandTarget::PrivateMessage))
+        .exec_discord(do_update),
     Command::new("whois")
         .help(Some("<discord mention, user id, or roblox username>"),
-              "Retrieves the Roblox account a Discord account is verified with or vice versa.")
+              "Retrieves the Roblox accounts a Discord account is verified with or vice versa.")
         .required_permissions(enum_set!(BotPermission::Whois))
         .exec(do_whois),
     Command::new("whowas")
@@ -479,11 +664,49 @@ crate const COMMANDS: &[Command] = &[
     Command::new("verify")
         .help(Some("<roblox username> <verification code>"),
               "Verifies a Roblox account to your Discord account.")
-        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage |
+                                    CommandTarget::PrivateMessage))
         .exec_discord(do_verify),
+    Command::new("link")
+        .help(Some("<roblox username> <verification code>"),
+              "Links an additional Roblox account to your Discord account.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage |
+                                    CommandTarget::PrivateMessage))
+        .exec_discord(do_link),
+    Command::new("unlink")
+        .help(Some("<roblox username>"),
+              "Unlinks an additional Roblox account from your Discord account.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage |
+                                    CommandTarget::PrivateMessage))
+        .exec_discord(do_unlink),
+    Command::new("set_primary")
+        .help(Some("<roblox username>"),
+              "Sets which of your linked Roblox accounts is used for your nickname.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage |
+                                    CommandTarget::PrivateMessage))
+        .exec_discord(do_set_primary),
+    Command::new("verify_profile")
+        .help(Some("<roblox username>"),
+              "Verifies a Roblox account to your Discord account using its profile description.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage |
+                                    CommandTarget::PrivateMessage))
+        .exec_discord(do_verify_profile),
+    Command::new("verify_oauth")
+        .help(None, "Verifies a Roblox account to your Discord account by logging in to Roblox.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage |
+                                    CommandTarget::PrivateMessage))
+        .exec_discord(do_verify_oauth),
+    Command::new("verify_in_game")
+        .help(Some("<roblox username>"),
+              "Verifies a Roblox account to your Discord account by entering a pairing code in \
+               the verification place.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage |
+                                    CommandTarget::PrivateMessage))
+        .exec_discord(do_verify_in_game),
     Command::new("unverify")
         .help(None, "Unverifies your Roblox account from your Discord account.")
-        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage |
+                                    CommandTarget::PrivateMessage))
         .required_permissions(enum_set!(BotPermission::Unverify))
         .exec_discord(do_unverify),
     Command::new("force_unverify")
@@ -492,6 +715,29 @@ crate const COMMANDS: &[Command] = &[
         .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
         .required_permissions(enum_set!(BotPermission::UnverifyOther))
         .exec(do_force_unverify),
+    Command::new("approve")
+        .help(Some("<discord mention or user id>"),
+              "Approves a verification waiting for approval in this server.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .required_permissions(enum_set!(BotPermission::ApproveVerification))
+        .exec_discord(do_approve),
+    Command::new("deny")
+        .help(Some("<discord mention or user id> [reason]"),
+              "Denies a verification waiting for approval in this server.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .required_permissions(enum_set!(BotPermission::ApproveVerification))
+        .exec_discord(do_deny),
+    Command::new("list_pending")
+        .help(None, "Lists the verifications waiting for approval in this server.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .required_permissions(enum_set!(BotPermission::ApproveVerification))
+        .exec_discord(do_list_pending),
+    Command::new("force_verify")
+        .help(Some("<discord mention or user id> <roblox username or id> <reason>"),
+              "Manually verifies a Discord account as a Roblox account.")
+        .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
+        .required_permissions(enum_set!(BotPermission::ForceVerify))
+        .exec_discord(do_force_verify),
     Command::new("set_verification_channel")
         .help(None, "Makes the current channel a verification channel.")
         .allowed_contexts(enum_set!(CommandTarget::ServerMessage))
@@ -0,0 +1,214 @@
+use core::bans::*;
+use core::config::*;
+use database::*;
+use errors::*;
+use roblox::RobloxUserID;
+use serenity::model::prelude::*;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::{SystemTime, Duration, UNIX_EPOCH};
+use util;
+
+pub struct AltFlag {
+    pub discord_id: UserId, pub roblox_id: RobloxUserID, pub reasons: Vec<String>,
+    pub holds_roles: bool, pub created: SystemTime,
+}
+
+fn discord_tag(discord_id: UserId) -> String {
+    match discord_id.to_user() {
+        Ok(user) => format!("{} (`{}`)", user.tag(), discord_id.0),
+        Err(_) => format!("(discord uid #{})", discord_id.0),
+    }
+}
+fn roblox_name(roblox_id: RobloxUserID) -> String {
+    match roblox_id.lookup_username() {
+        Ok(name) => format!("`{}`", name),
+        Err(_) => format!("(roblox uid #{})", roblox_id.0),
+    }
+}
+
+struct AltDetectorData {
+    config: ConfigManager, database: Database, bans: BanManager,
+}
+
+#[derive(Clone)]
+pub struct AltDetector(Arc<AltDetectorData>);
+impl AltDetector {
+    pub(in ::core) fn new(config: ConfigManager, database: Database, bans: BanManager) -> Self {
+        AltDetector(Arc::new(AltDetectorData { config, database, bans }))
+    }
+
+    fn find_reasons(
+        &self, guild_id: GuildId, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<Vec<String>> {
+        let config = &self.0.config;
+        let conn = self.0.database.connect()?;
+        let window = config.get(Some(guild_id), ConfigKeys::AltDetectionWindowSeconds)?;
+        let since = SystemTime::now() - Duration::from_secs(window);
+        let mut reasons = Vec::new();
+
+        let max_discord = config.get(Some(guild_id), ConfigKeys::AltDetectionDiscordAccounts)?;
+        if max_discord != 0 {
+            let count = conn.query(
+                "SELECT COUNT(DISTINCT discord_user_id) FROM user_history \
+                 WHERE roblox_user_id = ?1 AND is_unverify = 0 AND last_updated >= ?2",
+                (roblox_id, since),
+            ).get::<u32>()?;
+            if count >= max_discord {
+                reasons.push(format!("The Roblox account was verified by {} different Discord \
+                                      accounts in the last {}.",
+                                     count, util::to_english_time(window)));
+            }
+        }
+        let max_roblox = config.get(Some(guild_id), ConfigKeys::AltDetectionRobloxAccounts)?;
+        if max_roblox != 0 {
+            let count = conn.query(
+                "SELECT COUNT(DISTINCT roblox_user_id) FROM user_history \
+                 WHERE discord_user_id = ?1 AND is_unverify = 0 AND last_updated >= ?2",
+                (discord_id, since),
+            ).get::<u32>()?;
+            if count >= max_roblox {
+                reasons.push(format!("The Discord account verified as {} different Roblox \
+                                      accounts in the last {}.",
+                                     count, util::to_english_time(window)));
+            }
+        }
+
+        let other_discord_ids = conn.query(
+            "SELECT DISTINCT discord_user_id FROM user_history WHERE roblox_user_id = ?1",
+            roblox_id,
+        ).get_all::<UserId>()?;
+        for other_id in other_discord_ids {
+            if other_id == discord_id { continue }
+            if let Some(ban) = self.0.bans.find(Some(guild_id), BanTarget::Discord(other_id))? {
+                reasons.push(format!("The Roblox account was previously verified by {}, who is {}.",
+                                     discord_tag(other_id), ban.describe()));
+            }
+        }
+        let other_roblox_ids = conn.query(
+            "SELECT DISTINCT roblox_user_id FROM user_history WHERE discord_user_id = ?1",
+            discord_id,
+        ).get_all::<RobloxUserID>()?;
+        for other_id in other_roblox_ids {
+            if other_id == roblox_id { continue }
+            if let Some(ban) = self.0.bans.find(Some(guild_id), BanTarget::Roblox(other_id))? {
+                reasons.push(format!("The Discord account was previously verified as {}, which \
+                                      is {}.", roblox_name(other_id), ban.describe()));
+            }
+        }
+
+        Ok(reasons)
+    }
+
+    /// Checks a verification for patterns that suggest alt accounts in each of the given
+    /// servers, flagging it in servers that have alt account detection set up. Returns the
+    /// servers where the user's roles are now on hold.
+    pub fn check_verification(
+        &self, guilds: &[GuildId], discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<Vec<GuildId>> {
+        let mut held = Vec::new();
+        for &guild_id in guilds {
+            let channel = match self.0.config.get(Some(guild_id),
+                                                  ConfigKeys::AltDetectionChannel)? {
+                Some(channel) => ChannelId(channel),
+                None => continue,
+            };
+            let reasons = self.find_reasons(guild_id, discord_id, roblox_id)?;
+            if reasons.is_empty() { continue }
+
+            let holds_roles = self.0.config.get(Some(guild_id), ConfigKeys::AltDetectionHoldRoles)?;
+            self.0.database.connect()?.execute(
+                "REPLACE INTO alt_flags (\
+                    discord_guild_id, discord_user_id, roblox_user_id, reasons, holds_roles, \
+                    created\
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                (guild_id, discord_id, roblox_id, reasons.join("\n"), holds_roles,
+                 SystemTime::now()),
+            )?;
+            info!("{} (`{}`) was flagged as a possible alt account in {}: {}",
+                  discord_tag(discord_id), roblox_id.0, guild_id, reasons.join(" "));
+
+            let mut message = format!("{} 🚩 {} verified as {} \
+                                       (https://www.roblox.com/users/{}/profile), and was \
+                                       flagged as a possible alt account:",
+                                      util::discord_timestamp(SystemTime::now()),
+                                      discord_tag(discord_id),
+                                      roblox_name(roblox_id), roblox_id.0);
+            for reason in &reasons {
+                write!(message, "\n• {}", reason)?;
+            }
+            if holds_roles {
+                write!(message, "\nTheir roles are on hold until the flag is cleared.")?;
+                held.push(guild_id);
+            }
+            if let Err(e) = channel.send_message(|m| m.content(&message)) {
+                warn!("Could not send alt account flag to channel {}: {}", channel, e);
+            }
+        }
+        Ok(held)
+    }
+
+    /// Returns the time before which flags in a server have expired.
+    fn expiry_cutoff(&self, guild_id: GuildId) -> Result<SystemTime> {
+        let expiry = self.0.config.get(Some(guild_id), ConfigKeys::AltFlagExpirySeconds)?;
+        if expiry == 0 {
+            Ok(UNIX_EPOCH)
+        } else {
+            Ok(SystemTime::now().checked_sub(Duration::from_secs(expiry)).unwrap_or(UNIX_EPOCH))
+        }
+    }
+    /// Removes flags that were not reviewed before they expired. Returns the users whose roles
+    /// were on hold because of them.
+    pub fn remove_expired_flags(&self) -> Result<Vec<(GuildId, UserId)>> {
+        let conn = self.0.database.connect()?;
+        let guilds = conn.query("SELECT DISTINCT discord_guild_id FROM alt_flags", ())
+            .get_all::<GuildId>()?;
+        let mut released = Vec::new();
+        for guild_id in guilds {
+            let cutoff = self.expiry_cutoff(guild_id)?;
+            conn.transaction(|| {
+                released.extend(conn.query(
+                    "SELECT discord_user_id FROM alt_flags \
+                     WHERE discord_guild_id = ?1 AND created <= ?2 AND holds_roles = 1",
+                    (guild_id, cutoff),
+                ).get_all::<UserId>()?.into_iter().map(|discord_id| (guild_id, discord_id)));
+                conn.execute(
+                    "DELETE FROM alt_flags WHERE discord_guild_id = ?1 AND created <= ?2",
+                    (guild_id, cutoff),
+                )?;
+                Ok(())
+            })?;
+        }
+        Ok(released)
+    }
+
+    /// Returns whether a user's roles in a server are on hold pending review.
+    pub fn is_held(&self, guild_id: GuildId, discord_id: UserId) -> Result<bool> {
+        Ok(self.0.database.connect()?.query(
+            "SELECT COUNT(*) FROM alt_flags \
+             WHERE discord_guild_id = ?1 AND discord_user_id = ?2 AND holds_roles = 1 \
+               AND created > ?3",
+            (guild_id, discord_id, self.expiry_cutoff(guild_id)?),
+        ).get::<u32>()? != 0)
+    }
+    pub fn list_flags(&self, guild_id: GuildId) -> Result<Vec<AltFlag>> {
+        Ok(self.0.database.connect()?.query(
+            "SELECT discord_user_id, roblox_user_id, reasons, holds_roles, created \
+             FROM alt_flags WHERE discord_guild_id = ?1 AND created > ?2 ORDER BY created",
+            (guild_id, self.expiry_cutoff(guild_id)?),
+        ).get_all::<(UserId, RobloxUserID, String, bool, SystemTime)>()?
+            .into_iter()
+            .map(|(discord_id, roblox_id, reasons, holds_roles, created)| AltFlag {
+                discord_id, roblox_id, reasons: reasons.lines().map(|x| x.to_owned()).collect(),
+                holds_roles, created,
+            })
+            .collect())
+    }
+    /// Marks a flag as reviewed, removing it. Returns whether the user had been flagged.
+    pub fn clear_flag(&self, guild_id: GuildId, discord_id: UserId) -> Result<bool> {
+        Ok(self.0.database.connect()?.execute(
+            "DELETE FROM alt_flags WHERE discord_guild_id = ?1 AND discord_user_id = ?2",
+            (guild_id, discord_id),
+        )? != 0)
+    }
+}
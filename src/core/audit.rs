@@ -0,0 +1,154 @@
+use database::*;
+use errors::*;
+use roblox::RobloxUserID;
+use serde_json::Value;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+macro_rules! audit_event_types {
+    ($($name:ident => $str:expr),* $(,)*) => {
+        #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+        pub enum AuditEventType {
+            $($name,)*
+        }
+        impl AuditEventType {
+            pub const ALL: &'static [AuditEventType] = &[$(AuditEventType::$name,)*];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(AuditEventType::$name => $str,)*
+                }
+            }
+            pub fn from_name(name: &str) -> Option<AuditEventType> {
+                match name {
+                    $($str => Some(AuditEventType::$name),)*
+                    _ => None,
+                }
+            }
+        }
+    }
+}
+audit_event_types! {
+    Verify         => "verify",
+    Reverify       => "reverify",
+    Renew          => "renew",
+    Link           => "link",
+    Unlink         => "unlink",
+    Unverify       => "unverify",
+    VerifyFailed   => "verify_failed",
+    VerifyPending  => "verify_pending",
+    Approve        => "approve",
+    Deny           => "deny",
+    ForceVerify    => "force_verify",
+    ForceUnverify  => "force_unverify",
+    Expire         => "expire",
+    RoleChange     => "role_change",
+    ConfigChange   => "config_change",
+    Forget         => "forget",
+    Import         => "import",
+}
+
+#[derive(Clone, Debug)]
+pub struct AuditEvent {
+    pub id: u64, pub event_type: AuditEventType, pub guild_id: Option<GuildId>,
+    pub actor: Option<UserId>, pub discord_id: Option<UserId>,
+    pub roblox_id: Option<RobloxUserID>, pub created: SystemTime, pub payload: Value,
+}
+
+/// Restricts the events returned by `AuditLog::query`. Fields that are `None` match any event.
+#[derive(Clone, Debug, Default)]
+pub struct AuditFilter {
+    pub guild_id: Option<GuildId>, pub discord_id: Option<UserId>,
+    pub roblox_id: Option<RobloxUserID>, pub event_type: Option<AuditEventType>,
+    pub since: Option<SystemTime>, pub until: Option<SystemTime>,
+}
+
+struct AuditLogData {
+    database: Database,
+}
+
+#[derive(Clone)]
+pub struct AuditLog(Arc<AuditLogData>);
+impl AuditLog {
+    pub(in ::core) fn new(database: Database) -> AuditLog {
+        AuditLog(Arc::new(AuditLogData { database }))
+    }
+
+    /// Records an event in the audit log. `actor` is the user who caused the event, if any.
+    ///
+    /// Failures are logged rather than returned, as they should not prevent the action being
+    /// audited from completing.
+    pub fn record(
+        &self, event_type: AuditEventType, guild_id: Option<GuildId>, actor: Option<UserId>,
+        discord_id: Option<UserId>, roblox_id: Option<RobloxUserID>, payload: Value,
+    ) {
+        let result = self.0.database.connect().and_then(|conn| conn.execute(
+            "INSERT INTO audit_events (\
+                event_type, discord_guild_id, actor_discord_id, discord_user_id, roblox_user_id, \
+                created, payload\
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            (event_type.name(), guild_id, actor, discord_id, roblox_id, SystemTime::now(),
+             payload),
+        ));
+        if let Err(e) = result {
+            warn!("Could not record {} event in the audit log: {}", event_type.name(), e);
+        }
+    }
+
+    /// Returns the events matching a filter, newest first.
+    pub fn query(
+        &self, filter: &AuditFilter, offset: u32, limit: u32,
+    ) -> Result<Vec<AuditEvent>> {
+        // Only conditions for the fields that are set are included, so the query can use the
+        // indexes on those fields.
+        let mut conditions = Vec::new();
+        if filter.guild_id.is_some() {
+            conditions.push("discord_guild_id = ?1");
+        }
+        if filter.discord_id.is_some() {
+            conditions.push("(discord_user_id = ?2 OR actor_discord_id = ?2)");
+        }
+        if filter.roblox_id.is_some() {
+            conditions.push("roblox_user_id = ?3");
+        }
+        if filter.event_type.is_some() {
+            conditions.push("event_type = ?4");
+        }
+        if filter.since.is_some() {
+            conditions.push("created >= ?5");
+        }
+        if filter.until.is_some() {
+            conditions.push("created < ?6");
+        }
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        let events = self.0.database.connect()?.query(
+            &format!("SELECT id, event_type, discord_guild_id, actor_discord_id, \
+                             discord_user_id, roblox_user_id, created, payload \
+                      FROM audit_events {} \
+                      ORDER BY id DESC LIMIT ?7 OFFSET ?8", where_clause),
+            (filter.guild_id, filter.discord_id, filter.roblox_id,
+             filter.event_type.map(|x| x.name()), filter.since, filter.until, limit, offset),
+        ).get_all::<(u64, String, Option<GuildId>, Option<UserId>, Option<UserId>,
+                     Option<RobloxUserID>, SystemTime, Value)>()?;
+        let mut result = Vec::new();
+        for (id, event_type, guild_id, actor, discord_id, roblox_id, created, payload) in events {
+            let event_type = match AuditEventType::from_name(&event_type) {
+                Some(event_type) => event_type,
+                None => {
+                    warn!("Unknown event type in audit log: {}", event_type);
+                    continue
+                }
+            };
+            result.push(AuditEvent {
+                id, event_type, guild_id, actor, discord_id, roblox_id, created, payload,
+            });
+        }
+        Ok(result)
+    }
+}
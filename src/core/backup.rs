@@ -0,0 +1,214 @@
+use byteorder::*;
+use chrono::Utc;
+use core::config::*;
+use database::{self, Database};
+use errors::*;
+use parking_lot::Mutex;
+use roblox::lz4;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+const BACKUP_PREFIX: &str = "Sylph-Verifier-";
+const BACKUP_EXTENSION: &str = ".db";
+const COMPRESSED_EXTENSION: &str = ".db.lz4";
+
+/// Compressed backups start with this magic number, followed by a series of LZ4 blocks. Each
+/// block is prefixed with its decompressed and compressed length.
+const COMPRESSED_MAGIC: &[u8; 8] = b"SYLPHLZ4";
+const COMPRESSION_CHUNK_SIZE: usize = 1024 * 1024;
+
+fn compress_file(from: &Path, to: &Path) -> Result<()> {
+    let mut input = BufReader::new(File::open(from)?);
+    let mut output = BufWriter::new(File::create(to)?);
+    output.write_all(COMPRESSED_MAGIC)?;
+    loop {
+        let mut chunk = Vec::with_capacity(COMPRESSION_CHUNK_SIZE);
+        (&mut input).take(COMPRESSION_CHUNK_SIZE as u64).read_to_end(&mut chunk)?;
+        if chunk.is_empty() { break }
+        let compressed = lz4::compress(&chunk)?;
+        output.write_u32::<LittleEndian>(chunk.len() as u32)?;
+        output.write_u32::<LittleEndian>(compressed.len() as u32)?;
+        output.write_all(&compressed)?;
+    }
+    output.flush()?;
+    Ok(())
+}
+fn decompress_file(from: &Path, to: &Path) -> Result<()> {
+    let mut input = BufReader::new(File::open(from)?);
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    ensure!(&magic == COMPRESSED_MAGIC, "'{}' is not a compressed backup.", from.display());
+
+    let mut output = BufWriter::new(File::create(to)?);
+    loop {
+        let len = match input.read_u32::<LittleEndian>() {
+            Ok(len) => len as usize,
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        };
+        let compressed_len = input.read_u32::<LittleEndian>()? as usize;
+        ensure!(len <= COMPRESSION_CHUNK_SIZE && compressed_len <= COMPRESSION_CHUNK_SIZE * 2,
+                "'{}' is corrupted.", from.display());
+        let mut compressed = vec![0; compressed_len];
+        input.read_exact(&mut compressed)?;
+        output.write_all(&lz4::decompress(&compressed, len)?)?;
+    }
+    output.flush()?;
+    Ok(())
+}
+
+fn with_extension(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(extension);
+    PathBuf::from(name)
+}
+
+/// Replaces the database at `db_path` with a backup, which may be compressed. The backup is
+/// checked for corruption first, and the database being replaced is kept alongside it with a
+/// `.before-restore` extension.
+///
+/// This must only be called while the lock file is held, and the bot is not running.
+pub fn restore_from_backup(backup: &Path, db_path: &Path) -> Result<()> {
+    ensure!(backup.exists(), "Backup file '{}' does not exist.", backup.display());
+
+    let is_compressed = backup.to_string_lossy().ends_with(COMPRESSED_EXTENSION);
+    let temp = with_extension(db_path, ".restore.tmp");
+    let result = (|| {
+        let source = if is_compressed {
+            decompress_file(backup, &temp)?;
+            temp.as_path()
+        } else {
+            backup
+        };
+        database::check_integrity(source)?;
+        if db_path.exists() {
+            database::restore_backup(db_path, with_extension(db_path, ".before-restore"))?;
+        }
+        database::restore_backup(source, db_path)
+    })();
+    if temp.exists() {
+        fs::remove_file(&temp)?;
+    }
+    result
+}
+
+/// A backup that was just written.
+#[derive(Clone, Debug)]
+pub struct BackupInfo {
+    pub path: PathBuf, pub size: u64, pub pruned: u32,
+}
+
+struct BackupManagerData {
+    config: ConfigManager, database: Database, root_path: PathBuf,
+    // Prevents scheduled and manual backups from running at the same time.
+    lock: Mutex<()>,
+}
+
+#[derive(Clone)]
+pub struct BackupManager(Arc<BackupManagerData>);
+impl BackupManager {
+    pub(in ::core) fn new(
+        config: ConfigManager, database: Database, root_path: PathBuf,
+    ) -> BackupManager {
+        BackupManager(Arc::new(BackupManagerData {
+            config, database, root_path, lock: Mutex::new(()),
+        }))
+    }
+
+    fn backup_dir(&self) -> Result<PathBuf> {
+        Ok(self.0.root_path.join(self.0.config.get(None, ConfigKeys::BackupDirectory)?))
+    }
+
+    /// Lists the backups in the backup directory, newest first.
+    pub fn list_backups(&self) -> Result<Vec<(PathBuf, SystemTime)>> {
+        let dir = self.backup_dir()?;
+        if !dir.exists() { return Ok(Vec::new()) }
+
+        let mut backups = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(BACKUP_PREFIX) &&
+                (name.ends_with(BACKUP_EXTENSION) || name.ends_with(COMPRESSED_EXTENSION)) {
+                backups.push((entry.path(), entry.metadata()?.modified()?));
+            }
+        }
+        backups.sort_by(|a, b| b.1.cmp(&a.1));
+        Ok(backups)
+    }
+
+    fn write_backup(&self, dir: &Path, name: &str, temp: &Path) -> Result<PathBuf> {
+        self.0.database.connect()?.backup_to(temp)?;
+        database::check_integrity(temp)?;
+
+        let compress = self.0.config.get(None, ConfigKeys::BackupCompression)?;
+        let extension = if compress { COMPRESSED_EXTENSION } else { BACKUP_EXTENSION };
+        let path = dir.join(format!("{}{}", name, extension));
+        ensure!(!path.exists(), "Backup file '{}' already exists.", path.display());
+        if compress {
+            compress_file(temp, &path)?;
+        } else {
+            fs::rename(temp, &path)?;
+        }
+        Ok(path)
+    }
+    fn prune_backups(&self, newest: &Path) -> Result<u32> {
+        let count = self.0.config.get(None, ConfigKeys::BackupRetentionCount)? as usize;
+        let max_age = self.0.config.get(None, ConfigKeys::BackupRetentionSeconds)?;
+        let cutoff = if max_age == 0 {
+            None
+        } else {
+            SystemTime::now().checked_sub(Duration::from_secs(max_age))
+        };
+
+        let mut pruned = 0;
+        for (i, (path, time)) in self.list_backups()?.into_iter().enumerate() {
+            if path == newest { continue }
+            let too_many = count != 0 && i >= count;
+            let too_old = cutoff.map_or(false, |cutoff| time < cutoff);
+            if too_many || too_old {
+                fs::remove_file(&path)?;
+                pruned += 1;
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// Writes a backup of the database to the backup directory, then removes backups beyond
+    /// the configured retention count or age.
+    pub fn backup(&self) -> Result<BackupInfo> {
+        let _guard = self.0.lock.lock();
+
+        let dir = self.backup_dir()?;
+        fs::create_dir_all(&dir)?;
+        let name = format!("{}{}", BACKUP_PREFIX, Utc::now().format("%Y%m%d-%H%M%S"));
+        let temp = dir.join(format!("{}.tmp", name));
+        let result = self.write_backup(&dir, &name, &temp);
+        if temp.exists() {
+            fs::remove_file(&temp)?;
+        }
+        let path = result?;
+
+        let size = fs::metadata(&path)?.len();
+        let pruned = self.prune_backups(&path)?;
+        Ok(BackupInfo { path, size, pruned })
+    }
+
+    /// Writes a backup if the newest one is older than the backup interval.
+    pub fn run_scheduled(&self) -> Result<()> {
+        let interval = self.0.config.get(None, ConfigKeys::BackupIntervalSeconds)?;
+        if interval == 0 { return Ok(()) }
+        if let Some((_, newest)) = self.list_backups()?.first() {
+            if newest.elapsed().map(|x| x.as_secs() < interval).unwrap_or(true) {
+                return Ok(())
+            }
+        }
+        let info = self.backup()?;
+        info!("Wrote scheduled backup to {} ({} bytes, {} old backups removed).",
+              info.path.display(), info.size, info.pruned);
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+use database::*;
+use errors::*;
+use roblox::RobloxUserID;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+use std::time::SystemTime;
+use util;
+
+const SCOPE_GLOBAL: u64 = 0;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum BanTarget {
+    Discord(UserId), Roblox(RobloxUserID),
+}
+impl BanTarget {
+    fn to_sql(self) -> (bool, u64) {
+        match self {
+            BanTarget::Discord(id) => (false, id.0),
+            BanTarget::Roblox(id) => (true, id.0),
+        }
+    }
+    fn from_sql(is_roblox: bool, id: u64) -> BanTarget {
+        if is_roblox {
+            BanTarget::Roblox(RobloxUserID(id))
+        } else {
+            BanTarget::Discord(UserId(id))
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BanEntry {
+    pub guild_id: Option<GuildId>, pub target: BanTarget, pub reason: String,
+    pub author: Option<UserId>, pub created: SystemTime, pub expires: Option<SystemTime>,
+}
+impl BanEntry {
+    /// Describes the ban for messages shown to users, e.g. "banned from verifying in this
+    /// server (reason: ...)".
+    pub fn describe(&self) -> String {
+        let scope = if self.guild_id.is_some() { "in this server" } else { "with this bot" };
+        match self.expires {
+            Some(expires) =>
+                format!("banned from verifying {} for {} (reason: {})",
+                        scope, util::english_time_diff(SystemTime::now(), expires), self.reason),
+            None => format!("banned from verifying {} (reason: {})", scope, self.reason),
+        }
+    }
+}
+
+fn scope_to_sql(guild_id: Option<GuildId>) -> u64 {
+    guild_id.map_or(SCOPE_GLOBAL, |x| x.0)
+}
+
+struct BanManagerData {
+    database: Database,
+}
+
+#[derive(Clone)]
+pub struct BanManager(Arc<BanManagerData>);
+impl BanManager {
+    pub(in ::core) fn new(database: Database) -> BanManager {
+        BanManager(Arc::new(BanManagerData { database }))
+    }
+
+    /// Adds an entry to a server's ban list, or the global ban list if no server is given.
+    /// Any existing entry for the account is replaced.
+    pub fn add(
+        &self, guild_id: Option<GuildId>, target: BanTarget, reason: &str,
+        author: Option<UserId>, expires: Option<SystemTime>,
+    ) -> Result<()> {
+        let (is_roblox, target_id) = target.to_sql();
+        self.0.database.connect()?.execute(
+            "REPLACE INTO ban_list (\
+                discord_guild_id, is_roblox, target_id, reason, author_discord_id, created, \
+                expires\
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            (scope_to_sql(guild_id), is_roblox, target_id, reason, author, SystemTime::now(),
+             expires),
+        )?;
+        Ok(())
+    }
+    /// Removes an entry from a ban list, returning whether it existed.
+    pub fn remove(&self, guild_id: Option<GuildId>, target: BanTarget) -> Result<bool> {
+        let (is_roblox, target_id) = target.to_sql();
+        let removed = self.0.database.connect()?.execute(
+            "DELETE FROM ban_list \
+             WHERE discord_guild_id = ?1 AND is_roblox = ?2 AND target_id = ?3",
+            (scope_to_sql(guild_id), is_roblox, target_id),
+        )?;
+        Ok(removed != 0)
+    }
+    pub fn list(&self, guild_id: Option<GuildId>) -> Result<Vec<BanEntry>> {
+        Ok(self.0.database.connect()?.query(
+            "SELECT is_roblox, target_id, reason, author_discord_id, created, expires \
+             FROM ban_list \
+             WHERE discord_guild_id = ?1 AND (expires IS NULL OR expires > ?2) \
+             ORDER BY created", (scope_to_sql(guild_id), SystemTime::now()),
+        ).get_all::<(bool, u64, String, Option<UserId>, SystemTime, Option<SystemTime>)>()?
+            .into_iter()
+            .map(|(is_roblox, target_id, reason, author, created, expires)| BanEntry {
+                guild_id, target: BanTarget::from_sql(is_roblox, target_id),
+                reason, author, created, expires,
+            })
+            .collect())
+    }
+
+    /// Finds an active ban for an account, checking both the server's ban list and the global
+    /// ban list.
+    pub fn find(&self, guild_id: Option<GuildId>, target: BanTarget) -> Result<Option<BanEntry>> {
+        let (is_roblox, target_id) = target.to_sql();
+        let ban = self.0.database.connect()?.query(
+            "SELECT discord_guild_id, reason, author_discord_id, created, expires \
+             FROM ban_list \
+             WHERE (discord_guild_id = ?1 OR discord_guild_id = ?2) \
+               AND is_roblox = ?3 AND target_id = ?4 \
+               AND (expires IS NULL OR expires > ?5) \
+             ORDER BY discord_guild_id = ?1 DESC LIMIT 1",
+            (SCOPE_GLOBAL, scope_to_sql(guild_id), is_roblox, target_id, SystemTime::now()),
+        ).get_opt::<(u64, String, Option<UserId>, SystemTime, Option<SystemTime>)>()?;
+        Ok(ban.map(|(scope, reason, author, created, expires)| BanEntry {
+            guild_id: if scope == SCOPE_GLOBAL { None } else { Some(GuildId(scope)) },
+            target, reason, author, created, expires,
+        }))
+    }
+    pub fn is_banned(&self, guild_id: Option<GuildId>, target: BanTarget) -> Result<bool> {
+        Ok(self.find(guild_id, target)?.is_some())
+    }
+
+    pub fn on_cleanup_tick(&self) {
+        let result = self.0.database.connect().and_then(|conn| conn.execute(
+            "DELETE FROM ban_list WHERE expires IS NOT NULL AND expires <= ?1", SystemTime::now(),
+        ));
+        if let Err(e) = result {
+            warn!("Could not remove expired bans: {}", e);
+        }
+    }
+}
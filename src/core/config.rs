@@ -4,6 +4,7 @@ use errors::*;
 use parking_lot::RwLock;
 use serenity::model::prelude::GuildId;
 use std::any::{Any, TypeId};
+use std::collections::BTreeMap;
 use std::marker::PhantomData;
 use std::mem;
 use std::ops::{Deref, DerefMut};
@@ -207,6 +208,7 @@ config_keys! {
 
     // Role management settings
     SetNickname<bool>(true);
+    MatchAnyLinkedAccount<bool>(false);
     SetRolesOnJoin<bool>(false);
     EnableAutoUpdate<bool>(false);
     EnableAutoUpdateUnverified<bool>(false);
@@ -215,16 +217,39 @@ config_keys! {
     UpdateCooldownSeconds<u64>(60 * 60);
     AutoUpdateCooldownSeconds<u64>(60 * 60 * 24);
 
+    // Web server settings
+    WebServerAddress<Option<String>>(None, |guild, core| {
+        core.refresh_place()?;
+        core.verify_channel().update(guild)
+    });
+    WebServerPublicUrl<Option<String>>(None, |guild, core| {
+        core.refresh_place()?;
+        core.verify_channel().update(guild)
+    });
+
+    // Roblox OAuth settings
+    OAuthIssuerUrl<String>("https://apis.roblox.com/oauth/".to_owned());
+    OAuthClientId<Option<String>>(None, |guild, core| core.verify_channel().update(guild));
+    OAuthClientSecret<Option<String>>(None, |guild, core| core.verify_channel().update(guild));
+
     // Verification place settings
     PlaceUITitle<String>("Roblox Account Verifier".to_owned(), |_, core| core.refresh_place());
     PlaceUIInstructions<String>(
         "To verify your Roblox account, please enter the following command into the \
          verification channel:".to_owned(),
         |_, core| core.refresh_place());
+    PlaceUITitleLocales<BTreeMap<String, String>>(BTreeMap::new(),
+        |_, core| core.refresh_place());
+    PlaceUIInstructionsLocales<BTreeMap<String, String>>(BTreeMap::new(),
+        |_, core| core.refresh_place());
     PlaceUIBackground<Option<String>>(None, |_, core| core.refresh_place());
     PlaceID<Option<u64>>(None, |guild, core| core.verify_channel().update(guild));
 
     // Verification settings
+    AllowPlaceVerification<bool>(true, |guild, core| core.verify_channel().update(guild));
+    AllowProfileVerification<bool>(true, |guild, core| core.verify_channel().update(guild));
+    AllowOAuthVerification<bool>(true, |guild, core| core.verify_channel().update(guild));
+    AllowPushVerification<bool>(true, |guild, core| core.verify_channel().update(guild));
     VerificationAttemptLimit<u32>(10);
     VerificationCooldownSeconds<u64>(60 * 60 * 24);
 
@@ -239,12 +264,63 @@ config_keys! {
         core.refresh_place()?;
         Ok(())
     });
+    TokenLength<u32>(8, |_, core| {
+        core.verifier().rekey(false)?;
+        core.refresh_place()?;
+        Ok(())
+    });
+    TokenExcludeConfusable<bool>(true, |_, core| {
+        core.verifier().rekey(false)?;
+        core.refresh_place()?;
+        Ok(())
+    });
+    TokenRotationSeconds<u64>(0, |_, core| core.check_key_rotation());
+    TokenRotationLeadSeconds<u64>(60 * 60 * 24);
+    TokenGracePeriodSeconds<u64>(60 * 60);
 
     AllowReverifyDiscord<bool>(false);
     AllowReverifyRoblox<bool>(false);
     ReverificationCooldownSeconds<u64>(60 * 60 * 24);
+    MaxLinkedAccounts<u32>(5);
+
+    // Verification expiry settings
+    VerificationMaxAgeSeconds<u64>(0);
+    VerificationExpiryWarningSeconds<u64>(60 * 60 * 24 * 7);
+
+    // Alt account detection settings
+    AltDetectionChannel<Option<u64>>(None);
+    AltDetectionDiscordAccounts<u32>(3);
+    AltDetectionRobloxAccounts<u32>(0);
+    AltDetectionWindowSeconds<u64>(60 * 60 * 24 * 30);
+    AltDetectionHoldRoles<bool>(false);
+    AltFlagExpirySeconds<u64>(60 * 60 * 24 * 30);
+
+    // Verification approval settings
+    RequireVerificationApproval<bool>(false);
+    VerificationReviewChannel<Option<u64>>(None);
+    VerificationApprovalSeconds<u64>(60 * 60 * 24 * 3);
+    VerificationApprovalReactions<bool>(true);
+
+    // Import settings
+    TrustImportedVerifications<bool>(false);
 
     GlobalVerificationLogChannel<Option<u64>>(None);
+    VerificationLogChannel<Option<u64>>(None);
+    VerificationLogEvents<String>("verify,failure,reverify,unverify,legacy_unverify".to_owned());
+
+    // Data retention settings
+    UserHistoryRetentionSeconds<u64>(0);
+    VerificationCooldownRetentionSeconds<u64>(60 * 60 * 24 * 30);
+    RolesLastUpdatedRetentionSeconds<u64>(60 * 60 * 24 * 90);
+    AuditLogRetentionSeconds<u64>(0);
+    GuildDataGracePeriodSeconds<u64>(60 * 60 * 24 * 30);
+
+    // Backup settings
+    BackupDirectory<String>("backups".to_owned());
+    BackupIntervalSeconds<u64>(60 * 60 * 24);
+    BackupCompression<bool>(true);
+    BackupRetentionCount<u32>(14);
+    BackupRetentionSeconds<u64>(60 * 60 * 24 * 30);
 
     // Internal settings for debugging. These aren't configurable via commands.
     ProductionMode<bool>(true);
@@ -0,0 +1,322 @@
+use core::permissions::*;
+use core::retention::GUILD_TARGETS;
+use core::roles::RoleManager;
+use database::*;
+use errors::*;
+use roblox::RobloxUserID;
+use serenity;
+use serenity::model::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// A kind of inconsistency found in the database.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum IssueCategory {
+    PrimaryNotLinked, LinkedWithoutPrimary, MissingRobloxInfo, HistoryMismatch,
+    DeletedRoleRules, DeletedRolePermissions, InvalidPermissionScopes, DepartedGuildData,
+}
+impl IssueCategory {
+    pub const ALL: &'static [IssueCategory] = &[
+        IssueCategory::PrimaryNotLinked, IssueCategory::LinkedWithoutPrimary,
+        IssueCategory::MissingRobloxInfo, IssueCategory::HistoryMismatch,
+        IssueCategory::DeletedRoleRules, IssueCategory::DeletedRolePermissions,
+        IssueCategory::InvalidPermissionScopes, IssueCategory::DepartedGuildData,
+    ];
+
+    pub fn description(self) -> &'static str {
+        match self {
+            IssueCategory::PrimaryNotLinked =>
+                "Primary Roblox accounts missing from the user's linked accounts",
+            IssueCategory::LinkedWithoutPrimary =>
+                "Linked Roblox accounts belonging to users with no primary account",
+            IssueCategory::MissingRobloxInfo =>
+                "Verified Roblox accounts with no entry in `roblox_user_info`",
+            IssueCategory::HistoryMismatch =>
+                "Linked Roblox accounts whose latest history entry is an unverification",
+            IssueCategory::DeletedRoleRules =>
+                "Verification rules assigned to roles that no longer exist",
+            IssueCategory::DeletedRolePermissions =>
+                "Permissions granted to roles that no longer exist",
+            IssueCategory::InvalidPermissionScopes =>
+                "Permissions granted to unknown scopes",
+            IssueCategory::DepartedGuildData =>
+                "Servers the bot has left, whose data is not scheduled for deletion",
+        }
+    }
+}
+
+enum Repair {
+    /// Links a user's primary account to them, as nobody else has linked it.
+    LinkPrimary(UserId, RobloxUserID),
+    /// Adds the missing `roblox_user_info` entry for a verified account.
+    AddRobloxInfo(RobloxUserID),
+    DeleteActiveRule(GuildId, String),
+    DeleteScope(Scope),
+    DeleteInvalidScopes,
+    MarkDeparted(GuildId),
+}
+
+pub struct ConsistencyIssue {
+    pub category: IssueCategory, pub description: String, repair: Option<Repair>,
+}
+impl ConsistencyIssue {
+    pub fn is_fixable(&self) -> bool {
+        self.repair.is_some()
+    }
+}
+
+/// The result of checking the database. Checks that depend on Discord's cache are skipped
+/// while the bot is disconnected, or still loading its servers.
+pub struct ConsistencyReport {
+    pub issues: Vec<ConsistencyIssue>, pub skipped_guild_checks: bool, pub fixed: u32,
+}
+
+fn check_links(conn: &DatabaseConnection, issues: &mut Vec<ConsistencyIssue>) -> Result<()> {
+    // A primary account linked to somebody else can't be repaired automatically, as there is
+    // no way to tell which of the two users rightfully owns it.
+    for (discord_id, roblox_id, owner) in conn.query(
+        "SELECT d.discord_user_id, d.roblox_user_id, l.discord_user_id \
+         FROM discord_user_info d \
+         LEFT JOIN discord_linked_accounts l ON l.roblox_user_id = d.roblox_user_id \
+         WHERE d.roblox_user_id IS NOT NULL \
+           AND (l.discord_user_id IS NULL OR l.discord_user_id != d.discord_user_id)", (),
+    ).get_all::<(UserId, RobloxUserID, Option<UserId>)>()? {
+        let (description, repair) = match owner {
+            None => (format!("Discord user {}'s primary account, Roblox uid #{}, is not linked.",
+                             discord_id.0, roblox_id.0),
+                     Some(Repair::LinkPrimary(discord_id, roblox_id))),
+            Some(owner) => (format!("Discord user {}'s primary account, Roblox uid #{}, is \
+                                     linked to Discord user {}.",
+                                    discord_id.0, roblox_id.0, owner.0),
+                            None),
+        };
+        issues.push(ConsistencyIssue {
+            category: IssueCategory::PrimaryNotLinked, description, repair,
+        });
+    }
+
+    // Every verified account has had an entry in `roblox_user_info` since schema version 8.
+    for roblox_id in conn.query(
+        "SELECT roblox_user_id FROM discord_user_info WHERE roblox_user_id IS NOT NULL \
+         UNION SELECT roblox_user_id FROM discord_linked_accounts \
+         EXCEPT SELECT roblox_user_id FROM roblox_user_info", (),
+    ).get_all::<RobloxUserID>()? {
+        issues.push(ConsistencyIssue {
+            category: IssueCategory::MissingRobloxInfo,
+            description: format!("Roblox uid #{} is verified, but has no entry in \
+                                  `roblox_user_info`.", roblox_id.0),
+            repair: Some(Repair::AddRobloxInfo(roblox_id)),
+        });
+    }
+
+    for (discord_id, roblox_id) in conn.query(
+        "SELECT l.discord_user_id, l.roblox_user_id FROM discord_linked_accounts l \
+         LEFT JOIN discord_user_info d ON d.discord_user_id = l.discord_user_id \
+         WHERE d.roblox_user_id IS NULL", (),
+    ).get_all::<(UserId, RobloxUserID)>()? {
+        issues.push(ConsistencyIssue {
+            category: IssueCategory::LinkedWithoutPrimary,
+            description: format!("Roblox uid #{} is linked to Discord user {}, who is not \
+                                  verified.", roblox_id.0, discord_id.0),
+            repair: None,
+        });
+    }
+
+    // History may have been pruned, and taking over another user's account does not record an
+    // unverification for them, so only the latest entry of accounts still linked is checked.
+    // Denied and expired approval requests are recorded as unverifications of an account that
+    // may already be linked, so they are ignored.
+    for (discord_id, roblox_id) in conn.query(
+        "SELECT l.discord_user_id, l.roblox_user_id FROM discord_linked_accounts l \
+         WHERE (\
+             SELECT is_unverify FROM user_history h \
+             WHERE h.discord_user_id = l.discord_user_id AND h.roblox_user_id = l.roblox_user_id \
+               AND h.note IS NOT 'verification denied' AND h.note IS NOT 'approval expired' \
+             ORDER BY h.last_updated DESC, h.rowid DESC LIMIT 1\
+         ) = 1", (),
+    ).get_all::<(UserId, RobloxUserID)>()? {
+        issues.push(ConsistencyIssue {
+            category: IssueCategory::HistoryMismatch,
+            description: format!("Roblox uid #{} is linked to Discord user {}, but their \
+                                  history shows it as unverified.", roblox_id.0, discord_id.0),
+            repair: None,
+        });
+    }
+
+    let invalid_scopes = conn.query(
+        &format!("SELECT COUNT(*) FROM permissions WHERE {}", invalid_scopes_sql()), (),
+    ).get::<u64>()?;
+    if invalid_scopes != 0 {
+        issues.push(ConsistencyIssue {
+            category: IssueCategory::InvalidPermissionScopes,
+            description: format!("{} permission row(s) do not belong to any scope.",
+                                 invalid_scopes),
+            repair: Some(Repair::DeleteInvalidScopes),
+        });
+    }
+
+    Ok(())
+}
+
+fn check_guilds(conn: &DatabaseConnection, issues: &mut Vec<ConsistencyIssue>) -> Result<bool> {
+    let guild_roles: HashMap<GuildId, HashSet<RoleId>> = {
+        let cache = serenity::CACHE.read();
+        if cache.guilds.is_empty() || !cache.unavailable_guilds.is_empty() {
+            return Ok(false)
+        }
+        cache.guilds.iter()
+            .map(|(id, guild)| (*id, guild.read().roles.keys().cloned().collect()))
+            .collect()
+    };
+    let role_exists = |guild_id: GuildId, role_id: RoleId| match guild_roles.get(&guild_id) {
+        Some(roles) => roles.contains(&role_id),
+        // Servers the bot has left are reported separately.
+        None => true,
+    };
+
+    for (guild_id, rule_name, role_id) in conn.query(
+        "SELECT discord_guild_id, rule_name, discord_role_id FROM guild_active_rules", (),
+    ).get_all::<(GuildId, String, RoleId)>()? {
+        if !role_exists(guild_id, role_id) {
+            issues.push(ConsistencyIssue {
+                category: IssueCategory::DeletedRoleRules,
+                description: format!("Rule `{}` in server {} is assigned to deleted role {}.",
+                                     rule_name, guild_id.0, role_id.0),
+                repair: Some(Repair::DeleteActiveRule(guild_id, rule_name)),
+            });
+        }
+    }
+
+    for (guild_id, role_id) in get_role_scopes(conn)? {
+        if !role_exists(guild_id, role_id) {
+            issues.push(ConsistencyIssue {
+                category: IssueCategory::DeletedRolePermissions,
+                description: format!("Deleted role {} in server {} has permissions.",
+                                     role_id.0, guild_id.0),
+                repair: Some(Repair::DeleteScope(Scope::GuildRole(guild_id, role_id))),
+            });
+        }
+    }
+
+    let mut departed = HashSet::new();
+    for &(table, _) in GUILD_TARGETS {
+        for guild_id in conn.query(
+            &format!("SELECT DISTINCT discord_guild_id FROM {} \
+                      WHERE discord_guild_id NOT IN (SELECT discord_guild_id FROM departed_guilds)",
+                     table), (),
+        ).get_all::<GuildId>()? {
+            // The global ban list is stored under server id 0.
+            if guild_id.0 != 0 && !guild_roles.contains_key(&guild_id) {
+                departed.insert(guild_id);
+            }
+        }
+    }
+    let mut departed: Vec<_> = departed.into_iter().collect();
+    departed.sort();
+    for guild_id in departed {
+        issues.push(ConsistencyIssue {
+            category: IssueCategory::DepartedGuildData,
+            description: format!("The bot is no longer in server {}, but its data has not been \
+                                  scheduled for deletion.", guild_id.0),
+            repair: Some(Repair::MarkDeparted(guild_id)),
+        });
+    }
+
+    Ok(true)
+}
+
+struct ConsistencyCheckerData {
+    database: Database, roles: RoleManager, permissions: PermissionManager,
+}
+
+#[derive(Clone)]
+pub struct ConsistencyChecker(Arc<ConsistencyCheckerData>);
+impl ConsistencyChecker {
+    pub(in ::core) fn new(
+        database: Database, roles: RoleManager, permissions: PermissionManager,
+    ) -> ConsistencyChecker {
+        ConsistencyChecker(Arc::new(ConsistencyCheckerData { database, roles, permissions }))
+    }
+
+    fn apply_repair(&self, conn: &DatabaseConnection, repair: &Repair) -> Result<()> {
+        match *repair {
+            Repair::LinkPrimary(discord_id, roblox_id) => {
+                conn.execute(
+                    "INSERT OR IGNORE INTO discord_linked_accounts (\
+                         roblox_user_id, discord_user_id, last_updated\
+                     ) VALUES (?1, ?2, ?3)", (roblox_id, discord_id, SystemTime::now()),
+                )?;
+            }
+            Repair::AddRobloxInfo(roblox_id) => {
+                conn.execute(
+                    "INSERT OR IGNORE INTO roblox_user_info (roblox_user_id, last_updated) \
+                     VALUES (?1, ?2)", (roblox_id, SystemTime::now()),
+                )?;
+            }
+            Repair::DeleteActiveRule(guild_id, ref rule_name) => {
+                conn.execute(
+                    "DELETE FROM guild_active_rules \
+                     WHERE discord_guild_id = ?1 AND rule_name = ?2", (guild_id, rule_name),
+                )?;
+            }
+            Repair::DeleteScope(scope) => self.0.permissions.delete_scope(conn, scope)?,
+            Repair::DeleteInvalidScopes => {
+                conn.execute(&format!("DELETE FROM permissions WHERE {}", invalid_scopes_sql()),
+                             ())?;
+            }
+            Repair::MarkDeparted(guild_id) => {
+                conn.execute(
+                    "INSERT OR IGNORE INTO departed_guilds (discord_guild_id, departed) \
+                     VALUES (?1, ?2)", (guild_id, SystemTime::now()),
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn run_checks(&self, conn: &DatabaseConnection, fix: bool) -> Result<ConsistencyReport> {
+        let mut issues = Vec::new();
+        check_links(conn, &mut issues)?;
+        let checked_guilds = check_guilds(conn, &mut issues)?;
+
+        let mut fixed = 0;
+        if fix {
+            for repair in issues.iter().filter_map(|x| x.repair.as_ref()) {
+                self.apply_repair(conn, repair)?;
+                fixed += 1;
+            }
+        }
+        Ok(ConsistencyReport { issues, skipped_guild_checks: !checked_guilds, fixed })
+    }
+
+    /// Scans the database for orphaned rows and inconsistencies. If `fix` is set, every issue
+    /// with a safe repair is repaired in a single transaction.
+    pub fn check(&self, fix: bool) -> Result<ConsistencyReport> {
+        let conn = self.0.database.connect()?;
+        if !fix {
+            return conn.transaction(|| self.run_checks(&conn, false))
+        }
+
+        let report = conn.transaction_immediate(|| self.run_checks(&conn, true))?;
+        let mut guilds = HashSet::new();
+        let mut linked = Vec::new();
+        for issue in &report.issues {
+            match issue.repair {
+                Some(Repair::DeleteActiveRule(guild_id, _)) => {
+                    guilds.insert(guild_id);
+                }
+                Some(Repair::LinkPrimary(discord_id, roblox_id)) =>
+                    linked.push((discord_id, roblox_id)),
+                _ => { }
+            }
+        }
+        for guild_id in guilds {
+            self.0.roles.refresh_cache(guild_id)?;
+        }
+        for (discord_id, roblox_id) in linked {
+            self.0.roles.assign_roles_all(discord_id, Some(roblox_id), None)?;
+        }
+        Ok(report)
+    }
+}
@@ -188,7 +188,8 @@ impl EventHandler for Handler {
         if !self.printed_url.compare_and_swap(false, true, Ordering::Relaxed) {
             let permissions = Permissions::MANAGE_ROLES | Permissions::MANAGE_NICKNAMES |
                               Permissions::READ_MESSAGES | Permissions::SEND_MESSAGES |
-                              Permissions::MANAGE_MESSAGES | Permissions::READ_MESSAGE_HISTORY;
+                              Permissions::MANAGE_MESSAGES | Permissions::READ_MESSAGE_HISTORY |
+                              Permissions::ADD_REACTIONS;
             info!("Add bot link: \
                    https://discordapp.com/oauth2/authorize?client_id={}&permissions={}&scope=bot",
                   ready.user.id, permissions.bits());
@@ -197,6 +198,10 @@ impl EventHandler for Handler {
         error_report::catch_error(||
             self.shared.verify_channel.check_verification_channels_ready(&ready)
         ).ok();
+
+        let shard = ready.shard;
+        let guilds: Vec<_> = ready.guilds.iter().map(|guild| guild.id()).collect();
+        self.shared.tasks.dispatch_task(move |core| core.retention().on_ready(shard, &guilds));
     }
 
     fn message(&self, ctx: Context, message: Message) {
@@ -256,13 +261,21 @@ impl EventHandler for Handler {
         );
     }
 
+    fn reaction_add(&self, _: Context, reaction: Reaction) {
+        self.shared.tasks.dispatch_task(move |core| review_reaction(core, &reaction));
+    }
+
     fn guild_create(&self, _: Context, guild: Guild, _: bool) {
         error_report::catch_error(||
             self.shared.verify_channel.check_guild_create(guild.id)
         ).ok();
+        let guild_id = guild.id;
+        self.shared.tasks.dispatch_task(move |core| core.retention().on_guild_create(guild_id));
     }
     fn guild_delete(&self, _: Context, guild: PartialGuild, _: Option<Arc<RwLock<Guild>>>) {
         self.on_guild_remove(guild.id);
+        let guild_id = guild.id;
+        self.shared.tasks.dispatch_task(move |core| core.retention().on_guild_delete(guild_id));
     }
     fn guild_unavailable(&self, _: Context, guild_id: GuildId) {
         self.on_guild_remove(guild_id);
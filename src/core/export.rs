@@ -0,0 +1,314 @@
+use chrono::{NaiveDate, TimeZone, Utc};
+use database::*;
+use errors::*;
+use serde_json::{self, Map, Value as JsonValue};
+use serenity::model::prelude::*;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use util;
+
+/// A table, or group of tables, that can be exported.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ExportDataset {
+    Users, History, Rules, Config,
+}
+impl ExportDataset {
+    pub const ALL: &'static [ExportDataset] = &[
+        ExportDataset::Users, ExportDataset::History, ExportDataset::Rules, ExportDataset::Config,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ExportDataset::Users => "users",
+            ExportDataset::History => "history",
+            ExportDataset::Rules => "rules",
+            ExportDataset::Config => "config",
+        }
+    }
+    pub fn from_name(name: &str) -> Option<ExportDataset> {
+        ExportDataset::ALL.iter().cloned().find(|x| x.name() == name)
+    }
+
+    // The filters are added to the `WHERE` clause by `ExportOptions::query`.
+    fn sql(self) -> &'static str {
+        match self {
+            ExportDataset::Users =>
+                "SELECT discord_user_id, roblox_user_id, last_updated FROM discord_user_info",
+            ExportDataset::History =>
+                "SELECT discord_user_id, roblox_user_id, is_unverify, last_updated, \
+                        actor_discord_id, note \
+                 FROM user_history",
+            ExportDataset::Rules =>
+                "SELECT r.discord_guild_id, r.rule_name, a.discord_role_id, c.condition, \
+                        r.last_updated \
+                 FROM (\
+                     SELECT discord_guild_id, rule_name, MAX(last_updated) AS last_updated \
+                     FROM (\
+                         SELECT discord_guild_id, rule_name, last_updated \
+                         FROM guild_active_rules \
+                         UNION ALL \
+                         SELECT discord_guild_id, rule_name, last_updated \
+                         FROM guild_custom_rules\
+                     ) GROUP BY discord_guild_id, rule_name\
+                 ) r \
+                 LEFT JOIN guild_active_rules a \
+                     ON a.discord_guild_id = r.discord_guild_id AND a.rule_name = r.rule_name \
+                 LEFT JOIN guild_custom_rules c \
+                     ON c.discord_guild_id = r.discord_guild_id AND c.rule_name = r.rule_name",
+            ExportDataset::Config => "SELECT discord_guild_id, key, value FROM guild_config",
+        }
+    }
+    fn order_by(self) -> &'static str {
+        match self {
+            ExportDataset::Users => "discord_user_id",
+            ExportDataset::History => "last_updated",
+            ExportDataset::Rules => "r.discord_guild_id, r.rule_name",
+            ExportDataset::Config => "discord_guild_id, key",
+        }
+    }
+    /// The column the server filter applies to. Users and history are not stored per server,
+    /// so they are instead filtered by the server's member list.
+    fn guild_column(self) -> Option<&'static str> {
+        match self {
+            ExportDataset::Users | ExportDataset::History => None,
+            ExportDataset::Rules => Some("r.discord_guild_id"),
+            ExportDataset::Config => Some("discord_guild_id"),
+        }
+    }
+    /// The column the date range applies to. Configuration has no timestamps, so the date range
+    /// is ignored for it.
+    fn date_column(self) -> Option<&'static str> {
+        match self {
+            ExportDataset::Users | ExportDataset::History => Some("last_updated"),
+            ExportDataset::Rules => Some("r.last_updated"),
+            ExportDataset::Config => None,
+        }
+    }
+    fn columns(self) -> &'static [&'static str] {
+        match self {
+            ExportDataset::Users => &["discord_user_id", "roblox_user_id", "last_updated"],
+            ExportDataset::History => &["discord_user_id", "roblox_user_id", "is_unverify",
+                                        "last_updated", "actor_discord_id", "note"],
+            ExportDataset::Rules => &["discord_guild_id", "rule_name", "discord_role_id",
+                                      "condition", "last_updated"],
+            ExportDataset::Config => &["discord_guild_id", "key", "value"],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ExportFormat {
+    Csv, NdJson,
+}
+impl ExportFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::NdJson => "ndjson",
+        }
+    }
+}
+
+/// The options for an export, as given to the `export_data` command or the `export` command
+/// line mode.
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    pub datasets: Vec<ExportDataset>, pub format: ExportFormat, pub output: PathBuf,
+    pub guild_id: Option<GuildId>, pub since: Option<SystemTime>, pub until: Option<SystemTime>,
+}
+impl ExportOptions {
+    pub const USAGE: &'static str =
+        "<users|history|rules|config|all>[,...] <csv|ndjson> <output directory> \
+         [guild:<id>] [since:<YYYY-MM-DD>] [until:<YYYY-MM-DD>]";
+
+    fn parse_date(date: &str) -> Result<SystemTime> {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+            .to_cmd_err(|| "Dates must look like `2020-01-31`.")?;
+        Ok(Utc.from_utc_date(&date).and_hms(0, 0, 0).into())
+    }
+    pub fn parse(args: &[&str]) -> Result<ExportOptions> {
+        cmd_ensure!(args.len() >= 3, "Usage: {}", ExportOptions::USAGE);
+
+        let mut datasets = Vec::new();
+        for name in args[0].split(',') {
+            let found = if name == "all" {
+                ExportDataset::ALL.to_vec()
+            } else {
+                vec![ExportDataset::from_name(name)
+                    .to_cmd_err(|| format!("Unknown dataset: {}", name))?]
+            };
+            for dataset in found {
+                if !datasets.contains(&dataset) {
+                    datasets.push(dataset);
+                }
+            }
+        }
+        let format = match args[1] {
+            "csv" => ExportFormat::Csv,
+            "ndjson" | "json" => ExportFormat::NdJson,
+            _ => cmd_error!("The format must be `csv` or `ndjson`."),
+        };
+        let mut options = ExportOptions {
+            datasets, format, output: PathBuf::from(args[2]),
+            guild_id: None, since: None, until: None,
+        };
+
+        for arg in &args[3..] {
+            let mut split = arg.splitn(2, ':');
+            match (split.next(), split.next()) {
+                (Some("guild"), Some(id)) => options.guild_id = Some(GuildId(
+                    id.parse().to_cmd_err(|| "Could not parse server id.")?
+                )),
+                (Some("since"), Some(date)) => options.since = Some(Self::parse_date(date)?),
+                (Some("until"), Some(date)) => options.until = Some(Self::parse_date(date)?),
+                _ => cmd_error!("Unknown filter: {}", arg),
+            }
+        }
+        Ok(options)
+    }
+
+    /// Builds the query for a dataset, binding only the filters that apply to it.
+    fn query(&self, dataset: ExportDataset) -> (String, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut args = Vec::new();
+        {
+            let mut add_condition = |column: &str, op: &str, value: Value| {
+                args.push(value);
+                conditions.push(format!("{} {} ?{}", column, op, args.len()));
+            };
+            if let (Some(column), Some(guild_id)) = (dataset.guild_column(), self.guild_id) {
+                add_condition(column, "=", Value::Integer(guild_id.0 as i64));
+            }
+            if let Some(column) = dataset.date_column() {
+                if let Some(since) = self.since {
+                    add_condition(column, ">=", Value::Integer(util::time_to_i64(since)));
+                }
+                if let Some(until) = self.until {
+                    add_condition(column, "<", Value::Integer(util::time_to_i64(until)));
+                }
+            }
+        }
+
+        let mut sql = dataset.sql().to_string();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY ");
+        sql.push_str(dataset.order_by());
+        (sql, args)
+    }
+}
+
+/// Returns the members of a server, used to filter the users and history datasets.
+///
+/// These are read from Discord's cache when the bot is connected to the server. Otherwise, such
+/// as in the `export` command line mode, the users whose roles were updated in the server are
+/// used instead. This may miss users whose role updates have been pruned.
+fn guild_members(conn: &DatabaseConnection, guild_id: GuildId) -> Result<HashSet<UserId>> {
+    if let Some(guild) = guild_id.to_guild_cached() {
+        let members = guild.read().members.keys().cloned().collect();
+        return Ok(members)
+    }
+    Ok(conn.query(
+        "SELECT DISTINCT discord_user_id FROM roles_last_updated WHERE discord_guild_id = ?1",
+        guild_id,
+    ).get_all::<UserId>()?.into_iter().collect())
+}
+
+fn json_value(value: Value) -> JsonValue {
+    match value {
+        Value::Null => JsonValue::Null,
+        Value::Integer(i) => i.into(),
+        Value::Real(f) => f.into(),
+        Value::Text(s) => s.into(),
+        Value::Blob(b) => util::to_hex(&b).into(),
+    }
+}
+fn csv_field(value: Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        Value::Integer(i) => return i.to_string(),
+        Value::Real(f) => return f.to_string(),
+        Value::Text(s) => s,
+        Value::Blob(b) => return util::to_hex(&b),
+    };
+    if text.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+fn export_dataset(
+    conn: &DatabaseConnection, dataset: ExportDataset, options: &ExportOptions,
+    members: Option<&HashSet<UserId>>, path: &Path,
+) -> Result<u64> {
+    let columns = dataset.columns();
+    let mut out = BufWriter::new(File::create(path)?);
+    if options.format == ExportFormat::Csv {
+        writeln!(out, "{}", columns.join(","))?;
+    }
+
+    let mut count = 0;
+    let members = if dataset.guild_column().is_none() { members } else { None };
+    let (sql, args) = options.query(dataset);
+    conn.query(&sql, args).for_each(|row| {
+        if let Some(members) = members {
+            if !members.contains(&row.get::<UserId>(0)?) {
+                return Ok(())
+            }
+        }
+        match options.format {
+            ExportFormat::Csv => {
+                let mut fields = Vec::new();
+                for i in 0..row.len() {
+                    fields.push(csv_field(row.get(i)?));
+                }
+                writeln!(out, "{}", fields.join(","))?;
+            }
+            ExportFormat::NdJson => {
+                let mut object = Map::new();
+                for (i, column) in columns.iter().enumerate() {
+                    object.insert(column.to_string(), json_value(row.get(i)?));
+                }
+                serde_json::to_writer(&mut out, &object)?;
+                writeln!(out)?;
+            }
+        }
+        count += 1;
+        Ok(())
+    })?;
+    out.flush()?;
+    Ok(count)
+}
+
+/// Writes each dataset to a file in the output directory. Every dataset is read within the same
+/// transaction, so the files are consistent with each other. As the database uses WAL mode,
+/// this does not block writes from the bot.
+///
+/// Returns the path and row count of each file written.
+pub fn export_data(
+    database: &Database, options: &ExportOptions,
+) -> Result<Vec<(PathBuf, u64)>> {
+    fs::create_dir_all(&options.output)?;
+    let conn = database.connect()?;
+    conn.transaction(|| {
+        let members = match options.guild_id {
+            Some(guild_id) if options.datasets.iter().any(|x| x.guild_column().is_none()) =>
+                Some(guild_members(&conn, guild_id)?),
+            _ => None,
+        };
+        let mut written = Vec::new();
+        for &dataset in &options.datasets {
+            let mut path = options.output.clone();
+            path.push(format!("{}.{}", dataset.name(), options.format.extension()));
+            let count = export_dataset(&conn, dataset, options, members.as_ref(), &path)?;
+            written.push((path, count));
+        }
+        Ok(written)
+    })
+}
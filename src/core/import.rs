@@ -0,0 +1,168 @@
+use errors::*;
+use roblox::RobloxUserID;
+use serde_json::{self, Value};
+use serenity::model::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+/// The note recorded in `user_history` for links created by an import. Whether a link is still
+/// unconfirmed is stored in `discord_user_info.is_imported` instead, as history may be pruned.
+pub(in ::core) const IMPORTED_NOTE: &str = "imported";
+
+/// The smallest valid Discord snowflake, as the lower 22 bits contain no timestamp.
+const MIN_DISCORD_ID: u64 = 1 << 22;
+
+/// How to handle imported links that conflict with a user's existing verification.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum ImportConflictPolicy {
+    /// Keeps the existing link, and skips the imported one.
+    Skip,
+    /// Replaces the existing link with the imported one.
+    Overwrite,
+}
+impl ImportConflictPolicy {
+    pub fn from_name(name: &str) -> Option<ImportConflictPolicy> {
+        match name {
+            "skip" => Some(ImportConflictPolicy::Skip),
+            "overwrite" => Some(ImportConflictPolicy::Overwrite),
+            _ => None,
+        }
+    }
+}
+
+/// The outcome of importing a list of links.
+#[derive(Clone, Debug, Default)]
+pub struct ImportReport {
+    pub imported: u32, pub unchanged: u32, pub busy: u32,
+    pub conflicts: Vec<(UserId, RobloxUserID)>,
+}
+
+type RawLink = StdResult<(u64, u64), &'static str>;
+
+/// A list of links read from an import file, along with the entries that could not be used.
+#[derive(Clone, Debug, Default)]
+pub struct ImportData {
+    pub links: Vec<(UserId, RobloxUserID)>, pub invalid: Vec<String>,
+}
+
+fn parse_id(value: &str) -> Option<u64> {
+    value.trim().trim_matches('"').parse().ok()
+}
+fn json_id(value: Option<&Value>) -> Option<u64> {
+    match value? {
+        Value::Number(number) => number.as_u64(),
+        Value::String(string) => parse_id(string),
+        _ => None,
+    }
+}
+fn check_link(discord_id: Option<u64>, roblox_id: Option<u64>) -> RawLink {
+    match (discord_id, roblox_id) {
+        (None, _) => Err("missing or malformed Discord id"),
+        (_, None) => Err("missing or malformed Roblox id"),
+        (Some(discord_id), _) if discord_id < MIN_DISCORD_ID => Err("invalid Discord id"),
+        (_, Some(0)) => Err("invalid Roblox id"),
+        (Some(discord_id), Some(roblox_id)) => Ok((discord_id, roblox_id)),
+    }
+}
+
+fn parse_json(data: &str, raw: &mut Vec<(String, RawLink)>) -> Result<()> {
+    let entries = match serde_json::from_str(data)? {
+        Value::Array(entries) => entries,
+        _ => cmd_error!("JSON imports must contain an array of objects."),
+    };
+    for (i, entry) in entries.iter().enumerate() {
+        let field = |names: &[&str]| names.iter().filter_map(|x| entry.get(x)).next();
+        let discord_id = json_id(field(&["discord_id", "discordId", "discord_user_id"]));
+        let roblox_id = json_id(field(&["roblox_id", "robloxId", "roblox_user_id"]));
+        raw.push((format!("entry {}", i + 1), check_link(discord_id, roblox_id)));
+    }
+    Ok(())
+}
+/// Splits a CSV line into fields. Fields may be quoted, with `""` standing for a quote, so
+/// quoted fields can contain separators.
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => in_quotes = !in_quotes,
+            ',' | ';' | '\t' if !in_quotes => fields.push(mem::replace(&mut field, String::new())),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+fn parse_csv(data: &str, raw: &mut Vec<(String, RawLink)>) {
+    let mut columns = (0, 1);
+    let mut is_first = true;
+    for (i, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() { continue }
+        let fields = split_csv_line(line);
+        let is_header = is_first && fields.iter().all(|x| parse_id(x).is_none());
+        is_first = false;
+        if is_header {
+            // Use the header to find the columns, if the file has one.
+            let find = |name: &str| fields.iter()
+                .position(|x| x.trim().to_lowercase().contains(name));
+            if let (Some(discord), Some(roblox)) = (find("discord"), find("roblox")) {
+                columns = (discord, roblox);
+            }
+            continue
+        }
+        let discord_id = fields.get(columns.0).and_then(|x| parse_id(x));
+        let roblox_id = fields.get(columns.1).and_then(|x| parse_id(x));
+        raw.push((format!("line {}", i + 1), check_link(discord_id, roblox_id)));
+    }
+}
+
+/// Reads Discord and Roblox id pairs from a CSV or JSON export. JSON exports are arrays of
+/// objects with `discord_id` and `roblox_id` fields. CSV exports have the Discord id in the
+/// first column and the Roblox id in the second, unless a header row says otherwise.
+///
+/// Entries that are malformed, or that give conflicting links for the same account, are
+/// reported rather than imported.
+pub fn parse_import(data: &str) -> Result<ImportData> {
+    let mut raw = Vec::new();
+    match data.trim_start().chars().next() {
+        Some('[') | Some('{') => parse_json(data, &mut raw)?,
+        _ => parse_csv(data, &mut raw),
+    }
+
+    let mut by_discord = HashMap::new();
+    let mut by_roblox = HashMap::new();
+    for (_, link) in &raw {
+        if let Ok((discord_id, roblox_id)) = *link {
+            by_discord.entry(discord_id).or_insert_with(Vec::new).push(roblox_id);
+            by_roblox.entry(roblox_id).or_insert_with(Vec::new).push(discord_id);
+        }
+    }
+
+    let mut seen = HashSet::new();
+    let mut data = ImportData::default();
+    for (location, link) in raw {
+        match link {
+            Ok((discord_id, roblox_id)) => {
+                let conflicting = |ids: &[u64]| ids.iter().any(|&x| x != ids[0]);
+                if conflicting(&by_discord[&discord_id]) {
+                    data.invalid.push(format!("{}: Discord id {} is listed with more than one \
+                                               Roblox id", location, discord_id));
+                } else if conflicting(&by_roblox[&roblox_id]) {
+                    data.invalid.push(format!("{}: Roblox id {} is listed with more than one \
+                                               Discord id", location, roblox_id));
+                } else if seen.insert(discord_id) {
+                    data.links.push((UserId(discord_id), RobloxUserID(roblox_id)));
+                }
+            }
+            Err(e) => data.invalid.push(format!("{}: {}", location, e)),
+        }
+    }
+    Ok(data)
+}
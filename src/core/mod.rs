@@ -9,22 +9,53 @@ use std::sync::atomic::{AtomicU8, Ordering};
 use std::thread;
 use std::time::{Duration, Instant};
 
+mod alt_detector;
+mod audit;
+mod backup;
+mod bans;
 mod config;
+mod consistency;
 mod delete_service;
 mod discord;
+mod export;
+mod import;
+mod oauth;
 mod permissions;
 mod place;
+mod privacy;
+mod retention;
 mod roles;
 mod tasks;
 mod terminal;
 mod verification_channel;
+mod verification_log;
 mod verifier;
+mod verify_result;
+mod web_server;
 
+pub use self::alt_detector::{AltDetector, AltFlag};
+pub use self::audit::{AuditLog, AuditEvent, AuditEventType, AuditFilter};
+pub use self::backup::{BackupManager, BackupInfo, restore_from_backup};
+pub use self::bans::{BanManager, BanEntry, BanTarget};
 pub use self::config::{ConfigKey, ConfigKeys};
+pub use self::consistency::{ConsistencyChecker, ConsistencyIssue, ConsistencyReport,
+                            IssueCategory};
+pub use self::export::{ExportDataset, ExportFormat, ExportOptions, export_data};
+pub use self::import::{ImportConflictPolicy, ImportData, ImportReport, parse_import};
+pub use self::oauth::OAuthManager;
 pub use self::permissions::{BotPermission, Scope};
+pub use self::privacy::PrivacyManager;
+pub use self::retention::{RetentionManager, PruneReport};
 pub use self::roles::{RoleManager, AssignedRole, ConfiguredRole, SetRolesStatus};
 pub use self::verification_channel::VerificationChannelManager;
-pub use self::verifier::{Verifier, VerifyResult, TokenStatus, HistoryEntry};
+pub use self::verification_log::{VerificationLog, LogEvent};
+pub use self::verifier::{Verifier, VerifyResult, TokenStatus, HistoryEntry, KeyInfo, KeyState,
+                         LinkedAccount, PushVerifyResult, PendingVerification,
+                         MIN_TOKEN_LENGTH, MAX_TOKEN_LENGTH};
+pub use self::verify_result::{APPROVE_EMOJI, DENY_EMOJI, check_alts, finish_verify,
+                              format_discord_id, format_roblox_id, get_discord_username,
+                              guild_name, roles_summary, update_linked_roles,
+                              verify_status_str};
 
 use self::config::ConfigManager;
 use self::delete_service::DeleteService;
@@ -33,6 +64,7 @@ use self::permissions::PermissionManager;
 use self::place::PlaceManager;
 use self::terminal::Terminal;
 use self::tasks::TaskManager;
+use self::web_server::WebServer;
 
 const STATUS_STOPPED : u8 = 0;
 const STATUS_RUNNING : u8 = 1;
@@ -40,11 +72,15 @@ const STATUS_STOPPING: u8 = 2;
 
 struct VerifierCoreData {
     status: AtomicU8,
-    _database: Database, config: ConfigManager, core_ref: CoreRef,
+    database: Database, config: ConfigManager, core_ref: CoreRef,
     terminal: Terminal, verifier: Verifier, discord: DiscordManager,
     place: PlaceManager, roles: RoleManager, permissions: PermissionManager,
-    _tasks: TaskManager,
+    tasks: TaskManager,
     verify_channel: VerificationChannelManager,
+    oauth: OAuthManager, web_server: WebServer, bans: BanManager,
+    alt_detector: AltDetector, audit: AuditLog, verification_log: VerificationLog,
+    privacy: PrivacyManager, retention: RetentionManager, backups: BackupManager,
+    consistency: ConsistencyChecker,
 }
 
 struct CoreRefActiveGuard<'a>(&'a CoreRef);
@@ -102,22 +138,46 @@ impl VerifierCore {
         let terminal = Terminal::new(core_ref.clone())?;
         let verify_channel = VerificationChannelManager::new(config.clone(), database.clone(),
                                                              delete_service.clone());
-        let verifier = Verifier::new(config.clone(), database.clone())?;
+        let audit = AuditLog::new(database.clone());
+        let verification_log = VerificationLog::new(config.clone());
+        let bans = BanManager::new(database.clone());
+        let alt_detector = AltDetector::new(config.clone(), database.clone(), bans.clone());
+        let verifier = Verifier::new(config.clone(), database.clone(), bans.clone(), &root_path)?;
         let permissions = PermissionManager::new(database.clone());
         let place = PlaceManager::new(place_target)?;
         let roles = RoleManager::new(config.clone(), database.clone(), verifier.clone(),
-                                     tasks.clone(), permissions.clone());
+                                     tasks.clone(), permissions.clone(), bans.clone(),
+                                     alt_detector.clone(), audit.clone(),
+                                     verification_log.clone());
+        let privacy = PrivacyManager::new(database.clone(), verifier.clone(),
+                                          permissions.clone(), roles.clone());
+        let retention = RetentionManager::new(config.clone(), database.clone());
+        let consistency = ConsistencyChecker::new(database.clone(), roles.clone(),
+                                                  permissions.clone());
+        let backups = BackupManager::new(config.clone(), database.clone(), root_path);
+        let oauth = OAuthManager::new(config.clone());
+        let web_server = WebServer::new(config.clone(), core_ref.clone());
         let discord = DiscordManager::new(config.clone(), core_ref.clone(), roles.clone(),
                                           tasks.clone(), verify_channel.clone(),
                                           delete_service.clone(), permissions.clone());
 
         tasks.dispatch_repeating_task(Duration::from_secs(60 * 10), |core| core.cleanup());
+        tasks.dispatch_repeating_task(Duration::from_secs(60), |core| core.check_key_rotation());
+        tasks.dispatch_repeating_task(Duration::from_secs(60 * 60),
+                                      |core| core.roles().check_verification_expiry());
+        tasks.dispatch_repeating_task(Duration::from_secs(60 * 60),
+                                      |core| core.roles().check_alt_flag_expiry());
+        tasks.dispatch_repeating_task(Duration::from_secs(60 * 60),
+                                      |core| core.retention().prune_and_report());
+        tasks.dispatch_repeating_task(Duration::from_secs(60 * 10),
+                                      |core| core.backups().run_scheduled());
 
         Ok(VerifierCore(Arc::new(VerifierCoreData {
             status: AtomicU8::new(STATUS_STOPPED),
-            _database: database, _tasks: tasks,
+            database, tasks,
             config, core_ref, terminal, verifier, discord, place, permissions,
-            roles, verify_channel,
+            roles, verify_channel, oauth, web_server, bans, alt_detector, audit,
+            verification_log, privacy, retention, backups, consistency,
         })))
     }
 
@@ -129,6 +189,8 @@ impl VerifierCore {
         self.0.roles.on_cleanup_tick();
         self.0.verify_channel.on_cleanup_tick();
         self.0.verifier.on_cleanup_tick();
+        self.0.oauth.on_cleanup_tick();
+        self.0.bans.on_cleanup_tick();
         Ok(())
     }
     fn wait_on_instances(&self) {
@@ -155,11 +217,15 @@ impl VerifierCore {
                 "VerifierCore already started.");
         let core_ref_guard = self.0.core_ref.activate(&self.0);
         self.refresh_place()?;
+        if let Err(e) = self.0.web_server.start() {
+            error!("Could not start web server: {}", e);
+        }
         self.0.discord.connect()?;
         self.0.terminal.open()?;
         ensure!(self.0.status.load(Ordering::Relaxed) == STATUS_STOPPING,
                 "Terminal interrupted without initializing shutdown!");
         drop(core_ref_guard);
+        self.0.web_server.shutdown()?;
         self.0.discord.shutdown()?;
         self.wait_on_instances();
         ensure!(self.0.status.compare_and_swap(STATUS_STOPPING, STATUS_STOPPED,
@@ -179,6 +245,9 @@ impl VerifierCore {
         }
     }
 
+    pub fn database(&self) -> &Database {
+        &self.0.database
+    }
     pub fn config(&self) -> &ConfigManager {
         &self.0.config
     }
@@ -197,8 +266,47 @@ impl VerifierCore {
     pub fn verify_channel(&self) -> &VerificationChannelManager {
         &self.0.verify_channel
     }
+    pub fn oauth(&self) -> &OAuthManager {
+        &self.0.oauth
+    }
+    pub fn bans(&self) -> &BanManager {
+        &self.0.bans
+    }
+    pub fn alt_detector(&self) -> &AltDetector {
+        &self.0.alt_detector
+    }
+    pub fn audit(&self) -> &AuditLog {
+        &self.0.audit
+    }
+    pub fn verification_log(&self) -> &VerificationLog {
+        &self.0.verification_log
+    }
+    pub fn privacy(&self) -> &PrivacyManager {
+        &self.0.privacy
+    }
+    pub fn retention(&self) -> &RetentionManager {
+        &self.0.retention
+    }
+    pub fn backups(&self) -> &BackupManager {
+        &self.0.backups
+    }
+    pub fn consistency(&self) -> &ConsistencyChecker {
+        &self.0.consistency
+    }
+    pub(in ::core) fn tasks(&self) -> &TaskManager {
+        &self.0.tasks
+    }
 
     pub fn refresh_place(&self) -> Result<()> {
         self.0.place.update_place(self)
     }
+    pub fn current_place(&self) -> Result<(Vec<u8>, [u8; 32])> {
+        self.0.place.current_place(self)
+    }
+    pub fn check_key_rotation(&self) -> Result<()> {
+        if self.0.verifier.check_rotation()? {
+            self.refresh_place()?;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,137 @@
+use core::config::*;
+use core::web_server::public_url;
+use errors::*;
+use parking_lot::Mutex;
+use rand::{RngCore, rngs::OsRng};
+use roblox::*;
+use serenity::model::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{SystemTime, Duration};
+use util;
+
+pub const OAUTH_CALLBACK_PATH: &str = "/oauth/callback";
+const LOGIN_EXPIRY_SECS: u64 = 60 * 10;
+const DISCOVERY_CACHE_SECS: u64 = 60 * 60;
+
+struct PendingLogin {
+    guild_id: Option<GuildId>,
+    discord_id: UserId,
+    nonce: String,
+    code_verifier: String,
+    expires: SystemTime,
+}
+
+fn random_string() -> String {
+    let mut data = [0u8; 32];
+    OsRng.fill_bytes(&mut data);
+    util::to_hex(&data)
+}
+
+struct OAuthSettings {
+    client_id: String,
+    client_secret: String,
+    redirect_uri: String,
+}
+
+/// Checks whether everything needed for OAuth verification has been configured.
+crate fn oauth_configured(config: &ConfigManager) -> Result<bool> {
+    Ok(config.get(None, ConfigKeys::OAuthClientId)?.is_some() &&
+       config.get(None, ConfigKeys::OAuthClientSecret)?.is_some() &&
+       public_url(config, OAUTH_CALLBACK_PATH)?.is_some())
+}
+
+struct OAuthManagerData {
+    config: ConfigManager,
+    endpoints: Mutex<Option<(OAuthEndpoints, SystemTime)>>,
+    pending: Mutex<HashMap<String, PendingLogin>>,
+}
+
+#[derive(Clone)]
+pub struct OAuthManager(Arc<OAuthManagerData>);
+impl OAuthManager {
+    pub(in ::core) fn new(config: ConfigManager) -> OAuthManager {
+        OAuthManager(Arc::new(OAuthManagerData {
+            config,
+            endpoints: Mutex::new(None),
+            pending: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    pub fn is_configured(&self) -> Result<bool> {
+        oauth_configured(&self.0.config)
+    }
+    fn settings(&self) -> Result<OAuthSettings> {
+        cmd_ensure!(self.is_configured()?,
+                    "Verification by logging in to Roblox has not been set up. Please ask the \
+                     bot owner to fix this.");
+        Ok(OAuthSettings {
+            client_id: self.0.config.get(None, ConfigKeys::OAuthClientId)?
+                .ok_or_else(Error::none)?,
+            client_secret: self.0.config.get(None, ConfigKeys::OAuthClientSecret)?
+                .ok_or_else(Error::none)?,
+            redirect_uri: public_url(&self.0.config, OAUTH_CALLBACK_PATH)?
+                .ok_or_else(Error::none)?,
+        })
+    }
+    fn endpoints(&self) -> Result<OAuthEndpoints> {
+        let issuer = self.0.config.get(None, ConfigKeys::OAuthIssuerUrl)?;
+        let mut endpoints = self.0.endpoints.lock();
+        match endpoints.as_ref() {
+            Some((cached, expires)) if cached.issuer() == issuer.trim_end_matches('/') &&
+                                       *expires > SystemTime::now() =>
+                return Ok(cached.clone()),
+            _ => { }
+        }
+        let discovered = OAuthEndpoints::discover(&issuer)?;
+        let expires = SystemTime::now() + Duration::from_secs(DISCOVERY_CACHE_SECS);
+        *endpoints = Some((discovered.clone(), expires));
+        Ok(discovered)
+    }
+
+    /// Creates a one-time login link for a user. Any link previously issued to the user stops
+    /// working.
+    pub fn start_login(
+        &self, guild_id: Option<GuildId>, discord_id: UserId,
+    ) -> Result<(String, SystemTime)> {
+        let settings = self.settings()?;
+        let endpoints = self.endpoints()?;
+
+        let state = random_string();
+        let nonce = random_string();
+        let code_verifier = random_string();
+        let url = endpoints.authorize_url(&settings.client_id, &settings.redirect_uri, &state,
+                                          &nonce, &code_verifier)?;
+
+        let expires = SystemTime::now() + Duration::from_secs(LOGIN_EXPIRY_SECS);
+        let mut pending = self.0.pending.lock();
+        pending.retain(|_, login| login.discord_id != discord_id);
+        pending.insert(state, PendingLogin { guild_id, discord_id, nonce, code_verifier, expires });
+        Ok((url, expires))
+    }
+
+    /// Completes a login started with `start_login`, returning the server the login was
+    /// started in (if any), the Discord user that started it, and the Roblox account they
+    /// logged in as.
+    pub fn finish_login(
+        &self, state: &str, code: &str,
+    ) -> Result<(Option<GuildId>, UserId, RobloxUserID)> {
+        let login = self.0.pending.lock().remove(state);
+        let login = match login {
+            Some(ref login) if login.expires > SystemTime::now() => login,
+            _ => cmd_error!("This login link is invalid or has expired. Please request a new \
+                             one from Discord."),
+        };
+        let settings = self.settings()?;
+        let endpoints = self.endpoints()?;
+        let roblox_id = endpoints.exchange_code(&settings.client_id, &settings.client_secret,
+                                                &settings.redirect_uri, code,
+                                                &login.code_verifier, &login.nonce)?;
+        Ok((login.guild_id, login.discord_id, roblox_id))
+    }
+
+    pub fn on_cleanup_tick(&self) {
+        let now = SystemTime::now();
+        self.0.pending.lock().retain(|_, login| login.expires > now);
+    }
+}
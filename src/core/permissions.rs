@@ -32,6 +32,36 @@ impl Scope {
             Scope::GuildUser(gid, uid) => (SCOPE_GUILD_USER, gid.0, uid.0),
         }
     }
+    fn guild(self) -> Option<GuildId> {
+        match self {
+            Scope::GlobalAllGuilds | Scope::GlobalAllUsers | Scope::User(_) => None,
+            Scope::Guild(gid) | Scope::GuildAllUsers(gid) |
+            Scope::GuildRole(gid, _) | Scope::GuildUser(gid, _) => Some(gid),
+        }
+    }
+}
+
+/// Returns an SQL condition matching the rows of the permissions table that belong to the
+/// server passed as `?1`.
+pub(in ::core) fn guild_scopes_sql() -> String {
+    format!("((scope_1 = {} OR scope_1 = {}) AND id = ?1) OR \
+             ((scope_1 = {} OR scope_1 = {}) AND scope_2 = ?1)",
+            SCOPE_GUILD, SCOPE_GUILD_USERS, SCOPE_GUILD_ROLE, SCOPE_GUILD_USER)
+}
+/// Returns an SQL condition matching the rows of the permissions table that do not belong to
+/// any valid scope.
+pub(in ::core) fn invalid_scopes_sql() -> String {
+    format!("scope_1 < {} OR scope_1 > {} OR \
+             ((scope_1 = {} OR scope_1 = {}) AND (scope_2 != 0 OR id != 0)) OR \
+             ((scope_1 = {} OR scope_1 = {} OR scope_1 = {}) AND scope_2 != 0)",
+            SCOPE_GLOBAL_GUILD, SCOPE_GUILD_USER, SCOPE_GLOBAL_GUILD, SCOPE_GLOBAL_USERS,
+            SCOPE_USER, SCOPE_GUILD, SCOPE_GUILD_USERS)
+}
+/// Returns every role that has been granted permissions, along with its server.
+pub(in ::core) fn get_role_scopes(conn: &DatabaseConnection) -> Result<Vec<(GuildId, RoleId)>> {
+    conn.query(
+        "SELECT scope_2, id FROM permissions WHERE scope_1 = ?1", SCOPE_GUILD_ROLE,
+    ).get_all::<(GuildId, RoleId)>()
 }
 
 // This enum's order is reflected in the database format!
@@ -52,18 +82,39 @@ pub enum BotPermission {
 
     // Guild permissions 2
     BypassNicknameUpdate,
+
+    // Ban permissions
+    ManageBans, ManageGlobalBans,
+
+    // Alt account detection permissions
+    ReviewAltFlags,
+
+    // Verification approval permissions
+    ApproveVerification,
+
+    // Manual verification permissions
+    ForceVerify,
+
+    // Audit log permissions
+    ViewAuditLog, ViewGlobalAuditLog,
+
+    // Import permissions
+    ImportVerifications,
 }
 
 use self::BotPermission::*;
 
 const ALWAYS_GLOBAL_GUILD: EnumSet<BotPermission> =
-    enum_set!(GuildAdmin | ManageGuildSettings | ManageRoles);
+    enum_set!(GuildAdmin | ManageGuildSettings | ManageRoles | ManageBans | ReviewAltFlags |
+              ApproveVerification | ViewAuditLog);
 const DEFAULT_GLOBAL_ALL_GUILDS: EnumSet<BotPermission> =
     enum_set!();
 const DEFAULT_GLOBAL_ALL_USERS: EnumSet<BotPermission> =
     enum_set!(Unverify | Whois | Whowas);
 const GUILD_ONLY: EnumSet<BotPermission> =
     enum_set!();
+const GLOBAL_ONLY: EnumSet<BotPermission> =
+    enum_set!(ForceVerify);
 
 pub struct PermissionManagerData {
     database: Database, scope_cache: ConcurrentCache<Scope, EnumSet<BotPermission>>,
@@ -113,6 +164,23 @@ impl PermissionManager {
         *self.0.scope_cache.write(&scope)? = permissions;
         Ok(())
     }
+    /// Returns every scope that grants permissions to a specific user, either globally or in a
+    /// single server.
+    pub fn get_user_scopes(&self, user: UserId) -> Result<Vec<(Scope, EnumSet<BotPermission>)>> {
+        let scopes = self.0.database.connect()?.query(
+            "SELECT scope_1, scope_2, permission_bits FROM permissions \
+             WHERE (scope_1 = ?1 OR scope_1 = ?2) AND id = ?3",
+            (SCOPE_USER, SCOPE_GUILD_USER, user),
+        ).get_all::<(u64, u64, u64)>()?;
+        Ok(scopes.into_iter().map(|(scope_1, scope_2, bits)| {
+            let scope = if scope_1 == SCOPE_USER {
+                Scope::User(user)
+            } else {
+                Scope::GuildUser(GuildId(scope_2), user)
+            };
+            (scope, EnumSet::from_u64(bits))
+        }).collect())
+    }
     pub fn get_guild_perms(&self, guild_id: GuildId) -> Result<EnumSet<BotPermission>> {
         let mut perms = self.get_scope(Scope::GlobalAllGuilds)?;
         perms |= self.get_scope(Scope::Guild(guild_id))?;
@@ -159,14 +227,24 @@ impl PermissionManager {
         } else {
             guild_perms &= self.get_guild_perms(guild_id)?;
         }
+        guild_perms -= GLOBAL_ONLY;
         self.get_user_raw(user, guild_perms)
     }
 
+    pub(in ::core) fn delete_scope(&self, conn: &DatabaseConnection, scope: Scope) -> Result<()> {
+        conn.execute(
+            "DELETE FROM permissions WHERE scope_1 = ?1 AND scope_2 = ?2 AND id = ?3",
+            scope.to_sql(),
+        )?;
+        self.0.scope_cache.remove(&scope);
+        Ok(())
+    }
+
     pub fn on_cleanup_tick(&self) {
         self.0.scope_cache.shrink_to_fit();
     }
-    pub fn on_guild_remove(&self, _: GuildId) {
-        // TODO: Implement
+    pub fn on_guild_remove(&self, guild: GuildId) {
+        self.0.scope_cache.retain(|scope, _| scope.guild() != Some(guild));
         self.0.scope_cache.shrink_to_fit();
     }
 }
\ No newline at end of file
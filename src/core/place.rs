@@ -1,5 +1,6 @@
 use core::VerifierCore;
 use core::config::*;
+use core::web_server::{public_url, PUSH_VERIFY_PATH};
 use errors::*;
 use parking_lot::Mutex;
 use roblox::*;
@@ -9,7 +10,7 @@ use std::io::{Read, Write};
 use std::path::PathBuf;
 
 struct PlaceManagerState {
-    place_target: PathBuf, current_hash: [u8; 32],
+    place_target: PathBuf, current_hash: [u8; 32], current_data: Option<Vec<u8>>,
 }
 crate struct PlaceManager(Mutex<PlaceManagerState>);
 impl PlaceManager {
@@ -25,7 +26,9 @@ impl PlaceManager {
         } else {
             [0u8; 32]
         };
-        Ok(PlaceManager(Mutex::new(PlaceManagerState { place_target, current_hash })))
+        Ok(PlaceManager(Mutex::new(PlaceManagerState {
+            place_target, current_hash, current_data: None,
+        })))
     }
 
     fn place_config(&self, core: &VerifierCore) -> Result<Vec<LuaConfigEntry>> {
@@ -34,31 +37,48 @@ impl PlaceManager {
                                         core.config().get(None, ConfigKeys::PlaceUITitle)?));
         config.push(LuaConfigEntry::new("intro_text", false,
                                         core.config().get(None, ConfigKeys::PlaceUIInstructions)?));
+        config.push(LuaConfigEntry::new("title_locales", false,
+                                        core.config().get(None, ConfigKeys::PlaceUITitleLocales)?));
+        config.push(LuaConfigEntry::new("intro_text_locales", false,
+                                        core.config().get(None,
+                                                          ConfigKeys::PlaceUIInstructionsLocales)?));
         config.push(LuaConfigEntry::new("bot_prefix", false,
                                         core.config().get(None, ConfigKeys::CommandPrefix)?));
         config.push(LuaConfigEntry::new("background_image", false,
                                         core.config().get(None, ConfigKeys::PlaceUIBackground)?));
+        config.push(LuaConfigEntry::new("push_endpoint", true,
+                                        public_url(core.config(), PUSH_VERIFY_PATH)?));
         core.verifier().add_config(&mut config);
         Ok(config)
     }
-    fn check_write_place(&self, data: &[u8]) -> Result<()> {
-        let state = self.0.lock();
+    fn check_write_place(&self, data: Vec<u8>) -> Result<()> {
+        let mut state = self.0.lock();
 
-        let hash = Sha256::digest(data);
+        let hash = Sha256::digest(&data);
         if hash.as_slice() != state.current_hash {
             info!("An updated place file has been written to '{}'.", state.place_target.display());
             // TODO: Online documentation!
             info!("Please follow the instructions at [url] to update the place. If you do not, \
-                   the verifier bot may not work.");
+                   the verifier bot may not work. You can also use the 'get_place' command to \
+                   download the place file.");
 
             let mut handle = File::create(&state.place_target)?;
-            handle.write_all(data)?;
+            handle.write_all(&data)?;
+            state.current_hash.copy_from_slice(hash.as_slice());
         }
+        state.current_data = Some(data);
         Ok(())
     }
     crate fn update_place(&self, core: &VerifierCore) -> Result<()> {
         let place_data = create_place_file(None, &self.place_config(core)?)?;
-        self.check_write_place(&place_data)?;
+        self.check_write_place(place_data)?;
         Ok(())
     }
+    crate fn current_place(&self, core: &VerifierCore) -> Result<(Vec<u8>, [u8; 32])> {
+        if self.0.lock().current_data.is_none() {
+            self.update_place(core)?;
+        }
+        let state = self.0.lock();
+        Ok((state.current_data.clone().ok_or_else(Error::none)?, state.current_hash))
+    }
 }
\ No newline at end of file
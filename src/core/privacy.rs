@@ -0,0 +1,211 @@
+use core::permissions::*;
+use core::roles::*;
+use core::verifier::*;
+use database::*;
+use errors::*;
+use roblox::RobloxUserID;
+use serde_json::Value;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+use std::time::SystemTime;
+use util;
+
+type BanRow = (u64, bool, u64, String, Option<UserId>, SystemTime, Option<SystemTime>);
+
+struct PrivacyManagerData {
+    database: Database, verifier: Verifier, permissions: PermissionManager, roles: RoleManager,
+}
+
+#[derive(Clone)]
+pub struct PrivacyManager(Arc<PrivacyManagerData>);
+impl PrivacyManager {
+    pub(in ::core) fn new(
+        database: Database, verifier: Verifier, permissions: PermissionManager,
+        roles: RoleManager,
+    ) -> PrivacyManager {
+        PrivacyManager(Arc::new(PrivacyManagerData { database, verifier, permissions, roles }))
+    }
+
+    /// Collects every row stored about a user into a JSON document. Timestamps are given in
+    /// seconds since the Unix epoch.
+    pub fn export_user_data(&self, discord_id: UserId) -> Result<Value> {
+        let conn = self.0.database.connect()?;
+        let time = util::time_to_i64;
+
+        let user_info = conn.query(
+            "SELECT roblox_user_id, last_updated FROM discord_user_info \
+             WHERE discord_user_id = ?1", discord_id,
+        ).get_opt::<(Option<RobloxUserID>, SystemTime)>()?;
+        let linked_accounts = conn.query(
+            "SELECT roblox_user_id, last_updated FROM discord_linked_accounts \
+             WHERE discord_user_id = ?1 ORDER BY last_updated", discord_id,
+        ).get_all::<(RobloxUserID, SystemTime)>()?;
+        let history = conn.query(
+            "SELECT roblox_user_id, is_unverify, last_updated, actor_discord_id, note \
+             FROM user_history WHERE discord_user_id = ?1 ORDER BY last_updated", discord_id,
+        ).get_all::<(RobloxUserID, bool, SystemTime, Option<UserId>, Option<String>)>()?;
+        let cooldown = conn.query(
+            "SELECT last_attempt, attempt_count FROM verification_cooldown \
+             WHERE discord_user_id = ?1", discord_id,
+        ).get_opt::<(SystemTime, u32)>()?;
+        let roles_last_updated = conn.query(
+            "SELECT discord_guild_id, is_manual, last_updated FROM roles_last_updated \
+             WHERE discord_user_id = ?1 ORDER BY discord_guild_id", discord_id,
+        ).get_all::<(GuildId, bool, SystemTime)>()?;
+        let pending = conn.query(
+            "SELECT discord_guild_id, roblox_user_id, is_link, created, expires \
+             FROM pending_verifications WHERE discord_user_id = ?1", discord_id,
+        ).get_all::<(GuildId, RobloxUserID, bool, SystemTime, SystemTime)>()?;
+        let expiry_notices = conn.query(
+            "SELECT discord_guild_id, verified_at, is_expired FROM verification_expiry_notices \
+             WHERE discord_user_id = ?1", discord_id,
+        ).get_all::<(GuildId, SystemTime, bool)>()?;
+        let approved = conn.query(
+            "SELECT discord_guild_id, roblox_user_id, approved, actor_discord_id \
+             FROM approved_verifications WHERE discord_user_id = ?1", discord_id,
+        ).get_all::<(GuildId, RobloxUserID, SystemTime, Option<UserId>)>()?;
+        let alt_flags = conn.query(
+            "SELECT discord_guild_id, roblox_user_id, reasons, holds_roles, created \
+             FROM alt_flags WHERE discord_user_id = ?1", discord_id,
+        ).get_all::<(GuildId, RobloxUserID, String, bool, SystemTime)>()?;
+        let mut bans = conn.query(
+            "SELECT discord_guild_id, is_roblox, target_id, reason, author_discord_id, created, \
+                    expires \
+             FROM ban_list WHERE is_roblox = 0 AND target_id = ?1", discord_id,
+        ).get_all::<BanRow>()?;
+        for &(roblox_id, _) in &linked_accounts {
+            bans.extend(conn.query(
+                "SELECT discord_guild_id, is_roblox, target_id, reason, author_discord_id, \
+                        created, expires \
+                 FROM ban_list WHERE is_roblox = 1 AND target_id = ?1", roblox_id,
+            ).get_all::<BanRow>()?);
+        }
+        let audit_events = conn.query(
+            "SELECT event_type, discord_guild_id, actor_discord_id, discord_user_id, \
+                    roblox_user_id, created, payload \
+             FROM audit_events WHERE discord_user_id = ?1 OR actor_discord_id = ?1 \
+             ORDER BY id", discord_id,
+        ).get_all::<(String, Option<GuildId>, Option<UserId>, Option<UserId>,
+                     Option<RobloxUserID>, SystemTime, Value)>()?;
+        let permissions = self.0.permissions.get_user_scopes(discord_id)?;
+
+        Ok(json!({
+            "discord_user_id": discord_id.0,
+            "discord_user_info": user_info.map(|(roblox_id, last_updated)| json!({
+                "roblox_user_id": roblox_id.map(|x| x.0),
+                "last_updated": time(last_updated),
+            })),
+            "linked_accounts": linked_accounts.into_iter().map(|(roblox_id, last_updated)| json!({
+                "roblox_user_id": roblox_id.0,
+                "last_updated": time(last_updated),
+            })).collect::<Vec<_>>(),
+            "user_history": history.into_iter().map(|(roblox_id, is_unverify, last_updated,
+                                                      actor, note)| json!({
+                "roblox_user_id": roblox_id.0,
+                "is_unverify": is_unverify,
+                "last_updated": time(last_updated),
+                "actor_discord_id": actor.map(|x| x.0),
+                "note": note,
+            })).collect::<Vec<_>>(),
+            "verification_cooldown": cooldown.map(|(last_attempt, attempt_count)| json!({
+                "last_attempt": time(last_attempt),
+                "attempt_count": attempt_count,
+            })),
+            "roles_last_updated": roles_last_updated.into_iter().map(|(guild_id, is_manual,
+                                                                       last_updated)| json!({
+                "discord_guild_id": guild_id.0,
+                "is_manual": is_manual,
+                "last_updated": time(last_updated),
+            })).collect::<Vec<_>>(),
+            "pending_verifications": pending.into_iter().map(|(guild_id, roblox_id, is_link,
+                                                               created, expires)| json!({
+                "discord_guild_id": guild_id.0,
+                "roblox_user_id": roblox_id.0,
+                "is_link": is_link,
+                "created": time(created),
+                "expires": time(expires),
+            })).collect::<Vec<_>>(),
+            "verification_expiry_notices": expiry_notices.into_iter().map(|(guild_id, verified_at,
+                                                                           is_expired)| json!({
+                "discord_guild_id": guild_id.0,
+                "verified_at": time(verified_at),
+                "is_expired": is_expired,
+            })).collect::<Vec<_>>(),
+            "approved_verifications": approved.into_iter().map(|(guild_id, roblox_id, approved,
+                                                                   actor)| json!({
+                "discord_guild_id": guild_id.0,
+                "roblox_user_id": roblox_id.0,
+                "approved": time(approved),
+                "actor_discord_id": actor.map(|x| x.0),
+            })).collect::<Vec<_>>(),
+            "alt_flags": alt_flags.into_iter().map(|(guild_id, roblox_id, reasons, holds_roles,
+                                                     created)| json!({
+                "discord_guild_id": guild_id.0,
+                "roblox_user_id": roblox_id.0,
+                "reasons": reasons,
+                "holds_roles": holds_roles,
+                "created": time(created),
+            })).collect::<Vec<_>>(),
+            "ban_list": bans.into_iter().map(|(guild_id, is_roblox, target_id, reason, author,
+                                               created, expires)| json!({
+                "discord_guild_id": guild_id,
+                "is_roblox": is_roblox,
+                "target_id": target_id,
+                "reason": reason,
+                "author_discord_id": author.map(|x| x.0),
+                "created": time(created),
+                "expires": expires.map(time),
+            })).collect::<Vec<_>>(),
+            "audit_events": audit_events.into_iter().map(|(event_type, guild_id, actor,
+                                                           subject, roblox_id, created,
+                                                           payload)| json!({
+                "event_type": event_type,
+                "discord_guild_id": guild_id.map(|x| x.0),
+                "actor_discord_id": actor.map(|x| x.0),
+                "discord_user_id": subject.map(|x| x.0),
+                "roblox_user_id": roblox_id.map(|x| x.0),
+                "created": time(created),
+                "payload": payload,
+            })).collect::<Vec<_>>(),
+            "permissions": permissions.into_iter().map(|(scope, perms)| json!({
+                "discord_guild_id": match scope {
+                    Scope::GuildUser(guild_id, _) => Some(guild_id.0),
+                    _ => None,
+                },
+                "permission_bits": perms.as_u64(),
+            })).collect::<Vec<_>>(),
+        }))
+    }
+
+    /// Unverifies a user, removes their managed roles in every server they share with the bot,
+    /// and deletes or anonymises the data stored about them.
+    ///
+    /// Bans, alt account flags and permissions are kept, as they are set by moderators rather
+    /// than the user.
+    pub fn forget_user(
+        &self, discord_id: UserId,
+    ) -> Result<(Vec<RobloxUserID>, Vec<(GuildId, Result<SetRolesStatus>)>)> {
+        let linked = self.0.verifier.forget_user(discord_id)?;
+        let results = self.0.roles.assign_roles_all(discord_id, None, None)?;
+
+        let conn = self.0.database.connect()?;
+        conn.transaction(|| {
+            conn.execute(
+                "DELETE FROM roles_last_updated WHERE discord_user_id = ?1", discord_id,
+            )?;
+            conn.execute(
+                "DELETE FROM verification_expiry_notices WHERE discord_user_id = ?1", discord_id,
+            )?;
+            conn.execute(
+                "UPDATE audit_events SET discord_user_id = NULL WHERE discord_user_id = ?1",
+                discord_id,
+            )?;
+            conn.execute(
+                "UPDATE audit_events SET actor_discord_id = NULL WHERE actor_discord_id = ?1",
+                discord_id,
+            )?;
+            Ok(())
+        })?;
+        Ok((linked, results))
+    }
+}
@@ -0,0 +1,218 @@
+use core::config::*;
+use core::permissions::guild_scopes_sql;
+use database::*;
+use errors::*;
+use serenity::model::prelude::*;
+use std::cmp;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// How many rows are deleted per transaction while pruning, so other queries are not blocked
+/// for long.
+const PRUNE_BATCH_SIZE: isize = 500;
+
+/// Tables pruned by age. Each entry lists the table, the columns identifying a row, the column
+/// holding the row's age, and the setting controlling how long rows are kept for.
+const AGE_TARGETS: &[(&str, &str, &str, ConfigKey<u64>)] = &[
+    ("user_history", "rowid", "last_updated", ConfigKeys::UserHistoryRetentionSeconds),
+    ("verification_cooldown", "discord_user_id", "last_attempt",
+     ConfigKeys::VerificationCooldownRetentionSeconds),
+    ("roles_last_updated", "discord_guild_id, discord_user_id, is_manual", "last_updated",
+     ConfigKeys::RolesLastUpdatedRetentionSeconds),
+    ("audit_events", "id", "created", ConfigKeys::AuditLogRetentionSeconds),
+];
+
+/// Tables holding per-server data, and the columns identifying a row in each.
+pub(in ::core) const GUILD_TARGETS: &[(&str, &str)] = &[
+    ("guild_config", "discord_guild_id, key"),
+    ("guild_custom_rules", "discord_guild_id, rule_name"),
+    ("guild_active_rules", "discord_guild_id, rule_name"),
+    ("roles_last_updated", "discord_guild_id, discord_user_id, is_manual"),
+    ("verification_channel_info", "discord_guild_id"),
+    ("ban_list", "discord_guild_id, is_roblox, target_id"),
+    ("alt_flags", "discord_guild_id, discord_user_id"),
+    ("pending_verifications", "discord_guild_id, discord_user_id"),
+    ("approved_verifications", "discord_guild_id, discord_user_id, roblox_user_id"),
+    ("verification_expiry_notices", "discord_guild_id, discord_user_id"),
+];
+
+/// The rows removed by a pruning run, or that would be removed by a dry run.
+#[derive(Clone, Debug, Default)]
+pub struct PruneReport {
+    pub tables: Vec<(&'static str, u64)>, pub guilds: Vec<GuildId>,
+}
+impl PruneReport {
+    fn add(&mut self, table: &'static str, rows: u64) {
+        if rows == 0 { return }
+        match self.tables.iter_mut().find(|x| x.0 == table) {
+            Some(entry) => entry.1 += rows,
+            None => self.tables.push((table, rows)),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tables.is_empty() && self.guilds.is_empty()
+    }
+}
+
+struct RetentionManagerData {
+    config: ConfigManager, database: Database,
+}
+
+#[derive(Clone)]
+pub struct RetentionManager(Arc<RetentionManagerData>);
+impl RetentionManager {
+    pub(in ::core) fn new(config: ConfigManager, database: Database) -> RetentionManager {
+        RetentionManager(Arc::new(RetentionManagerData { config, database }))
+    }
+
+    /// Returns the longest verification cooldown configured globally or in any server, so
+    /// cooldowns are not lifted early by pruning `verification_cooldown`.
+    fn longest_cooldown(&self, conn: &DatabaseConnection) -> Result<u64> {
+        let config = &self.0.config;
+        let mut longest = config.get(None, ConfigKeys::VerificationCooldownSeconds)?;
+        for guild_id in conn.query(
+            "SELECT discord_guild_id FROM guild_config \
+             WHERE key = 'VerificationCooldownSeconds'", (),
+        ).get_all::<GuildId>()? {
+            let cooldown = config.get(Some(guild_id), ConfigKeys::VerificationCooldownSeconds)?;
+            longest = cmp::max(longest, cooldown);
+        }
+        Ok(longest)
+    }
+
+    fn prune_rows(
+        conn: &DatabaseConnection, table: &str, key: &str, condition: &str,
+        arg: impl ToSqlArgs + Copy, dry_run: bool,
+    ) -> Result<u64> {
+        if dry_run {
+            return conn.query(&format!("SELECT COUNT(*) FROM {} WHERE {}", table, condition), arg)
+                .get::<u64>()
+        }
+        let sql = format!("DELETE FROM {0} WHERE ({1}) IN (\
+                               SELECT {1} FROM {0} WHERE {2} LIMIT {3}\
+                           )", table, key, condition, PRUNE_BATCH_SIZE);
+        let mut total = 0;
+        loop {
+            let deleted = conn.execute(&sql, arg)?;
+            total += deleted as u64;
+            if deleted < PRUNE_BATCH_SIZE { return Ok(total) }
+        }
+    }
+
+    /// Removes rows older than their retention period, and the data of servers the bot left
+    /// more than the grace period ago. If `dry_run` is set, nothing is deleted, and the report
+    /// shows what would have been removed.
+    pub fn prune(&self, dry_run: bool) -> Result<PruneReport> {
+        let config = &self.0.config;
+        let conn = self.0.database.connect()?;
+        let now = SystemTime::now();
+        let mut report = PruneReport::default();
+
+        for &(table, key, column, setting) in AGE_TARGETS {
+            let mut retention = config.get(None, setting)?;
+            if retention == 0 { continue }
+            if table == "verification_cooldown" {
+                retention = cmp::max(retention, self.longest_cooldown(&conn)?);
+            }
+            if let Some(cutoff) = now.checked_sub(Duration::from_secs(retention)) {
+                let condition = format!("{} < ?1", column);
+                report.add(table, RetentionManager::prune_rows(&conn, table, key, &condition,
+                                                               cutoff, dry_run)?);
+            }
+        }
+
+        let grace_period = config.get(None, ConfigKeys::GuildDataGracePeriodSeconds)?;
+        if grace_period != 0 {
+            if let Some(cutoff) = now.checked_sub(Duration::from_secs(grace_period)) {
+                let departed = conn.query(
+                    "SELECT discord_guild_id FROM departed_guilds WHERE departed < ?1", cutoff,
+                ).get_all::<GuildId>()?;
+                // Servers the bot rejoins are removed from `departed_guilds` when they are
+                // created, so the table is trusted rather than the cache, which may be loading.
+                for guild_id in departed {
+                    for &(table, key) in GUILD_TARGETS {
+                        report.add(table, RetentionManager::prune_rows(
+                            &conn, table, key, "discord_guild_id = ?1", guild_id, dry_run,
+                        )?);
+                    }
+                    report.add("permissions", RetentionManager::prune_rows(
+                        &conn, "permissions", "scope_1, scope_2, id", &guild_scopes_sql(),
+                        guild_id, dry_run,
+                    )?);
+                    if !dry_run {
+                        conn.execute("DELETE FROM departed_guilds WHERE discord_guild_id = ?1",
+                                     guild_id)?;
+                    }
+                    report.guilds.push(guild_id);
+                }
+            }
+        }
+
+        Ok(report)
+    }
+    pub fn prune_and_report(&self) -> Result<()> {
+        let report = self.prune(false)?;
+        if !report.is_empty() {
+            let tables = report.tables.iter()
+                .map(|(table, rows)| format!("{} from {}", rows, table))
+                .collect::<Vec<_>>();
+            info!("Pruned old data: {} rows ({}), {} departed servers.",
+                  report.tables.iter().map(|x| x.1).sum::<u64>(), tables.join(", "),
+                  report.guilds.len());
+        }
+        Ok(())
+    }
+
+    /// Records the servers the bot was removed from while it was not running. `guilds` is the
+    /// complete list of servers on a shard, as given by its ready event.
+    pub fn on_ready(&self, shard: Option<[u64; 2]>, guilds: &[GuildId]) -> Result<()> {
+        let guilds: HashSet<GuildId> = guilds.iter().cloned().collect();
+        let on_shard = |guild_id: GuildId| match shard {
+            Some([shard_id, shard_count]) => (guild_id.0 >> 22) % shard_count == shard_id,
+            None => true,
+        };
+        let conn = self.0.database.connect()?;
+        let mut departed = HashSet::new();
+        conn.transaction(|| {
+            for &(table, _) in GUILD_TARGETS {
+                for guild_id in conn.query(
+                    &format!("SELECT DISTINCT discord_guild_id FROM {} WHERE discord_guild_id \
+                              NOT IN (SELECT discord_guild_id FROM departed_guilds)", table), (),
+                ).get_all::<GuildId>()? {
+                    // The global ban list is stored under server id 0.
+                    if guild_id.0 != 0 && on_shard(guild_id) && !guilds.contains(&guild_id) {
+                        departed.insert(guild_id);
+                    }
+                }
+            }
+            for &guild_id in &departed {
+                conn.execute(
+                    "INSERT OR IGNORE INTO departed_guilds (discord_guild_id, departed) \
+                     VALUES (?1, ?2)", (guild_id, SystemTime::now()),
+                )?;
+            }
+            Ok(())
+        })?;
+        if !departed.is_empty() {
+            info!("Found {} server(s) the bot was removed from while it was not running.",
+                  departed.len());
+        }
+        Ok(())
+    }
+    /// Records that the bot was removed from a server, starting its grace period.
+    pub fn on_guild_delete(&self, guild: GuildId) -> Result<()> {
+        self.0.database.connect()?.execute(
+            "INSERT OR IGNORE INTO departed_guilds (discord_guild_id, departed) VALUES (?1, ?2)",
+            (guild, SystemTime::now()),
+        )?;
+        Ok(())
+    }
+    /// Cancels the grace period of a server the bot has been added back to.
+    pub fn on_guild_create(&self, guild: GuildId) -> Result<()> {
+        self.0.database.connect()?.execute(
+            "DELETE FROM departed_guilds WHERE discord_guild_id = ?1", guild,
+        )?;
+        Ok(())
+    }
+}
@@ -1,7 +1,10 @@
-use chrono::Utc;
+use core::alt_detector::*;
+use core::audit::*;
+use core::bans::*;
 use core::config::*;
 use core::permissions::*;
 use core::tasks::*;
+use core::verification_log::*;
 use core::verifier::*;
 use database::*;
 use errors::*;
@@ -9,7 +12,7 @@ use parking_lot::RwLock;
 use serenity;
 use serenity::model::prelude::*;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::mem::drop;
 use std::sync::Arc;
 use std::time::{SystemTime, Duration};
@@ -50,11 +53,17 @@ pub enum SetRolesStatus {
         set_roles_error: bool, was_unverified: bool,
     },
     NotVerified,
+    Banned,
+    OnHold,
+    Expired,
+    Unconfirmed,
+    Unapproved,
 }
 
 struct RoleManagerData {
     config: ConfigManager, database: Database, verifier: Verifier,
-    tasks: TaskManager, permissions: PermissionManager,
+    tasks: TaskManager, permissions: PermissionManager, bans: BanManager,
+    alt_detector: AltDetector, audit: AuditLog, verification_log: VerificationLog,
     rule_cache: ConcurrentCache<GuildId, Arc<RwLock<VerificationRulesStatus>>>,
     update_cache: ConcurrentCache<GuildId, Arc<ConcurrentCache<(UserId, bool), Option<SystemTime>>>>,
 }
@@ -63,11 +72,13 @@ pub struct RoleManager(Arc<RoleManagerData>);
 impl RoleManager {
     pub fn new(
         config: ConfigManager, database: Database, verifier: Verifier, tasks: TaskManager,
-        permissions: PermissionManager,
+        permissions: PermissionManager, bans: BanManager, alt_detector: AltDetector,
+        audit: AuditLog, verification_log: VerificationLog,
     ) -> RoleManager {
         let db_ref_update = database.clone();
         RoleManager(Arc::new(RoleManagerData {
-            config, database, verifier, tasks, permissions,
+            config, database, verifier, tasks, permissions, bans, alt_detector, audit,
+            verification_log,
             rule_cache: ConcurrentCache::new(|_|
                 Ok(Arc::new(RwLock::new(VerificationRulesStatus::NotCompiled)))
             ),
@@ -162,7 +173,7 @@ impl RoleManager {
         Ok(())
     }
 
-    fn refresh_cache(&self, guild: GuildId) -> Result<()> {
+    pub(in ::core) fn refresh_cache(&self, guild: GuildId) -> Result<()> {
         let cache = self.0.rule_cache.read(&guild)?;
         self.update_rules(&cache, guild, true)
     }
@@ -235,16 +246,41 @@ impl RoleManager {
         })
     }
 
+    /// Determines the roles assigned for a set of Roblox accounts. A rule matches if it matches
+    /// for any of the accounts.
     pub fn get_assigned_roles(
-        &self, guild: GuildId, roblox_id: RobloxUserID
+        &self, guild: GuildId, roblox_ids: &[RobloxUserID]
+    ) -> Result<Vec<AssignedRole>> {
+        self.evaluate_roles(guild, roblox_ids, None)
+    }
+    /// Determines the roles assigned for a set of Roblox accounts. If `ban_listed` is set, it is
+    /// used as the result of the `banned()` term rather than checking each account.
+    fn evaluate_roles(
+        &self, guild: GuildId, roblox_ids: &[RobloxUserID], ban_listed: Option<bool>,
     ) -> Result<Vec<AssignedRole>> {
         let lock = self.0.rule_cache.read(&guild)?;
         self.update_rules(&lock, guild, false)?;
         let read = lock.read();
         Ok(match *read {
             VerificationRulesStatus::Compiled(ref rule_set, ref role_info) => {
+                let mut results: HashMap<&str, RuleResult> = HashMap::new();
+                for &roblox_id in roblox_ids {
+                    let is_ban_listed = match ban_listed {
+                        Some(ban_listed) => ban_listed,
+                        None => self.0.bans.is_banned(Some(guild), BanTarget::Roblox(roblox_id))?,
+                    };
+                    for (rule_name, is_assigned) in rule_set.verify(roblox_id, is_ban_listed)? {
+                        let combined = match (results.get(rule_name), is_assigned) {
+                            (Some(RuleResult::True), _) | (_, RuleResult::True) => RuleResult::True,
+                            (Some(RuleResult::Error), _) | (_, RuleResult::Error) =>
+                                RuleResult::Error,
+                            _ => RuleResult::False,
+                        };
+                        results.insert(rule_name, combined);
+                    }
+                }
                 let mut vec = Vec::new();
-                for (rule_name, is_assigned) in rule_set.verify(roblox_id)? {
+                for (rule_name, is_assigned) in results {
                     let role_id = role_info[rule_name];
                     vec.push(AssignedRole {
                         rule: rule_name.to_string(), role_id, is_assigned,
@@ -269,9 +305,14 @@ impl RoleManager {
             self.0.config.get(None, ConfigKeys::SetNickname)? &&
             !self.0.permissions.get_user_perms(guild, discord_id)?
                 .contains(BotPermission::BypassNicknameUpdate);
+        let is_banned = match roblox_id {
+            Some(roblox_id) => self.is_member_banned(guild, discord_id, roblox_id)?,
+            None => false,
+        };
 
         if can_access_user && do_set_nickname {
-            let target_nickname = if let Some(roblox_id) = roblox_id {
+            // Banned members are not named after their Roblox account.
+            let target_nickname = if let (Some(roblox_id), false) = (roblox_id, is_banned) {
                 Some(format!("{}\u{17B5}", roblox_id.lookup_username()?))
             } else {
                 None
@@ -285,16 +326,43 @@ impl RoleManager {
         let mut determine_roles_error = false;
         let mut set_roles_error = false;
         let mut was_unverified = false;
-        if let Some(roblox_id) = roblox_id {
-            for role in self.get_assigned_roles(guild, roblox_id)? {
+        let mut added_roles = Vec::new();
+        let mut removed_roles = Vec::new();
+        let is_held = roblox_id.is_some() && self.0.alt_detector.is_held(guild, discord_id)?;
+        let is_expired = roblox_id.is_some() && self.is_verification_expired(guild, discord_id)?;
+        let is_unconfirmed = roblox_id.is_some() &&
+            !self.0.config.get(Some(guild), ConfigKeys::TrustImportedVerifications)? &&
+            self.0.verifier.is_verification_imported(discord_id)?;
+        let is_unapproved = match roblox_id {
+            Some(roblox_id) => !self.0.verifier.is_link_approved(guild, discord_id, roblox_id)?,
+            None => false,
+        };
+        let is_untrusted = is_banned || is_held || is_expired || is_unconfirmed || is_unapproved;
+        if let (Some(roblox_id), false) = (roblox_id, is_untrusted) {
+            let mut roblox_ids = vec![roblox_id];
+            if self.0.config.get(Some(guild), ConfigKeys::MatchAnyLinkedAccount)? {
+                for account in self.0.verifier.get_linked_accounts(discord_id)? {
+                    if account.roblox_id != roblox_id &&
+                       self.0.verifier.is_link_approved(guild, discord_id, account.roblox_id)? {
+                        roblox_ids.push(account.roblox_id);
+                    }
+                }
+            }
+            for role in self.get_assigned_roles(guild, &roblox_ids)? {
                 match role.is_assigned {
                     RuleResult::True  => if !member.roles.contains(&role.role_id) {
                         trace!("Adding role to {}: {}", member.distinct(), role.role_id.0);
-                        set_roles_error |= member.add_role(role.role_id).is_err();
+                        match member.add_role(role.role_id) {
+                            Ok(()) => added_roles.push(role.role_id.0),
+                            Err(_) => set_roles_error = true,
+                        }
                     },
                     RuleResult::False => if member.roles.contains(&role.role_id) {
                         trace!("Removing role from {}: {}", member.distinct(), role.role_id.0);
-                        set_roles_error |= member.remove_role(role.role_id).is_err();
+                        match member.remove_role(role.role_id) {
+                            Ok(()) => removed_roles.push(role.role_id.0),
+                            Err(_) => set_roles_error = true,
+                        }
                     },
                     RuleResult::Error => {
                         determine_roles_error = true;
@@ -302,24 +370,180 @@ impl RoleManager {
                 }
             }
         } else {
+            let ban_roles = match roblox_id {
+                Some(roblox_id) if is_banned => match self.get_ban_roles(guild, roblox_id) {
+                    Ok(roles) => roles,
+                    Err(_) => {
+                        determine_roles_error = true;
+                        Vec::new()
+                    }
+                },
+                _ => Vec::new(),
+            };
+
             let config = self.get_configuration(guild)?;
             for (_, role) in config {
                 if let Some(id) = role.role_id {
-                    if member.roles.contains(&id) {
+                    if ban_roles.contains(&id) {
+                        if !member.roles.contains(&id) {
+                            trace!("Adding role to {}: {}", member.distinct(), id.0);
+                            match member.add_role(id) {
+                                Ok(()) => added_roles.push(id.0),
+                                Err(_) => set_roles_error = true,
+                            }
+                        }
+                    } else if member.roles.contains(&id) {
                         trace!("Removing role from {}: {}", member.distinct(), id.0);
-                        set_roles_error |= member.remove_role(id).is_err();
+                        match member.remove_role(id) {
+                            Ok(()) => removed_roles.push(id.0),
+                            Err(_) => set_roles_error = true,
+                        }
                         was_unverified = true;
                     }
                 }
             }
         }
 
+        if !added_roles.is_empty() || !removed_roles.is_empty() {
+            let reason = if is_banned {
+                "banned"
+            } else if is_held {
+                "on_hold"
+            } else if is_expired {
+                "expired"
+            } else if is_unconfirmed {
+                "unconfirmed_import"
+            } else if is_unapproved {
+                "unapproved"
+            } else if roblox_id.is_none() {
+                "unverified"
+            } else {
+                "rules"
+            };
+            self.0.audit.record(AuditEventType::RoleChange, Some(guild), None, Some(discord_id),
+                                roblox_id, json!({
+                                    "added": added_roles, "removed": removed_roles,
+                                    "reason": reason,
+                                }));
+
+            let mention_list = |roles: &[u64]| if roles.is_empty() {
+                "*(none)*".to_owned()
+            } else {
+                roles.iter().map(|x| format!("<@&{}>", x)).collect::<Vec<_>>().join(", ")
+            };
+            self.0.verification_log.log(
+                Some(guild), LogEvent::RoleChange, Some(discord_id), roblox_id,
+                &format!("The roles of {} were updated.\nAdded: {}\nRemoved: {}",
+                         member.distinct(), mention_list(&added_roles),
+                         mention_list(&removed_roles)),
+            );
+        }
+
+        if is_banned && !set_roles_error {
+            return Ok(SetRolesStatus::Banned)
+        }
+        if is_held && !set_roles_error {
+            return Ok(SetRolesStatus::OnHold)
+        }
+        if is_expired && !set_roles_error {
+            return Ok(SetRolesStatus::Expired)
+        }
+        if is_unconfirmed && !set_roles_error {
+            return Ok(SetRolesStatus::Unconfirmed)
+        }
+        if is_unapproved && !set_roles_error {
+            return Ok(SetRolesStatus::Unapproved)
+        }
         Ok(SetRolesStatus::Success {
             nickname_admin_error: !can_access_user && do_set_nickname,
             determine_roles_error, set_roles_error, was_unverified,
         })
     }
 
+    /// Returns the roles given to a banned member by rules using the `banned()` term. These are
+    /// the roles the rules give them while banned, but not otherwise.
+    fn get_ban_roles(&self, guild: GuildId, roblox_id: RobloxUserID) -> Result<Vec<RoleId>> {
+        let unbanned = self.evaluate_roles(guild, &[roblox_id], Some(false))?;
+        let mut roles = Vec::new();
+        for role in self.evaluate_roles(guild, &[roblox_id], Some(true))? {
+            let was_assigned = unbanned.iter()
+                .any(|x| x.rule == role.rule && x.is_assigned == RuleResult::True);
+            if role.is_assigned == RuleResult::True && !was_assigned {
+                roles.push(role.role_id);
+            }
+        }
+        Ok(roles)
+    }
+    /// Checks whether a member, or any Roblox account linked to them, is on this server's ban
+    /// list or the global ban list.
+    fn is_member_banned(
+        &self, guild: GuildId, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<bool> {
+        let bans = &self.0.bans;
+        if bans.is_banned(Some(guild), BanTarget::Discord(discord_id))? ||
+           bans.is_banned(Some(guild), BanTarget::Roblox(roblox_id))? {
+            return Ok(true)
+        }
+        for account in self.0.verifier.get_linked_accounts(discord_id)? {
+            if bans.is_banned(Some(guild), BanTarget::Roblox(account.roblox_id))? {
+                return Ok(true)
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns when a user's verification expires in a server, if the server has a maximum
+    /// verification age set.
+    fn verification_expires(
+        &self, guild: GuildId, discord_id: UserId,
+    ) -> Result<Option<SystemTime>> {
+        let max_age = self.0.config.get(Some(guild), ConfigKeys::VerificationMaxAgeSeconds)?;
+        if max_age == 0 {
+            return Ok(None)
+        }
+        Ok(self.0.verifier.get_verification_time(discord_id)?
+            .and_then(|verified_at| verified_at.checked_add(Duration::from_secs(max_age))))
+    }
+    fn is_verification_expired(&self, guild: GuildId, discord_id: UserId) -> Result<bool> {
+        Ok(self.verification_expires(guild, discord_id)?
+            .map_or(false, |expires| expires <= SystemTime::now()))
+    }
+
+    /// Returns the servers the bot shares with a user.
+    pub fn mutual_guilds(&self, discord_id: UserId) -> Result<Vec<GuildId>> {
+        let cache = serenity::CACHE.read();
+        Ok(cache.guilds.iter()
+            .filter(|(_, guild)| guild.read().members.contains_key(&discord_id))
+            .map(|(&guild_id, _)| guild_id)
+            .collect())
+    }
+    /// Assigns roles to a user in every server they share with the bot, other than `except`.
+    pub fn assign_roles_all(
+        &self, discord_id: UserId, roblox_id: Option<RobloxUserID>, except: Option<GuildId>,
+    ) -> Result<Vec<(GuildId, Result<SetRolesStatus>)>> {
+        let mut results = Vec::new();
+        for guild_id in self.mutual_guilds(discord_id)? {
+            if Some(guild_id) != except {
+                let result = self.assign_roles(guild_id, discord_id, roblox_id);
+                if let Err(e) = &result {
+                    debug!("Could not assign roles to {} in {}: {}", discord_id, guild_id, e);
+                }
+                results.push((guild_id, result));
+            }
+        }
+        Ok(results)
+    }
+    /// Updates a user's roles in every other server they share with the bot in the background.
+    pub fn propagate_roles(
+        &self, guild_id: GuildId, discord_id: UserId, roblox_id: Option<RobloxUserID>,
+    ) {
+        let roles = self.clone();
+        self.0.tasks.dispatch_task(move |_| {
+            roles.assign_roles_all(discord_id, roblox_id, Some(guild_id))?;
+            Ok(())
+        })
+    }
+
     pub fn update_user(
         &self, guild: GuildId, discord_id: UserId, update_unverified: bool,
     ) -> Result<SetRolesStatus> {
@@ -390,23 +614,19 @@ impl RoleManager {
     }
 
     fn on_unverified_update(
-        result: SetRolesStatus, user_id: UserId, 
-        unverified_msg: Option<String>, log_channel: Option<u64>,
+        &self, result: SetRolesStatus, guild_id: GuildId, user_id: UserId,
+        unverified_msg: Option<String>,
     ) -> Result<()> {
         if let SetRolesStatus::Success { was_unverified: true, .. } = result {
+            let discord_username = user_id.to_user()?.tag();
+            self.0.verification_log.log(
+                Some(guild_id), LogEvent::LegacyUnverify, Some(user_id), None,
+                &format!("{} (`{}`) has been unverified due to having been a legacy \
+                          verification.", discord_username, user_id.0),
+            );
             if let Some(unverified_msg) = unverified_msg {
                 user_id.create_dm_channel()?.send_message(|m| m.content(unverified_msg))?;
             }
-            if let Some(log_channel_id) = log_channel {
-                let log_channel_id = ChannelId(log_channel_id);
-                let discord_username = user_id.to_user()?.tag();
-                log_channel_id.send_message(|m|
-                    m.content(format_args!("`[{}]` 🇫 {} (`{}`) has been unverified due to having \
-                                            been a legacy verification.",
-                                           Utc::now().format("%H:%M:%S"),
-                                           discord_username, user_id.0))
-                )?;
-            }
         }
         Ok(())
     }
@@ -420,8 +640,6 @@ impl RoleManager {
                 let unverified_msg =
                     self.0.config.get(Some(guild_id),
                                       ConfigKeys::EnableAutoUpdateUnverifiedMessage)?;
-                let log_channel = 
-                    self.0.config.get(None, ConfigKeys::GlobalVerificationLogChannel)?;
                 let roles = self.clone();
                 self.0.tasks.dispatch_task(move |_| {
                     let result = roles.update_user_with_cooldown(
@@ -429,8 +647,8 @@ impl RoleManager {
                     );
                     if let Ok(result) = &result {
                         catch_error(||
-                            Self::on_unverified_update(
-                                *result, user_id, unverified_msg, log_channel,
+                            roles.on_unverified_update(
+                                *result, guild_id, user_id, unverified_msg,
                             ).drop_nonfatal()
                         ).ok();
                     }
@@ -455,6 +673,123 @@ impl RoleManager {
         Ok(())
     }
 
+    fn send_expiry_notice(
+        &self, guild_id: GuildId, discord_id: UserId, expires: SystemTime, is_expired: bool,
+    ) -> Result<()> {
+        let guild_name = match guild_id.to_guild_cached() {
+            Some(guild) => guild.read().name.clone(),
+            None => format!("server #{}", guild_id.0),
+        };
+        let prefix = self.0.config.get(None, ConfigKeys::CommandPrefix)?;
+        let message = if is_expired {
+            format!("Your verification in **{}** has expired, and your roles there have been \
+                     removed. Please use the '{}verify' command to verify again.",
+                    guild_name, prefix)
+        } else {
+            format!("Your verification in **{}** will expire in {}. Please use the '{}verify' \
+                     command with the same Roblox account before then to keep your roles.",
+                    guild_name, util::english_time_diff(SystemTime::now(), expires), prefix)
+        };
+        discord_id.create_dm_channel()?.send_message(|m| m.content(message))?;
+        Ok(())
+    }
+    fn check_guild_expiry(&self, guild_id: GuildId) -> Result<()> {
+        let max_age = self.0.config.get(Some(guild_id), ConfigKeys::VerificationMaxAgeSeconds)?;
+        if max_age == 0 {
+            return Ok(())
+        }
+        let warning =
+            self.0.config.get(Some(guild_id), ConfigKeys::VerificationExpiryWarningSeconds)?;
+        let now = SystemTime::now();
+        let max_age = Duration::from_secs(max_age);
+        let threshold = match now.checked_sub(max_age - Duration::from_secs(warning).min(max_age)) {
+            Some(threshold) => threshold,
+            None => return Ok(()),
+        };
+
+        let members: HashSet<UserId> = match guild_id.to_guild_cached() {
+            Some(guild) => guild.read().members.keys().cloned().collect(),
+            None => return Ok(()),
+        };
+
+        let conn = self.0.database.connect()?;
+        let candidates = conn.query(
+            "SELECT i.discord_user_id, i.roblox_user_id, i.last_updated, n.is_expired \
+             FROM discord_user_info i \
+             LEFT JOIN verification_expiry_notices n \
+                 ON n.discord_guild_id = ?1 AND n.discord_user_id = i.discord_user_id \
+                AND n.verified_at = i.last_updated \
+             WHERE i.roblox_user_id IS NOT NULL AND i.last_updated <= ?2",
+            (guild_id, threshold),
+        ).get_all::<(UserId, RobloxUserID, SystemTime, Option<bool>)>()?;
+        for (discord_id, roblox_id, verified_at, notice) in candidates {
+            let expires = match verified_at.checked_add(max_age) {
+                Some(expires) => expires,
+                None => continue,
+            };
+            let is_expired = expires <= now;
+            if notice == Some(true) || notice == Some(is_expired) ||
+                !members.contains(&discord_id) {
+                continue
+            }
+
+            // The notice is recorded first, so a failure below cannot cause it to be repeated.
+            conn.execute(
+                "REPLACE INTO verification_expiry_notices (\
+                    discord_guild_id, discord_user_id, verified_at, is_expired\
+                ) VALUES (?1, ?2, ?3, ?4)", (guild_id, discord_id, verified_at, is_expired),
+            )?;
+
+            if is_expired {
+                debug!("Verification of {} has expired in {}.", discord_id, guild_id);
+                self.0.audit.record(AuditEventType::Expire, Some(guild_id), None, Some(discord_id),
+                                    Some(roblox_id),
+                                    json!({ "verified_at": util::time_to_i64(verified_at) }));
+                let result = self.assign_roles(guild_id, discord_id, Some(roblox_id));
+                if let Err(e) = result.drop_nonfatal() {
+                    warn!("Could not remove roles from {} in {} after their verification \
+                           expired: {}", discord_id, guild_id, e);
+                }
+                let discord_username = match discord_id.to_user_cached() {
+                    Some(user) => user.read().tag(),
+                    None => format!("(discord uid #{})", discord_id.0),
+                };
+                self.0.verification_log.log(
+                    Some(guild_id), LogEvent::Unverify, Some(discord_id), Some(roblox_id),
+                    &format!("{} (`{}`) has been unverified in this server as their \
+                              verification expired.", discord_username, discord_id.0),
+                );
+            }
+            if let Err(e) = self.send_expiry_notice(guild_id, discord_id, expires, is_expired) {
+                debug!("Could not send verification expiry notice to {}: {}", discord_id, e);
+            }
+        }
+        Ok(())
+    }
+    /// Removes alt account flags that have expired, and updates the roles they were holding.
+    pub fn check_alt_flag_expiry(&self) -> Result<()> {
+        for (guild_id, discord_id) in self.0.alt_detector.remove_expired_flags()? {
+            debug!("Alt account flag of {} in {} has expired.", discord_id, guild_id);
+            let roblox_id = self.0.verifier.get_verified_roblox_user(discord_id)?;
+            if let Err(e) = self.assign_roles(guild_id, discord_id, roblox_id).drop_nonfatal() {
+                debug!("Could not update roles of {} in {} after their alt account flag \
+                        expired: {}", discord_id, guild_id, e);
+            }
+        }
+        Ok(())
+    }
+    /// Warns users whose verification is about to expire in a server, and removes the roles of
+    /// users whose verification has expired.
+    pub fn check_verification_expiry(&self) -> Result<()> {
+        let guilds: Vec<GuildId> = serenity::CACHE.read().guilds.keys().cloned().collect();
+        for guild_id in guilds {
+            if let Err(e) = self.check_guild_expiry(guild_id) {
+                warn!("Could not check verification expiry in {}: {}", guild_id, e);
+            }
+        }
+        Ok(())
+    }
+
     pub fn on_cleanup_tick(&self) {
         let outdated_threshold = SystemTime::now() - Duration::from_secs(60 * 60 * 4);
         self.0.update_cache.for_each(|cache| {
@@ -1,8 +1,11 @@
 use database::*;
 use core::config::*;
 use core::delete_service::DeleteService;
+use core::oauth::oauth_configured;
+use core::web_server::{public_url, PUSH_VERIFY_PATH};
 use errors::*;
 use serenity::model::prelude::*;
+use std::fmt::Write;
 use std::sync::Arc;
 use std::time::{SystemTime, Duration};
 use util::ConcurrentCache;
@@ -103,20 +106,71 @@ impl VerificationChannelManager {
         Ok(())
     }
 
-    pub fn verify_instructions(&self) -> Result<String> {
-        if let Some(place_id) = self.0.config.get(None, ConfigKeys::PlaceID)? {
-            Ok(format!("To verify your Roblox account with your Discord account, please \
-                        follow these instructions:\n\
-                        • Join the place at <https://roblox.com/games/{}> using the account \
-                          you want to verify as.\n\
-                        • Copy the command it shows in the oval box into this channel. It should \
-                          look something like `!verify YourUsername ABCDEF`.",
-                       place_id))
-        } else {
-            error!("No place ID set! Please upload the place file to Roblox, and use \
-                    \"set_global place_id [your place id]\".");
-            cmd_error!("No place ID set. Please ask the bot owner to fix this.")
+    /// Checks whether the web server is configured to receive pairing codes from the
+    /// verification place.
+    pub fn push_configured(&self) -> Result<bool> {
+        Ok(public_url(&self.0.config, PUSH_VERIFY_PATH)?.is_some())
+    }
+
+    pub fn verify_instructions(&self, guild_id: Option<GuildId>) -> Result<String> {
+        let allow_place = self.0.config.get(guild_id, ConfigKeys::AllowPlaceVerification)?;
+        let allow_oauth =
+            self.0.config.get(guild_id, ConfigKeys::AllowOAuthVerification)? &&
+            oauth_configured(&self.0.config)?;
+        let allow_push =
+            self.0.config.get(guild_id, ConfigKeys::AllowPushVerification)? &&
+            self.push_configured()?;
+        let allow_profile =
+            self.0.config.get(guild_id, ConfigKeys::AllowProfileVerification)?;
+
+        let mut methods = Vec::new();
+        if allow_place || allow_push {
+            if let Some(place_id) = self.0.config.get(None, ConfigKeys::PlaceID)? {
+                if allow_place {
+                    methods.push(("joining the verification place", format!(
+                        "• Join the place at <https://roblox.com/games/{}> using the account \
+                           you want to verify as.\n\
+                         • Copy the command it shows in the oval box into this channel. It \
+                           should look something like `!verify YourUsername ABCDEF`.",
+                        place_id)));
+                }
+                if allow_push {
+                    methods.push(("entering a pairing code in the verification place", format!(
+                        "• Use `!verify_in_game YourUsername` in this channel to get a pairing \
+                           code.\n\
+                         • Join the place at <https://roblox.com/games/{}> using that account, \
+                           and type the pairing code into the chat.",
+                        place_id)));
+                }
+            } else {
+                error!("No place ID set! Please upload the place file to Roblox, and use \
+                        \"set_global place_id [your place id]\".");
+                cmd_error!("No place ID set. Please ask the bot owner to fix this.")
+            }
+        }
+        if allow_oauth {
+            methods.push(("logging in to Roblox",
+                "• Use `!verify_oauth` in this channel, and open the link you are sent in a \
+                   direct message.\n\
+                 • Log in to Roblox using the account you want to verify as.".to_owned()));
+        }
+        if allow_profile {
+            methods.push(("using your Roblox profile",
+                "• Use `!verify_profile YourUsername` in this channel, and add the phrase it \
+                   gives you to your Roblox profile description.\n\
+                 • Use `!verify_profile YourUsername` again once you have saved your \
+                   profile.".to_owned()));
+        }
+
+        cmd_ensure!(!methods.is_empty(), "Verification is disabled{}.",
+                    if guild_id.is_some() { " on this server" } else { "" });
+        let mut instructions =
+            format!("To verify your Roblox account with your Discord account, please follow \
+                     these instructions:\n{}", methods[0].1);
+        for (name, method) in &methods[1..] {
+            write!(instructions, "\n\nYou can also verify by {} instead:\n{}", name, method)?;
         }
+        Ok(instructions)
     }
     fn intro_message(&self, guild_id: GuildId) -> Result<String> {
         let verify_intro =
@@ -135,7 +189,7 @@ impl VerificationChannelManager {
 
         Ok(format!("{}{}{}{}{}",
                    verify_intro.unwrap_or_else(String::new), intro_space,
-                   self.verify_instructions()?,
+                   self.verify_instructions(Some(guild_id))?,
                    footer_space, verify_footer.unwrap_or_else(String::new)))
     }
     pub fn setup_check(&self, _: GuildId, channel_id: ChannelId) -> Result<()> {
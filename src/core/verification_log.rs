@@ -0,0 +1,133 @@
+use chrono::Utc;
+use core::config::*;
+use errors::*;
+use roblox::RobloxUserID;
+use serenity::model::prelude::*;
+use std::sync::Arc;
+
+macro_rules! log_events {
+    ($($name:ident => ($str:expr, $title:expr, $colour:expr)),* $(,)*) => {
+        #[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+        pub enum LogEvent {
+            $($name,)*
+        }
+        impl LogEvent {
+            pub const ALL: &'static [LogEvent] = &[$(LogEvent::$name,)*];
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $(LogEvent::$name => $str,)*
+                }
+            }
+            pub fn from_name(name: &str) -> Option<LogEvent> {
+                match name {
+                    $($str => Some(LogEvent::$name),)*
+                    _ => None,
+                }
+            }
+            fn title(self) -> &'static str {
+                match self {
+                    $(LogEvent::$name => $title,)*
+                }
+            }
+            fn colour(self) -> u32 {
+                match self {
+                    $(LogEvent::$name => $colour,)*
+                }
+            }
+        }
+    }
+}
+log_events! {
+    Verify         => ("verify", "Verification", 0x2ECC71),
+    Failure        => ("failure", "Verification failed", 0xE74C3C),
+    Reverify       => ("reverify", "Reverification", 0xF1C40F),
+    Unverify       => ("unverify", "Unverification", 0xE67E22),
+    RoleChange     => ("role_change", "Roles updated", 0x3498DB),
+    LegacyUnverify => ("legacy_unverify", "Legacy verification removed", 0x95A5A6),
+}
+
+/// Parses a comma separated list of log event names, such as the value of
+/// `VerificationLogEvents`. Unknown names are ignored.
+pub fn parse_log_events(list: &str) -> Vec<LogEvent> {
+    list.split(',').filter_map(|x| LogEvent::from_name(x.trim())).collect()
+}
+
+struct VerificationLogData {
+    config: ConfigManager,
+}
+
+#[derive(Clone)]
+pub struct VerificationLog(Arc<VerificationLogData>);
+impl VerificationLog {
+    pub(in ::core) fn new(config: ConfigManager) -> VerificationLog {
+        VerificationLog(Arc::new(VerificationLogData { config }))
+    }
+
+    fn send(
+        channel: ChannelId, event: LogEvent, discord_id: Option<UserId>,
+        roblox_id: Option<RobloxUserID>, message: &str,
+    ) -> Result<()> {
+        channel.send_message(|m| m.embed(|e| {
+            let mut e = e.title(event.title()).description(message)
+                .colour(event.colour()).timestamp(Utc::now().to_rfc3339());
+            if let Some(discord_id) = discord_id {
+                e = e.field("Discord account",
+                            format!("<@{}> (`{}`)", discord_id.0, discord_id.0), true);
+            }
+            if let Some(roblox_id) = roblox_id {
+                e = e.field("Roblox account",
+                            format!("[Profile](https://www.roblox.com/users/{}/profile) (`{}`)",
+                                    roblox_id.0, roblox_id.0), true);
+            }
+            e
+        }))?;
+        Ok(())
+    }
+
+    fn try_log_global(
+        &self, event: LogEvent, discord_id: Option<UserId>, roblox_id: Option<RobloxUserID>,
+        message: &str,
+    ) -> Result<()> {
+        if event != LogEvent::RoleChange {
+            let config = &self.0.config;
+            if let Some(channel) = config.get(None, ConfigKeys::GlobalVerificationLogChannel)? {
+                VerificationLog::send(ChannelId(channel), event, discord_id, roblox_id, message)?;
+            }
+        }
+        Ok(())
+    }
+    fn try_log_guild(
+        &self, guild_id: GuildId, event: LogEvent, discord_id: Option<UserId>,
+        roblox_id: Option<RobloxUserID>, message: &str,
+    ) -> Result<()> {
+        let config = &self.0.config;
+        if let Some(channel) = config.get(Some(guild_id), ConfigKeys::VerificationLogChannel)? {
+            let events = config.get(Some(guild_id), ConfigKeys::VerificationLogEvents)?;
+            if parse_log_events(&events).contains(&event) {
+                VerificationLog::send(ChannelId(channel), event, discord_id, roblox_id, message)?;
+            }
+        }
+        Ok(())
+    }
+    /// Logs a verification event to the global log channel, and to the log channel of the
+    /// server it happened in if that server logs this type of event.
+    ///
+    /// Role changes are only logged to server log channels.
+    pub fn log(
+        &self, guild_id: Option<GuildId>, event: LogEvent, discord_id: Option<UserId>,
+        roblox_id: Option<RobloxUserID>, message: &str,
+    ) {
+        // A failure to send to one channel should not prevent sending to the other.
+        if let Err(e) = self.try_log_global(event, discord_id, roblox_id, message) {
+            warn!("Could not send {} event to the global verification log: {}",
+                  event.name(), e);
+        }
+        if let Some(guild_id) = guild_id {
+            if let Err(e) = self.try_log_guild(guild_id, event, discord_id, roblox_id, message) {
+                warn!("Could not send {} event to the verification log of {}: {}",
+                      event.name(), guild_id, e);
+            }
+        }
+    }
+}
@@ -1,52 +1,55 @@
 use constant_time_eq::constant_time_eq;
+use core::bans::*;
 use core::config::*;
+use core::import::*;
 use database::*;
 use errors::*;
 use hmac::{Hmac, Mac, NewMac};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use rand::{RngCore, rngs::OsRng};
 use roblox::*;
+use serde_json;
 use serenity::model::prelude::*;
 use sha2::Sha256;
+use std::any::Any;
+use std::cmp;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter, Write, Result as FmtResult};
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use util;
 use util::MutexSet;
 
-const TOKEN_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
-const TOKEN_VERSION: u32 = 1;
+const TOKEN_CHARS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const TOKEN_CHARS_UNCONFUSABLE: &str = "ABCDEFGHJKMNPQRSTUVWXYZ";
+const TOKEN_VERSION: u32 = 2;
 const HISTORY_COUNT: u32 = 5;
 
+const PROFILE_PHRASE_WORDS: usize = 6;
+const PROFILE_PHRASE_EXPIRY_SECS: u64 = 60 * 60;
+const PROFILE_PHRASE_WORD_LIST: &[&str] = &[
+    "apple", "banana", "basket", "bridge", "candle", "canyon", "carrot", "castle",
+    "cloud", "comet", "copper", "desert", "dragon", "feather", "forest", "garden",
+    "glacier", "guitar", "hammer", "harbor", "island", "jacket", "kettle", "ladder",
+    "lantern", "lemon", "meadow", "mirror", "mountain", "needle", "ocean", "orange",
+    "paddle", "parrot", "pebble", "pepper", "pillow", "planet", "pocket", "puzzle",
+    "rabbit", "rainbow", "river", "rocket", "saddle", "sailor", "shadow", "silver",
+    "spider", "spring", "summer", "sunset", "thunder", "tiger", "tomato", "tunnel",
+    "turtle", "valley", "violin", "wagon", "walnut", "window", "winter", "yellow",
+];
+
+const PAIRING_CODE_LENGTH: u32 = 8;
+const PAIRING_CODE_EXPIRY_SECS: u64 = 60 * 10;
+
+pub const MIN_TOKEN_LENGTH: u32 = 6;
+pub const MAX_TOKEN_LENGTH: u32 = 16;
+
 // TODO: Add caching to this module. Extensive caching.
 
 #[derive(Clone, Hash, Debug, PartialOrd, Ord)]
-struct Token([u8; 6]);
-impl Token {
-    fn from_arr(arr: [u8; 6]) -> Token {
-        Token(arr)
-    }
-
-    fn from_str(token: &str) -> Result<Token> {
-        let token = token.as_bytes();
-        cmd_ensure!(token.len() == 6,
-                    "Verification token must be exactly 6 characters. Please check the code \
-                     you entered and try again");
-
-        let mut chars = [0u8; 6];
-        for i in 0..6 {
-            let byte = token[i];
-            if byte >= b'A' && byte <= b'Z' {
-                chars[i] = byte
-            } else if byte >= b'a' && byte <= b'z' {
-                chars[i] = byte - b'a' + b'A'
-            } else {
-                cmd_error!("Verification tokens may only contain letters. Please check the code \
-                            you entered and try again.")
-            }
-        }
-        Ok(Token(chars))
-    }
-}
+struct Token(Vec<u8>);
 impl PartialEq for Token {
     fn eq(&self, other: &Token) -> bool {
         constant_time_eq(&self.0, &other.0)
@@ -62,13 +65,58 @@ impl Display for Token {
     }
 }
 
+#[derive(Clone, Eq, PartialEq, Debug)]
+struct TokenFormat {
+    time_increment: u32, token_length: u32, token_chars: String,
+}
+impl TokenFormat {
+    fn from_config(config: &ConfigManager) -> Result<TokenFormat> {
+        let time_increment = config.get(None, ConfigKeys::TokenValiditySeconds)?;
+        let token_length = config.get(None, ConfigKeys::TokenLength)?;
+        ensure!(token_length >= MIN_TOKEN_LENGTH && token_length <= MAX_TOKEN_LENGTH,
+                "Configured token length is out of range.");
+        let token_chars = if config.get(None, ConfigKeys::TokenExcludeConfusable)? {
+            TOKEN_CHARS_UNCONFUSABLE
+        } else {
+            TOKEN_CHARS
+        }.to_owned();
+        Ok(TokenFormat { time_increment, token_length, token_chars })
+    }
+}
+
 struct TokenParameters {
-    id: u64, key: Vec<u8>, time_increment: u32, version: u32,
+    id: u64, key: Vec<u8>, version: u32, format: TokenFormat,
+    activates_at: SystemTime, is_scheduled: bool, key_nonce: Option<Vec<u8>>,
 }
 impl TokenParameters {
     fn add_config<'a>(&self, config: &mut Vec<LuaConfigEntry<'a>>) {
         config.push(LuaConfigEntry::new("shared_key", true, self.key.clone()));
-        config.push(LuaConfigEntry::new("time_increment", false, self.time_increment));
+        config.push(LuaConfigEntry::new("time_increment", false, self.format.time_increment));
+        config.push(LuaConfigEntry::new("token_version", false, self.version));
+        config.push(LuaConfigEntry::new("token_length", false, self.format.token_length));
+        config.push(LuaConfigEntry::new("token_chars", false, self.format.token_chars.clone()));
+    }
+
+    fn parse_token(&self, token: &str) -> Option<Token> {
+        let token = token.to_ascii_uppercase().into_bytes();
+        if token.len() == self.format.token_length as usize &&
+           token.iter().all(|c| self.format.token_chars.as_bytes().contains(c)) {
+            Some(Token(token))
+        } else {
+            None
+        }
+    }
+    fn check_format(&self, token: &str) -> Result<()> {
+        cmd_ensure!(token.len() == self.format.token_length as usize,
+                    "Verification token must be exactly {} characters. Please check the code \
+                     you entered and try again.", self.format.token_length);
+        cmd_ensure!(token.bytes().all(|c| c.is_ascii_alphabetic()),
+                    "Verification tokens may only contain letters. Please check the code \
+                     you entered and try again.");
+        cmd_ensure!(self.parse_token(token).is_some(),
+                    "Verification tokens only contain the letters `{}`. Please check the code \
+                     you entered and try again.", self.format.token_chars);
+        Ok(())
     }
 
     fn sha256_token(&self, data: &str) -> Token {
@@ -77,36 +125,56 @@ impl TokenParameters {
         let result = mac.finalize();
         let code = result.into_bytes();
 
-        let mut accum = 0;
-        for i in 0..6 {
-            accum *= 256;
-            accum += code[i] as u64;
-        }
-
-        let mut chars = [0u8; 6];
-        for i in 0..6 {
-            chars[i] = TOKEN_CHARS[(accum % TOKEN_CHARS.len() as u64) as usize];
-            accum /= TOKEN_CHARS.len() as u64;
+        let token_chars = self.format.token_chars.as_bytes();
+        let char_count = token_chars.len() as u64;
+        let mut chars = Vec::new();
+        if self.version == 1 {
+            let mut accum = 0;
+            for i in 0..6 {
+                accum *= 256;
+                accum += code[i] as u64;
+            }
+            for _ in 0..self.format.token_length {
+                chars.push(token_chars[(accum % char_count) as usize]);
+                accum /= char_count;
+            }
+        } else {
+            // Each character is taken from two bytes of the MAC, so every token length can be
+            // computed exactly using the floating point numbers available in Lua.
+            for i in 0..self.format.token_length as usize {
+                let value = (code[i * 2] as u64) * 256 + code[i * 2 + 1] as u64;
+                chars.push(token_chars[(value % char_count) as usize]);
+            }
         }
-        Token::from_arr(chars)
+        Token(chars)
     }
 
     fn current_epoch(&self) -> Result<i64> {
         let unix_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
-        Ok((unix_time / self.time_increment as u64) as i64)
+        Ok((unix_time / self.format.time_increment as u64) as i64)
     }
 
     fn make_token(&self, user_id: u64, epoch: i64) -> Token {
-        self.sha256_token(&format!("{}|{}|{}", TOKEN_VERSION, user_id, epoch))
+        self.sha256_token(&format!("{}|{}|{}", self.version, user_id, epoch))
+    }
+
+    fn check_signature(&self, data: &[u8], signature: &[u8]) -> bool {
+        let mut mac = Hmac::<Sha256>::new_varkey(&self.key).unwrap();
+        mac.update(data);
+        mac.verify(signature).is_ok()
     }
 
     fn make_current_token(&self, user: RobloxUserID) -> Result<Token> {
         Ok(self.make_token(user.0, self.current_epoch()?))
     }
-    fn check_token(&self, user: RobloxUserID, token: &Token) -> Result<Option<i64>> {
+    fn check_token(&self, user: RobloxUserID, token: &str) -> Result<Option<i64>> {
+        let token = match self.parse_token(token) {
+            Some(token) => token,
+            None => return Ok(None),
+        };
         let epoch = self.current_epoch()?;
         for i in &[1, 0, -1] {
-            if token == &self.make_token(user.0, epoch + i) {
+            if token == self.make_token(user.0, epoch + i) {
                 return Ok(Some(epoch + i))
             }
         }
@@ -116,9 +184,14 @@ impl TokenParameters {
 impl FromSqlRow for TokenParameters {
     fn from_sql_row(row: Row) -> Result<Self> {
         let (
-            id, key, time_increment, version
-        ): (u64, Vec<u8>, u32, u32) = FromSqlRow::from_sql_row(row)?;
-        Ok(TokenParameters { id, key, time_increment, version })
+            id, key, time_increment, version, token_length, token_chars, activates_at,
+            is_scheduled, key_nonce,
+        ): (u64, Vec<u8>, u32, u32, u32, String, SystemTime, bool, Option<Vec<u8>>) =
+            FromSqlRow::from_sql_row(row)?;
+        ensure!(token_length <= MAX_TOKEN_LENGTH && !token_chars.is_empty(),
+                "Invalid token format for key #{}.", id);
+        let format = TokenFormat { time_increment, token_length, token_chars };
+        Ok(TokenParameters { id, key, version, format, activates_at, is_scheduled, key_nonce })
     }
 }
 
@@ -127,24 +200,63 @@ pub enum TokenStatus {
     Verified { key_id: u64, epoch: i64 }, Outdated, NotVerified,
 }
 
+enum SignatureStatus<'a> {
+    Valid(&'a TokenParameters), Outdated, Invalid,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum KeyState {
+    Pending, Current, GracePeriod { ends: SystemTime }, Retired,
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct KeyInfo {
+    pub id: u64, pub activates_at: SystemTime, pub state: KeyState,
+}
+
 struct TokenContext {
-    current: TokenParameters, history: Vec<TokenParameters>
+    pending: Option<TokenParameters>, current: TokenParameters, history: Vec<TokenParameters>,
 }
 impl TokenContext {
-    fn from_db_internal(conn: &DatabaseConnection) -> Result<Option<TokenContext>> {
-        let mut results = conn.query(
-            "SELECT id, key, time_increment, version FROM verification_keys \
+    fn from_db_internal(
+        conn: &DatabaseConnection, master_key: Option<&MasterKey>,
+    ) -> Result<Option<TokenContext>> {
+        let now = SystemTime::now();
+        let mut pending = None;
+        let mut results = Vec::new();
+        for param in conn.query(
+            "SELECT id, key, time_increment, version, token_length, token_chars, activates_at, \
+                    is_scheduled, key_nonce \
+             FROM verification_keys \
              ORDER BY id DESC LIMIT ?1",
-            1 + HISTORY_COUNT,
-        ).get_all::<TokenParameters>()?;
+            2 + HISTORY_COUNT,
+        ).get_all::<TokenParameters>()? {
+            let mut param = param;
+            if let Some(nonce) = param.key_nonce.take() {
+                let master_key = match master_key {
+                    Some(master_key) => master_key,
+                    None => bail!("Token key #{} is encrypted, but no master key is configured.",
+                                  param.id),
+                };
+                param.key = master_key.decrypt(&param.key, &nonce)?;
+            }
+            if results.is_empty() && param.activates_at > now {
+                pending = Some(param);
+            } else {
+                results.push(param);
+            }
+        }
         if results.is_empty() {
             Ok(None)
         } else {
             let history = results.split_off(1);
-            Ok(Some(TokenContext { current: results.pop().unwrap(), history }))
+            Ok(Some(TokenContext { pending, current: results.pop().unwrap(), history }))
         }
     }
-    fn new_in_db(conn: &DatabaseConnection, time_increment: u32) -> Result<TokenContext> {
+    fn insert_key(
+        conn: &DatabaseConnection, master_key: Option<&MasterKey>, format: &TokenFormat,
+        version: u32, activates_at: SystemTime, is_scheduled: bool,
+    ) -> Result<()> {
         let mut key = Vec::new();
         for _ in 0..16 {
             let r = OsRng.next_u32();
@@ -153,93 +265,426 @@ impl TokenContext {
             key.push((r >> 16) as u8);
             key.push((r >> 24) as u8);
         }
+        let (key, key_nonce) = match master_key {
+            Some(master_key) => {
+                let (key, nonce) = master_key.encrypt(&key)?;
+                (key, Some(nonce))
+            }
+            None => (key, None),
+        };
 
         conn.execute(
-            "INSERT INTO verification_keys (key, time_increment, version) VALUES (?1, ?2, ?3)",
-            (key, time_increment, TOKEN_VERSION)
+            "INSERT INTO verification_keys (\
+                 key, time_increment, version, token_length, token_chars, activates_at, \
+                 is_scheduled, key_nonce\
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            (key, format.time_increment, version, format.token_length,
+             format.token_chars.as_str(), activates_at, is_scheduled, key_nonce)
         )?;
-        Ok(TokenContext::from_db_internal(conn)?.ok_or_else(Error::none)?)
+        Ok(())
     }
-    fn rekey(conn: &DatabaseConnection, time_increment: u32) -> Result<TokenContext> {
+    fn delete_pending(conn: &DatabaseConnection) -> Result<()> {
+        conn.execute("DELETE FROM verification_keys WHERE activates_at > ?1", SystemTime::now())?;
+        Ok(())
+    }
+    fn new_in_db(
+        conn: &DatabaseConnection, master_key: Option<&MasterKey>, format: &TokenFormat,
+    ) -> Result<TokenContext> {
+        // A key replaced outside of the rotation schedule may be compromised, so any staged key
+        // is discarded along with it, and it receives no grace period.
+        TokenContext::delete_pending(conn)?;
+        TokenContext::insert_key(conn, master_key, format, TOKEN_VERSION, SystemTime::now(),
+                                 false)?;
+        Ok(TokenContext::from_db_internal(conn, master_key)?.ok_or_else(Error::none)?)
+    }
+    fn rekey(
+        conn: &DatabaseConnection, master_key: Option<&MasterKey>, format: &TokenFormat,
+    ) -> Result<TokenContext> {
         info!("Regenerating token key due to user request.");
         conn.transaction_immediate(|| {
-            TokenContext::new_in_db(conn, time_increment)
+            TokenContext::new_in_db(conn, master_key, format)
+        })
+    }
+    /// Stages a new key. The place uses one token version for both of its keys, so the staged
+    /// key uses the same version as the current key.
+    fn stage(
+        conn: &DatabaseConnection, master_key: Option<&MasterKey>, format: &TokenFormat,
+        version: u32, activates_at: SystemTime,
+    ) -> Result<TokenContext> {
+        conn.transaction_immediate(|| {
+            TokenContext::delete_pending(conn)?;
+            TokenContext::insert_key(conn, master_key, format, version, activates_at, true)?;
+            Ok(TokenContext::from_db_internal(conn, master_key)?.ok_or_else(Error::none)?)
         })
     }
-    fn from_db(conn: &DatabaseConnection, time_increment: u32) -> Result<TokenContext> {
+    /// Loads the token keys from the database, replacing them if they don't use the configured
+    /// format. Keys from older token versions are kept, as the place still supports them, so
+    /// upgrading the bot does not require republishing the place.
+    fn from_db(
+        conn: &DatabaseConnection, master_key: Option<&MasterKey>, format: &TokenFormat,
+    ) -> Result<TokenContext> {
         conn.transaction_immediate(|| {
-            match TokenContext::from_db_internal(conn)? {
+            match TokenContext::from_db_internal(conn, master_key)? {
                 Some(x) => {
-                    if x.current.time_increment != time_increment {
+                    if x.current.format.time_increment != format.time_increment {
                         info!("Token key in database has a different time increment, \
                                regenerating...");
-                        TokenContext::new_in_db(conn, time_increment)
-                    } else if x.current.version != TOKEN_VERSION {
-                        info!("Token key in database is for an older version, \
+                        TokenContext::new_in_db(conn, master_key, format)
+                    } else if &x.current.format != format {
+                        info!("Token key in database has a different token format, \
                                regenerating...");
-                        TokenContext::new_in_db(conn, time_increment)
+                        TokenContext::new_in_db(conn, master_key, format)
+                    } else if x.pending.as_ref().map_or(false, |pending|
+                        pending.version != x.current.version || &pending.format != format
+                    ) {
+                        info!("Staged token key in database has a different token format, \
+                               discarding...");
+                        TokenContext::delete_pending(conn)?;
+                        Ok(TokenContext::from_db_internal(conn, master_key)?
+                            .ok_or_else(Error::none)?)
                     } else {
                         Ok(x)
                     }
                 },
                 None => {
                     info!("No token keys in database, generating new key...");
-                    TokenContext::new_in_db(conn, time_increment)
+                    TokenContext::new_in_db(conn, master_key, format)
                 },
             }
         })
     }
 
-    fn check_token(&self, user: RobloxUserID, token: &str) -> Result<TokenStatus> {
-        let token = Token::from_str(token)?;
-        if let Some(epoch) = self.current.check_token(user, &token)? {
+    fn add_config<'a>(&self, config: &mut Vec<LuaConfigEntry<'a>>) {
+        self.current.add_config(config);
+        let pending = self.pending.as_ref();
+        config.push(LuaConfigEntry::new("next_shared_key", true,
+                                        pending.map(|x| x.key.clone())));
+        // Unix timestamps can be represented exactly as a double.
+        config.push(LuaConfigEntry::new("next_key_activates", false,
+                                        pending.map(|x| util::time_to_i64(x.activates_at) as f64)));
+    }
+
+    fn grace_period_ends(&self, grace_period: u64) -> Option<SystemTime> {
+        if self.current.is_scheduled && !self.history.is_empty() {
+            let ends = self.current.activates_at + Duration::from_secs(grace_period);
+            if SystemTime::now() < ends {
+                return Some(ends)
+            }
+        }
+        None
+    }
+    fn key_info(&self, grace_period: u64) -> Vec<KeyInfo> {
+        let mut keys = Vec::new();
+        if let Some(pending) = &self.pending {
+            keys.push(KeyInfo {
+                id: pending.id, activates_at: pending.activates_at, state: KeyState::Pending,
+            });
+        }
+        keys.push(KeyInfo {
+            id: self.current.id, activates_at: self.current.activates_at,
+            state: KeyState::Current,
+        });
+        let grace_period_ends = self.grace_period_ends(grace_period);
+        for (i, param) in self.history.iter().enumerate() {
+            let state = match grace_period_ends {
+                Some(ends) if i == 0 => KeyState::GracePeriod { ends },
+                _ => KeyState::Retired,
+            };
+            keys.push(KeyInfo { id: param.id, activates_at: param.activates_at, state });
+        }
+        keys
+    }
+
+    fn check_token(
+        &self, user: RobloxUserID, token: &str, grace_period: u64,
+    ) -> Result<TokenStatus> {
+        // Tokens from older keys may use a different format, so a token is only rejected as
+        // malformed if no key could have generated it.
+        if self.current.parse_token(token).is_none() &&
+           self.pending.iter().chain(&self.history).all(|x| x.parse_token(token).is_none()) {
+            self.current.check_format(token)?;
+        }
+
+        // The staged key is only used by the place once it activates, so tokens made with it
+        // are accepted even if the bot has not switched over yet.
+        if let Some(pending) = &self.pending {
+            if let Some(epoch) = pending.check_token(user, token)? {
+                return Ok(TokenStatus::Verified { key_id: pending.id, epoch })
+            }
+        }
+        if let Some(epoch) = self.current.check_token(user, token)? {
             return Ok(TokenStatus::Verified { key_id: self.current.id, epoch })
         }
+        if self.grace_period_ends(grace_period).is_some() {
+            let previous = &self.history[0];
+            if let Some(epoch) = previous.check_token(user, token)? {
+                return Ok(TokenStatus::Verified { key_id: previous.id, epoch })
+            }
+        }
         for param in &self.history {
-            if param.check_token(user, &token)?.is_some() {
+            if param.check_token(user, token)?.is_some() {
                 return Ok(TokenStatus::Outdated)
             }
         }
         Ok(TokenStatus::NotVerified)
     }
+
+    fn check_signature(
+        &self, data: &[u8], signature: &[u8], grace_period: u64,
+    ) -> SignatureStatus {
+        if let Some(pending) = &self.pending {
+            if pending.check_signature(data, signature) {
+                return SignatureStatus::Valid(pending)
+            }
+        }
+        if self.current.check_signature(data, signature) {
+            return SignatureStatus::Valid(&self.current)
+        }
+        if self.grace_period_ends(grace_period).is_some() &&
+           self.history[0].check_signature(data, signature) {
+            return SignatureStatus::Valid(&self.history[0])
+        }
+        if self.history.iter().any(|x| x.check_signature(data, signature)) {
+            return SignatureStatus::Outdated
+        }
+        SignatureStatus::Invalid
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub enum VerifyResult {
-    VerificationOk, TokenAlreadyUsed, VerificationPlaceOutdated, InvalidToken,
+    VerificationOk, TokenAlreadyUsed, VerificationPlaceOutdated, InvalidToken, PhraseNotFound,
     ReverifyOk { discord_link: Option<UserId>, roblox_link: Option<RobloxUserID> },
-    TooManyAttempts { max_attempts: u32, cooldown: u64, cooldown_ends: SystemTime },
-    SenderVerifiedAs { other_roblox_id: RobloxUserID },
-    RobloxAccountVerifiedTo { other_discord_id: UserId },
-    ReverifyOnCooldown { cooldown: u64, cooldown_ends: SystemTime }
+    TooManyAttempts {
+        max_attempts: u32, cooldown: u64, cooldown_ends: SystemTime, guild_policy: bool,
+    },
+    SenderVerifiedAs { other_roblox_id: RobloxUserID, guild_policy: bool },
+    RobloxAccountVerifiedTo { other_discord_id: UserId, guild_policy: bool },
+    ReverifyOnCooldown { cooldown: u64, cooldown_ends: SystemTime, guild_policy: bool },
+    LinkOk { discord_link: Option<UserId> },
+    PendingApproval { expires: SystemTime },
+    RefreshOk,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+enum VerifyMode {
+    /// Verifies a Roblox account as the user's primary account, replacing the previous one.
+    Verify,
+    /// Links a Roblox account to a verified user in addition to their primary account.
+    Link,
+}
+
+/// How an account should be linked, once the reverification policies have been checked.
+enum LinkPlan {
+    /// The account is already linked to the user, so their verification is only renewed.
+    Renew,
+    /// The account should be linked, replacing the user's primary account and removing the
+    /// account from its current owner if needed.
+    Commit { roblox_link: Option<RobloxUserID>, discord_link: Option<UserId> },
+    /// A policy does not allow the account to be linked.
+    Rejected(VerifyResult),
+}
+
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
 pub struct HistoryEntry<T> {
     pub id: T, pub is_unverify: bool, pub last_updated: SystemTime,
+    pub actor: Option<UserId>, pub note: Option<String>,
 }
 impl <T> HistoryEntry<T> {
-    fn new(id: T, is_unverify: bool, last_updated: SystemTime) -> HistoryEntry<T> {
-        HistoryEntry { id, is_unverify, last_updated }
+    fn new(
+        (id, is_unverify, last_updated, actor, note):
+            (T, bool, SystemTime, Option<UserId>, Option<String>),
+    ) -> HistoryEntry<T> {
+        HistoryEntry { id, is_unverify, last_updated, actor, note }
+    }
+}
+
+/// A verification waiting for a moderator of a server to approve it.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct PendingVerification {
+    pub guild_id: GuildId, pub discord_id: UserId, pub roblox_id: RobloxUserID,
+    pub is_link: bool, pub created: SystemTime, pub expires: SystemTime,
+    pub review_message: Option<(ChannelId, MessageId)>,
+}
+impl PendingVerification {
+    fn from_row(
+        (guild_id, discord_id, roblox_id, is_link, created, expires, channel_id, message_id):
+            PendingRow,
+    ) -> PendingVerification {
+        PendingVerification {
+            guild_id, discord_id, roblox_id, is_link, created, expires,
+            review_message: match (channel_id, message_id) {
+                (Some(channel_id), Some(message_id)) => Some((channel_id, message_id)),
+                _ => None,
+            },
+        }
     }
 }
+const PENDING_COLUMNS: &str =
+    "discord_guild_id, discord_user_id, roblox_user_id, is_link, created, expires, \
+     review_channel_id, review_message_id";
+type PendingRow = (
+    GuildId, UserId, RobloxUserID, bool, SystemTime, SystemTime,
+    Option<ChannelId>, Option<MessageId>,
+);
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LinkedAccount {
+    pub roblox_id: RobloxUserID, pub is_primary: bool, pub last_updated: SystemTime,
+}
+
+struct ProfilePhrase {
+    roblox_id: RobloxUserID, phrase: String, expires: SystemTime,
+}
+
+struct PairingCode {
+    guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID, expires: SystemTime,
+}
+
+#[derive(Deserialize)]
+struct PushPayload {
+    roblox_user_id: u64, pairing_code: String, timestamp: i64,
+}
+
+/// The result of a verification pushed by the verification place.
+pub struct PushVerifyResult {
+    pub guild_id: Option<GuildId>, pub discord_id: UserId, pub roblox_id: RobloxUserID,
+    pub result: VerifyResult,
+}
 
 struct VerifierData {
-    config: ConfigManager, database: Database, token_ctx: RwLock<TokenContext>,
+    config: ConfigManager, database: Database, bans: BanManager, token_ctx: RwLock<TokenContext>,
+    master_key: RwLock<Option<MasterKey>>, key_file: PathBuf, new_key_file: PathBuf,
+    profile_phrases: Mutex<HashMap<UserId, ProfilePhrase>>,
+    pairing_codes: Mutex<HashMap<String, PairingCode>>,
     discord_lock: MutexSet<UserId>, roblox_lock: MutexSet<RobloxUserID>,
 }
 #[derive(Clone)]
 pub struct Verifier(Arc<VerifierData>);
 impl Verifier {
-    pub fn new(config: ConfigManager, database: Database) -> Result<Verifier> {
-        let ctx = TokenContext::from_db(&database.connect()?,
-                                        config.get(None, ConfigKeys::TokenValiditySeconds)?)?;
+    pub fn new(
+        config: ConfigManager, database: Database, bans: BanManager, root_path: &Path,
+    ) -> Result<Verifier> {
+        let conn = database.connect()?;
+        Verifier::finish_key_rotation(&conn, root_path)?;
+        let master_key = MasterKey::load(root_path)?;
+        let encrypted_count = conn.query(
+            "SELECT COUNT(*) FROM verification_keys WHERE key_nonce IS NOT NULL", (),
+        ).get::<u32>()?;
+        match master_key {
+            Some(_) => {
+                let unencrypted_count = conn.query(
+                    "SELECT COUNT(*) FROM verification_keys WHERE key_nonce IS NULL", (),
+                ).get::<u32>()?;
+                if unencrypted_count != 0 {
+                    warn!("{} token key(s) are stored unencrypted, as they were created before \
+                           the master key was configured. Use the 'rotate_master_key' command \
+                           to encrypt them.", unencrypted_count);
+                }
+            }
+            None => {
+                ensure!(encrypted_count == 0,
+                        "The token keys in the database are encrypted, but no master key was \
+                         found. Please set the {} environment variable, or provide the key file \
+                         at '{}'.",
+                        MASTER_KEY_ENV, MasterKey::key_file_path(root_path).display());
+                warn!("No master key is configured, so token keys are stored unencrypted in the \
+                       database. Use the 'rotate_master_key' command to generate one.");
+            }
+        }
+
+        let ctx = TokenContext::from_db(&conn, master_key.as_ref(),
+                                        &TokenFormat::from_config(&config)?)?;
         Ok(Verifier(Arc::new(VerifierData {
-            config, database, token_ctx: RwLock::new(ctx),
+            config, database, bans, token_ctx: RwLock::new(ctx),
+            master_key: RwLock::new(master_key), key_file: MasterKey::key_file_path(root_path),
+            new_key_file: MasterKey::pending_key_file_path(root_path),
+            profile_phrases: Mutex::new(HashMap::new()),
+            pairing_codes: Mutex::new(HashMap::new()),
             discord_lock: MutexSet::new(), roblox_lock: MutexSet::new(),
         })))
     }
 
+    /// Finishes a master key rotation that was interrupted after the database was re-encrypted,
+    /// but before the new key replaced the key file. If the database was not re-encrypted, the
+    /// new key is discarded instead.
+    fn finish_key_rotation(conn: &DatabaseConnection, root_path: &Path) -> Result<()> {
+        let new_key_file = MasterKey::pending_key_file_path(root_path);
+        if !new_key_file.exists() { return Ok(()) }
+
+        let new_key = MasterKey::read_from(&new_key_file)?;
+        let encrypted = conn.query(
+            "SELECT key, key_nonce FROM verification_keys WHERE key_nonce IS NOT NULL LIMIT 1", (),
+        ).get_opt::<(Vec<u8>, Vec<u8>)>()?;
+        let is_current = match encrypted {
+            Some((key, nonce)) => new_key.decrypt(&key, &nonce).is_ok(),
+            None => false,
+        };
+        if is_current {
+            let key_file = MasterKey::key_file_path(root_path);
+            warn!("Finishing an interrupted master key rotation. The new master key has been \
+                   moved to '{}'.", key_file.display());
+            fs::rename(&new_key_file, &key_file)?;
+        } else {
+            warn!("Removing the master key from an interrupted rotation, as the database was \
+                   not encrypted with it.");
+            fs::remove_file(&new_key_file)?;
+        }
+        Ok(())
+    }
+
+    /// Encrypts all token keys with a newly generated master key, and writes it to the key file.
+    /// Returns the path of the key file.
+    pub fn rotate_master_key(&self) -> Result<PathBuf> {
+        cmd_ensure!(!MasterKey::is_from_env(),
+                    "The master key is set by the {} environment variable, so a new key could \
+                     not be used after a restart. Write the current key to '{}' and unset the \
+                     environment variable first.",
+                    MASTER_KEY_ENV, self.0.key_file.display());
+
+        let _token_ctx = self.0.token_ctx.write();
+        let mut master_key = self.0.master_key.write();
+        let new_key = MasterKey::generate();
+
+        // The new key is written to a separate file first, so that whichever key the database
+        // was last encrypted with is never lost. If the bot stops before it is moved into
+        // place, this is finished on the next startup.
+        let new_key_file = self.0.new_key_file.clone();
+        new_key.write_to(&new_key_file)?;
+
+        let conn = self.0.database.connect()?;
+        let result = conn.transaction_immediate(|| {
+            let keys = conn.query(
+                "SELECT id, key, key_nonce FROM verification_keys", (),
+            ).get_all::<(u64, Vec<u8>, Option<Vec<u8>>)>()?;
+            for (id, key, nonce) in keys {
+                let key = match (nonce, master_key.as_ref()) {
+                    (Some(nonce), Some(master_key)) => master_key.decrypt(&key, &nonce)?,
+                    (Some(_), None) => bail!("Token key #{} is encrypted, but no master key is \
+                                              configured.", id),
+                    (None, _) => key,
+                };
+                let (key, nonce) = new_key.encrypt(&key)?;
+                conn.execute("UPDATE verification_keys SET key = ?1, key_nonce = ?2 WHERE id = ?3",
+                             (key, nonce, id))?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            fs::remove_file(&new_key_file).ok();
+            return Err(e)
+        }
+        *master_key = Some(new_key);
+        if let Err(e) = fs::rename(&new_key_file, &self.0.key_file) {
+            warn!("Could not move the new master key to '{}', so it will be moved on the next \
+                   startup: {}", self.0.key_file.display(), e);
+            return Ok(new_key_file)
+        }
+
+        info!("The master key has been rotated, and written to '{}'.", self.0.key_file.display());
+        Ok(self.0.key_file.clone())
+    }
+
     pub fn make_token(&self, user: RobloxUserID) -> Result<String> {
         let production = self.0.config.get(None, ConfigKeys::ProductionMode)?;
         cmd_ensure!(!production, "Cannot use this command in production mode.");
@@ -253,57 +698,371 @@ impl Verifier {
     pub fn rekey(&self, force: bool) -> Result<bool> {
         let mut lock = self.0.token_ctx.write();
         let cur_id = lock.current.id;
+        let format = TokenFormat::from_config(&self.0.config)?;
+        let master_key = self.0.master_key.read();
         *lock = if force {
-            TokenContext::rekey(&self.0.database.connect()?,
-                                self.0.config.get(None, ConfigKeys::TokenValiditySeconds)?)?
+            TokenContext::rekey(&self.0.database.connect()?, master_key.as_ref(), &format)?
         } else {
-            TokenContext::from_db(&self.0.database.connect()?,
-                                  self.0.config.get(None, ConfigKeys::TokenValiditySeconds)?)?
+            TokenContext::from_db(&self.0.database.connect()?, master_key.as_ref(), &format)?
         };
         Ok(cur_id != lock.current.id)
     }
 
+    /// Switches to a staged key once it activates, and stages a new key once the current key is
+    /// due to be rotated. Returns whether the keys in the place file have changed.
+    pub fn check_rotation(&self) -> Result<bool> {
+        let rotation = self.0.config.get(None, ConfigKeys::TokenRotationSeconds)?;
+        let lead_time = self.0.config.get(None, ConfigKeys::TokenRotationLeadSeconds)?;
+        let format = TokenFormat::from_config(&self.0.config)?;
+        let now = SystemTime::now();
+
+        let mut lock = self.0.token_ctx.write();
+        let master_key = self.0.master_key.read();
+        let mut changed = false;
+        if lock.pending.as_ref().map_or(false, |x| x.activates_at <= now) {
+            *lock = TokenContext::from_db(&self.0.database.connect()?, master_key.as_ref(),
+                                          &format)?;
+            info!("Token key #{} is now the current key.", lock.current.id);
+            changed = true;
+        }
+        if rotation != 0 && lock.pending.is_none() {
+            let rotates_at = lock.current.activates_at + Duration::from_secs(rotation);
+            let activates_at = now + Duration::from_secs(lead_time);
+            if activates_at >= rotates_at {
+                let version = lock.current.version;
+                *lock = TokenContext::stage(&self.0.database.connect()?, master_key.as_ref(),
+                                           &format, version, activates_at)?;
+                if let Some(pending) = &lock.pending {
+                    info!("Token key #{} has been staged, and will become the current key in {}. \
+                           Please update the verification place before then.",
+                          pending.id, util::english_time_diff(now, activates_at));
+                }
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+    pub fn key_status(&self) -> Result<Vec<KeyInfo>> {
+        let grace_period = self.0.config.get(None, ConfigKeys::TokenGracePeriodSeconds)?;
+        Ok(self.0.token_ctx.read().key_info(grace_period))
+    }
+    pub fn last_used_key(&self) -> Result<Option<(u64, SystemTime)>> {
+        self.0.database.connect()?.query(
+            "SELECT last_key_id, last_updated FROM roblox_user_info \
+             WHERE last_key_id IS NOT NULL ORDER BY last_updated DESC LIMIT 1", (),
+        ).get_opt()
+    }
+
     pub fn get_verified_roblox_user(&self, user: UserId) -> Result<Option<RobloxUserID>> {
         Ok(self.0.database.connect()?.query(
             "SELECT roblox_user_id FROM discord_user_info WHERE discord_user_id = ?1", user
         ).get_opt::<Option<RobloxUserID>>()?.and_then(|x| x))
     }
+    /// Returns when a user last verified, if they are currently verified.
+    pub fn get_verification_time(&self, user: UserId) -> Result<Option<SystemTime>> {
+        Ok(self.0.database.connect()?.query(
+            "SELECT last_updated FROM discord_user_info \
+             WHERE discord_user_id = ?1 AND roblox_user_id IS NOT NULL", user
+        ).get_opt::<SystemTime>()?)
+    }
+    /// Returns whether a user's current verification was imported from another bot, and they
+    /// have not verified the account themselves since.
+    pub fn is_verification_imported(&self, user: UserId) -> Result<bool> {
+        let conn = self.0.database.connect()?;
+        match self.get_verified_roblox_user(user)? {
+            Some(roblox_id) => Verifier::is_imported(&conn, user, roblox_id),
+            None => Ok(false),
+        }
+    }
     pub fn get_verified_discord_user(&self, user: RobloxUserID) -> Result<Option<UserId>> {
-        self.0.database.connect()?.query(
-            "SELECT discord_user_id FROM discord_user_info WHERE roblox_user_id = ?1", user
+        Verifier::linked_discord_user(&self.0.database.connect()?, user)
+    }
+    /// Returns all Roblox accounts linked to a Discord user, starting with their primary
+    /// account.
+    pub fn get_linked_accounts(&self, user: UserId) -> Result<Vec<LinkedAccount>> {
+        Ok(self.0.database.connect()?.query(
+            "SELECT l.roblox_user_id, l.roblox_user_id IS i.roblox_user_id, l.last_updated \
+             FROM discord_linked_accounts l \
+             LEFT JOIN discord_user_info i ON i.discord_user_id = l.discord_user_id \
+             WHERE l.discord_user_id = ?1 \
+             ORDER BY 2 DESC, l.last_updated", user
+        ).get_all::<(RobloxUserID, bool, SystemTime)>()?.into_iter()
+            .map(|(roblox_id, is_primary, last_updated)|
+                LinkedAccount { roblox_id, is_primary, last_updated })
+            .collect())
+    }
+
+    fn linked_discord_user(
+        conn: &DatabaseConnection, roblox_id: RobloxUserID,
+    ) -> Result<Option<UserId>> {
+        conn.query(
+            "SELECT discord_user_id FROM discord_linked_accounts WHERE roblox_user_id = ?1",
+            roblox_id,
         ).get_opt()
     }
+    fn is_imported(
+        conn: &DatabaseConnection, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<bool> {
+        Ok(conn.query(
+            "SELECT is_imported FROM discord_user_info \
+             WHERE discord_user_id = ?1 AND roblox_user_id = ?2", (discord_id, roblox_id),
+        ).get_opt::<bool>()?.unwrap_or(false))
+    }
+    /// Removes a linked Roblox account from a Discord user. If it was their primary account,
+    /// their most recently linked remaining account becomes their primary account.
+    fn remove_link(
+        conn: &DatabaseConnection, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<()> {
+        conn.execute(
+            "DELETE FROM discord_linked_accounts WHERE roblox_user_id = ?1", roblox_id,
+        )?;
+        conn.execute(
+            "UPDATE discord_user_info SET roblox_user_id = (\
+                 SELECT roblox_user_id FROM discord_linked_accounts \
+                 WHERE discord_user_id = ?1 ORDER BY last_updated DESC LIMIT 1\
+             ), is_imported = 0 WHERE discord_user_id = ?1 AND roblox_user_id = ?2",
+            (discord_id, roblox_id),
+        )?;
+        Ok(())
+    }
+    /// Records a change to a user's linked accounts. `actor` is set when someone other than the
+    /// user made the change.
+    fn add_history(
+        conn: &DatabaseConnection, discord_id: UserId, roblox_id: RobloxUserID,
+        is_unverify: bool, actor: Option<UserId>, note: Option<&str>,
+    ) -> Result<()> {
+        conn.execute(
+            "INSERT INTO user_history (\
+                 discord_user_id, roblox_user_id, is_unverify, last_updated, actor_discord_id, \
+                 note\
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            (discord_id, roblox_id, is_unverify, SystemTime::now(), actor, note),
+        )?;
+        Ok(())
+    }
 
     pub fn get_discord_user_history(
         &self, user: UserId, limit: u64,
     ) -> Result<Vec<HistoryEntry<RobloxUserID>>> {
         Ok(self.0.database.connect()?.query(
-            "SELECT roblox_user_id, is_unverify, last_updated FROM user_history \
-             WHERE discord_user_id = ?1 \
+            "SELECT roblox_user_id, is_unverify, last_updated, actor_discord_id, note \
+             FROM user_history WHERE discord_user_id = ?1 \
              ORDER BY rowid DESC LIMIT ?2", (user, limit)
-        ).get_all::<(RobloxUserID, bool, SystemTime)>()?.iter().rev()
-            .map(|&(id, is_unverify, time)| HistoryEntry::new(id, is_unverify, time))
-            .collect())
+        ).get_all::<(RobloxUserID, bool, SystemTime, Option<UserId>, Option<String>)>()?
+            .into_iter().rev().map(HistoryEntry::new).collect())
     }
     pub fn get_roblox_user_history(
         &self, user: RobloxUserID, limit: u64,
     ) -> Result<Vec<HistoryEntry<UserId>>> {
         Ok(self.0.database.connect()?.query(
-            "SELECT discord_user_id, is_unverify, last_updated FROM user_history \
-             WHERE roblox_user_id = ?1 \
+            "SELECT discord_user_id, is_unverify, last_updated, actor_discord_id, note \
+             FROM user_history WHERE roblox_user_id = ?1 \
              ORDER BY rowid DESC LIMIT ?2", (user, limit)
-        ).get_all::<(UserId, bool, SystemTime)>()?.iter().rev()
-            .map(|&(id, is_unverify, time)| HistoryEntry::new(id, is_unverify, time))
-            .collect())
+        ).get_all::<(UserId, bool, SystemTime, Option<UserId>, Option<String>)>()?
+            .into_iter().rev().map(HistoryEntry::new).collect())
     }
 
     pub fn try_verify(
-        &self, discord_id: UserId, roblox_id: RobloxUserID, token: &str,
+        &self, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID, token: &str,
     ) -> Result<VerifyResult> {
-        let conn = self.0.database.connect()?;
-
         debug!("Starting verification attempt: discord id {} -> roblox id {}, token = {}",
                discord_id.0, roblox_id.0, token);
+        self.verify_token(guild_id, discord_id, roblox_id, token, VerifyMode::Verify)
+    }
+    /// Links an additional Roblox account to a verified user, using a token from the
+    /// verification place.
+    pub fn try_link(
+        &self, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID, token: &str,
+    ) -> Result<VerifyResult> {
+        debug!("Starting link attempt: discord id {} -> roblox id {}, token = {}",
+               discord_id.0, roblox_id.0, token);
+        self.verify_token(guild_id, discord_id, roblox_id, token, VerifyMode::Link)
+    }
+    fn verify_token(
+        &self, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID,
+        token: &str, mode: VerifyMode,
+    ) -> Result<VerifyResult> {
+        self.verify_with(guild_id, discord_id, roblox_id, mode, |conn| {
+            let grace_period = self.0.config.get(None, ConfigKeys::TokenGracePeriodSeconds)?;
+            let token_ctx = self.0.token_ctx.read();
+            match token_ctx.check_token(roblox_id, token, grace_period)? {
+                TokenStatus::Verified { key_id, epoch } =>
+                    Verifier::use_key_epoch(conn, roblox_id, key_id, epoch),
+                TokenStatus::Outdated => Ok(Some(VerifyResult::VerificationPlaceOutdated)),
+                TokenStatus::NotVerified => Ok(Some(VerifyResult::InvalidToken)),
+            }
+        })
+    }
+
+    /// Records that a key epoch was used to verify a Roblox account, so that the same token or
+    /// request cannot be used again.
+    fn use_key_epoch(
+        conn: &DatabaseConnection, roblox_id: RobloxUserID, key_id: u64, epoch: i64,
+    ) -> Result<Option<VerifyResult>> {
+        let last_key = conn.query(
+            "SELECT last_key_id, last_key_epoch FROM roblox_user_info \
+             WHERE roblox_user_id = ?1", roblox_id
+        ).get_opt::<(Option<u64>, Option<i64>)>()?;
+        if let Some((Some(last_id), Some(last_epoch))) = last_key {
+            if last_id >= key_id && last_epoch >= epoch {
+                return Ok(Some(VerifyResult::TokenAlreadyUsed))
+            }
+        }
+        conn.execute(
+            "REPLACE INTO roblox_user_info \
+                 (roblox_user_id, last_key_id, last_key_epoch, last_updated) \
+             VALUES (?1, ?2, ?3, ?4)", (roblox_id, key_id, epoch, SystemTime::now()),
+        )?;
+        Ok(None)
+    }
+
+    /// Issues a code a user can enter in the verification place to verify as a Roblox account.
+    /// Any code previously issued to the user stops working.
+    pub fn issue_pairing_code(
+        &self, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> (String, SystemTime) {
+        let chars = TOKEN_CHARS_UNCONFUSABLE.as_bytes();
+        let mut codes = self.0.pairing_codes.lock();
+        let code = loop {
+            let code: String = (0..PAIRING_CODE_LENGTH)
+                .map(|_| chars[OsRng.next_u32() as usize % chars.len()] as char)
+                .collect();
+            if !codes.contains_key(&code) { break code }
+        };
+        let expires = SystemTime::now() + Duration::from_secs(PAIRING_CODE_EXPIRY_SECS);
+        codes.retain(|_, pairing| pairing.discord_id != discord_id);
+        codes.insert(code.clone(), PairingCode { guild_id, discord_id, roblox_id, expires });
+        (code, expires)
+    }
+
+    /// Verifies a request sent by the verification place when a user enters a pairing code.
+    /// The request body must be signed with the same key used to generate tokens.
+    pub fn try_verify_push(&self, body: &[u8], signature: &str) -> Result<PushVerifyResult> {
+        let signature = util::from_hex(signature.trim())
+            .to_cmd_err(|| "Malformed request signature.")?;
+        let grace_period = self.0.config.get(None, ConfigKeys::TokenGracePeriodSeconds)?;
+        let signing_key = match self.0.token_ctx.read()
+            .check_signature(body, &signature, grace_period) {
+            SignatureStatus::Valid(param) => Some((param.id, param.format.time_increment)),
+            SignatureStatus::Outdated => None,
+            SignatureStatus::Invalid => cmd_error!("Invalid request signature."),
+        };
+
+        let payload: PushPayload = serde_json::from_slice(body)
+            .to_cmd_err(|| "Malformed request body.")?;
+        let roblox_id = RobloxUserID(payload.roblox_user_id);
+        let code = payload.pairing_code.trim().to_ascii_uppercase();
+        let (guild_id, discord_id) = match self.0.pairing_codes.lock().get(&code) {
+            Some(pairing) if pairing.expires > SystemTime::now() => {
+                cmd_ensure!(pairing.roblox_id == roblox_id,
+                            "That pairing code was issued for a different Roblox account.");
+                (pairing.guild_id, pairing.discord_id)
+            }
+            _ => cmd_error!("That pairing code is invalid or has expired."),
+        };
+
+        debug!("Starting push verification attempt: discord id {} -> roblox id {}, \
+                pairing code = {}", discord_id.0, roblox_id.0, code);
+
+        let result = match signing_key {
+            Some((key_id, time_increment)) => {
+                let time_increment = time_increment as i64;
+                let now = util::time_to_i64(SystemTime::now());
+                cmd_ensure!((now - payload.timestamp).abs() <= time_increment,
+                            "Request timestamp is too far from the current time.");
+                let epoch = payload.timestamp / time_increment;
+                self.verify_with(guild_id, discord_id, roblox_id, VerifyMode::Verify, |conn| {
+                    Verifier::use_key_epoch(conn, roblox_id, key_id, epoch)
+                })?
+            }
+            None => VerifyResult::VerificationPlaceOutdated,
+        };
+        match result {
+            VerifyResult::VerificationOk | VerifyResult::ReverifyOk { .. } |
+            VerifyResult::PendingApproval { .. } | VerifyResult::RefreshOk => {
+                self.0.pairing_codes.lock().remove(&code);
+            }
+            _ => { }
+        }
+        Ok(PushVerifyResult { guild_id, discord_id, roblox_id, result })
+    }
+
+    /// Returns the phrase a user must add to their Roblox profile description, if one has been
+    /// issued to them for that account and has not expired yet.
+    pub fn profile_phrase(
+        &self, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Option<(String, SystemTime)> {
+        let phrases = self.0.profile_phrases.lock();
+        match phrases.get(&discord_id) {
+            Some(phrase) if phrase.roblox_id == roblox_id && phrase.expires > SystemTime::now() =>
+                Some((phrase.phrase.clone(), phrase.expires)),
+            _ => None,
+        }
+    }
+    pub fn issue_profile_phrase(
+        &self, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> (String, SystemTime) {
+        let mut words = Vec::new();
+        for _ in 0..PROFILE_PHRASE_WORDS {
+            words.push(PROFILE_PHRASE_WORD_LIST[OsRng.next_u32() as usize %
+                                                PROFILE_PHRASE_WORD_LIST.len()]);
+        }
+        let phrase = words.join(" ");
+        let expires = SystemTime::now() + Duration::from_secs(PROFILE_PHRASE_EXPIRY_SECS);
+        self.0.profile_phrases.lock().insert(discord_id, ProfilePhrase {
+            roblox_id, phrase: phrase.clone(), expires,
+        });
+        (phrase, expires)
+    }
+    pub fn try_verify_profile(
+        &self, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<VerifyResult> {
+        let phrase = match self.profile_phrase(discord_id, roblox_id) {
+            Some((phrase, _)) => phrase,
+            None => cmd_error!("No verification phrase has been issued for that account."),
+        };
+
+        debug!("Starting profile verification attempt: discord id {} -> roblox id {}, \
+                phrase = {}", discord_id.0, roblox_id.0, phrase);
+
+        // Fetch the profile before taking the verification locks, as this may take a while.
+        let description = roblox_id.profile_description()?.unwrap_or_else(String::new);
+        let result = self.verify_with(guild_id, discord_id, roblox_id, VerifyMode::Verify, |_| {
+            let normalize = |s: &str| s.split_whitespace()
+                .map(|x| x.to_lowercase()).collect::<Vec<_>>().join(" ");
+            if normalize(&description).contains(&phrase) {
+                Ok(None)
+            } else {
+                Ok(Some(VerifyResult::PhraseNotFound))
+            }
+        })?;
+        match result {
+            VerifyResult::VerificationOk | VerifyResult::ReverifyOk { .. } |
+            VerifyResult::PendingApproval { .. } | VerifyResult::RefreshOk => {
+                self.0.profile_phrases.lock().remove(&discord_id);
+            }
+            _ => { }
+        }
+        Ok(result)
+    }
+
+    /// Verifies a user whose Roblox account has already been confirmed by logging in to
+    /// Roblox.
+    pub fn try_verify_oauth(
+        &self, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<VerifyResult> {
+        debug!("Starting OAuth verification attempt: discord id {} -> roblox id {}",
+               discord_id.0, roblox_id.0);
+        self.verify_with(guild_id, discord_id, roblox_id, VerifyMode::Verify, |_| Ok(None))
+    }
+
+    /// Links a Roblox account to a Discord user once `check` confirms they own it. The
+    /// verification settings of `guild_id` are used, falling back to the global settings.
+    fn verify_with(
+        &self, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID,
+        mode: VerifyMode, check: impl FnOnce(&DatabaseConnection) -> Result<Option<VerifyResult>>,
+    ) -> Result<VerifyResult> {
+        let conn = self.0.database.connect()?;
 
         let discord_lock = self.0.discord_lock.lock(discord_id);
         cmd_ensure!(discord_lock.is_some(),
@@ -314,18 +1073,55 @@ impl Verifier {
                     "Someone else is currently trying to verify as that Roblox account. \
                      Please wait for their attempt to finish.");
 
+        self.check_bans(guild_id, discord_id, roblox_id, "You are")?;
+
+        let check_discord = conn.query(
+            "SELECT roblox_user_id, last_updated FROM discord_user_info \
+             WHERE discord_user_id = ?1", discord_id
+        ).get_opt::<(Option<RobloxUserID>, SystemTime)>()?;
+        if mode == VerifyMode::Link {
+            cmd_ensure!(check_discord.map_or(false, |x| x.0.is_some()),
+                        "You must verify before you can link additional Roblox accounts.");
+            // Linking an account again is allowed to request approval for it in this server.
+            if Verifier::linked_discord_user(&conn, roblox_id)? == Some(discord_id) {
+                cmd_ensure!(self.needs_approval(&conn, guild_id, discord_id, roblox_id)?,
+                            "That Roblox account is already linked to your Discord account.");
+            } else {
+                let max_links = self.0.config.get(None, ConfigKeys::MaxLinkedAccounts)?;
+                cmd_ensure!(Verifier::link_count(&conn, discord_id)? < max_links,
+                            "You can only link up to {} Roblox accounts to your Discord \
+                             account.", max_links);
+            }
+        }
+
         // Check cooldown
         let attempt_info = conn.query(
             "SELECT attempt_count, last_attempt FROM verification_cooldown \
              WHERE discord_user_id = ?1", discord_id
         ).get_opt::<(u32, SystemTime)>()?;
         let new_attempt_count = if let Some((attempt_count, last_attempt)) = attempt_info {
-            let max_attempts = self.0.config.get(None, ConfigKeys::VerificationAttemptLimit)?;
-            let cooldown = self.0.config.get(None, ConfigKeys::VerificationCooldownSeconds)?;
-            let cooldown_ends = last_attempt + Duration::from_secs(cooldown);
-            if SystemTime::now() < cooldown_ends {
+            // Returns the attempt limit and cooldown if the cooldown is still active.
+            let active_cooldown = |guild_id: Option<GuildId>| -> Result<Option<(u32, u64, _)>> {
+                let max_attempts =
+                    self.get_policy(guild_id, ConfigKeys::VerificationAttemptLimit, cmp::min)?;
+                let cooldown =
+                    self.get_policy(guild_id, ConfigKeys::VerificationCooldownSeconds, cmp::max)?;
+                let cooldown_ends = last_attempt + Duration::from_secs(cooldown);
+                Ok(if SystemTime::now() < cooldown_ends {
+                    Some((max_attempts, cooldown, cooldown_ends))
+                } else {
+                    None
+                })
+            };
+            if let Some((max_attempts, cooldown, cooldown_ends)) = active_cooldown(guild_id)? {
                 if attempt_count >= max_attempts {
-                    return Ok(VerifyResult::TooManyAttempts { max_attempts, cooldown, cooldown_ends })
+                    let guild_policy = guild_id.is_some() && match active_cooldown(None)? {
+                        Some((max_attempts, _, _)) => attempt_count < max_attempts,
+                        None => true,
+                    };
+                    return Ok(VerifyResult::TooManyAttempts {
+                        max_attempts, cooldown, cooldown_ends, guild_policy,
+                    })
                 }
                 attempt_count + 1
             } else {
@@ -340,92 +1136,242 @@ impl Verifier {
             ) VALUES (?1, ?2, ?3)", (discord_id, SystemTime::now(), new_attempt_count)
         )?;
 
-        // Check token
-        let token_ctx = self.0.token_ctx.read();
-        match token_ctx.check_token(roblox_id, token)? {
-            TokenStatus::Verified { key_id, epoch } => {
-                let last_key = conn.query(
-                    "SELECT last_key_id, last_key_epoch FROM roblox_user_info \
-                     WHERE roblox_user_id = ?1", roblox_id
-                ).get_opt::<(u64, i64)>()?;
-                if let Some((last_id, last_epoch)) = last_key {
-                    if last_id >= key_id && last_epoch >= epoch {
-                        return Ok(VerifyResult::TokenAlreadyUsed)
-                    }
-                }
-                conn.execute(
-                    "REPLACE INTO roblox_user_info \
-                         (roblox_user_id, last_key_id, last_key_epoch, last_updated) \
-                     VALUES (?1, ?2, ?3, ?4)", (roblox_id, key_id, epoch, SystemTime::now()),
-                )?;
-            }
-            TokenStatus::Outdated =>
-                return Ok(VerifyResult::VerificationPlaceOutdated),
-            TokenStatus::NotVerified =>
-                return Ok(VerifyResult::InvalidToken),
+        // Check the proof of account ownership
+        if let Some(result) = check(&conn)? {
+            return Ok(result)
         }
 
         // Attempt to verify user
-        let allow_reverify_discord = self.0.config.get(None, ConfigKeys::AllowReverifyDiscord)?;
-        let allow_reverify_roblox = self.0.config.get(None, ConfigKeys::AllowReverifyRoblox)?;
-        let check_discord = conn.query(
-            "SELECT roblox_user_id, last_updated FROM discord_user_info \
-             WHERE discord_user_id = ?1", discord_id
-        ).get_opt::<(Option<RobloxUserID>, SystemTime)>()?;
-        if let Some((current_id, last_updated)) = check_discord {
-            if !allow_reverify_discord {
-                if let Some(current_id) = current_id {
-                    return Ok(VerifyResult::SenderVerifiedAs { other_roblox_id: current_id })
+        let needs_approval = self.needs_approval(&conn, guild_id, discord_id, roblox_id)?;
+        let (roblox_link, discord_link) =
+            match self.plan_link(&conn, guild_id, discord_id, roblox_id, mode, check_discord)? {
+                LinkPlan::Rejected(result) => return Ok(result),
+                LinkPlan::Renew if !needs_approval => {
+                    Verifier::renew_link(&conn, discord_id, roblox_id, mode)?;
+                    return Ok(VerifyResult::RefreshOk)
+                }
+                LinkPlan::Renew => (None, None),
+                LinkPlan::Commit { roblox_link, discord_link } => (roblox_link, discord_link),
+            };
+
+        if let (Some(guild_id), true) = (guild_id, needs_approval) {
+            let expiry =
+                self.0.config.get(Some(guild_id), ConfigKeys::VerificationApprovalSeconds)?;
+            let expires = SystemTime::now() + Duration::from_secs(expiry);
+            conn.execute(
+                "REPLACE INTO pending_verifications (\
+                     discord_guild_id, discord_user_id, roblox_user_id, is_link, created, \
+                     expires\
+                 ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                (guild_id, discord_id, roblox_id, mode == VerifyMode::Link,
+                 SystemTime::now(), expires),
+            )?;
+            return Ok(VerifyResult::PendingApproval { expires })
+        }
+
+        Verifier::commit_link(&conn, discord_id, roblox_id, mode, roblox_link, discord_link,
+                              None, None)
+    }
+
+    /// Returns an error if the Discord user or Roblox account is banned from verifying.
+    /// `subject` describes the Discord user, e.g. "You are".
+    fn check_bans(
+        &self, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID,
+        subject: &str,
+    ) -> Result<()> {
+        if let Some(ban) = self.0.bans.find(guild_id, BanTarget::Discord(discord_id))? {
+            debug!("Discord id {} is banned: {}", discord_id.0, ban.reason);
+            cmd_error!("{} {}.", subject, ban.describe())
+        }
+        if let Some(ban) = self.0.bans.find(guild_id, BanTarget::Roblox(roblox_id))? {
+            debug!("Roblox id {} is banned: {}", roblox_id.0, ban.reason);
+            cmd_error!("That Roblox account is {}.", ban.describe())
+        }
+        Ok(())
+    }
+    /// Returns a verification policy setting in a server. Attempts and cooldowns are shared
+    /// between every server, so a server's setting is only used when `stricter` chooses it over
+    /// the global setting.
+    fn get_policy<T: ToSql + FromSql + Clone + Any + Send + Sync>(
+        &self, guild_id: Option<GuildId>, key: ConfigKey<T>, stricter: fn(T, T) -> T,
+    ) -> Result<T> {
+        let global = self.0.config.get(None, key)?;
+        Ok(match guild_id {
+            Some(guild_id) => stricter(global, self.0.config.get(Some(guild_id), key)?),
+            None => global,
+        })
+    }
+    fn link_count(conn: &DatabaseConnection, discord_id: UserId) -> Result<u32> {
+        conn.query(
+            "SELECT COUNT(*) FROM discord_linked_accounts WHERE discord_user_id = ?1",
+            discord_id,
+        ).get::<u32>()
+    }
+    /// Checks the reverification policies of `guild_id` to decide how an account should be
+    /// linked. `check_discord` is the user's current primary account and verification time.
+    fn plan_link(
+        &self, conn: &DatabaseConnection, guild_id: Option<GuildId>, discord_id: UserId,
+        roblox_id: RobloxUserID, mode: VerifyMode,
+        check_discord: Option<(Option<RobloxUserID>, SystemTime)>,
+    ) -> Result<LinkPlan> {
+        // Reverifying changes the link for every server, so a server can only make these
+        // policies stricter than the global setting, never looser.
+        let policy = |key: ConfigKey<bool>| -> Result<(bool, bool)> {
+            let allowed_globally = self.0.config.get(None, key)?;
+            let allowed_here = match guild_id {
+                Some(guild_id) => self.0.config.get(Some(guild_id), key)?,
+                None => true,
+            };
+            Ok((allowed_globally && allowed_here, allowed_globally && !allowed_here))
+        };
+        let (allow_reverify_discord, discord_guild_policy) =
+            policy(ConfigKeys::AllowReverifyDiscord)?;
+        let (allow_reverify_roblox, roblox_guild_policy) =
+            policy(ConfigKeys::AllowReverifyRoblox)?;
+        if mode == VerifyMode::Verify {
+            if let Some((current_id, last_updated)) = check_discord {
+                // Verifying as the same account again only renews the verification.
+                if current_id == Some(roblox_id) {
+                    return Ok(LinkPlan::Renew)
+                }
+                if !allow_reverify_discord {
+                    if let Some(current_id) = current_id {
+                        return Ok(LinkPlan::Rejected(VerifyResult::SenderVerifiedAs {
+                            other_roblox_id: current_id, guild_policy: discord_guild_policy,
+                        }))
+                    }
                 }
-            }
-            if current_id == Some(roblox_id) {
-                return Ok(VerifyResult::SenderVerifiedAs { other_roblox_id: roblox_id })
-            }
 
-            let cooldown =
-                self.0.config.get(None, ConfigKeys::ReverificationCooldownSeconds)?;
-            let cooldown_ends = last_updated + Duration::from_secs(cooldown);
-            if SystemTime::now() < cooldown_ends {
-                return Ok(VerifyResult::ReverifyOnCooldown { cooldown, cooldown_ends })
+                let cooldown_ends = |guild_id: Option<GuildId>| -> Result<(u64, SystemTime)> {
+                    let cooldown = self.get_policy(
+                        guild_id, ConfigKeys::ReverificationCooldownSeconds, cmp::max,
+                    )?;
+                    Ok((cooldown, last_updated + Duration::from_secs(cooldown)))
+                };
+                let (cooldown, cooldown_ends_at) = cooldown_ends(guild_id)?;
+                if SystemTime::now() < cooldown_ends_at {
+                    let guild_policy =
+                        guild_id.is_some() && SystemTime::now() >= cooldown_ends(None)?.1;
+                    return Ok(LinkPlan::Rejected(VerifyResult::ReverifyOnCooldown {
+                        cooldown, cooldown_ends: cooldown_ends_at, guild_policy,
+                    }))
+                }
             }
         }
 
-        let roblox_link = check_discord.and_then(|x| x.0);
-        let discord_link = conn.query(
-            "SELECT discord_user_id FROM discord_user_info \
-             WHERE roblox_user_id = ?1", roblox_id,
-        ).get_opt::<UserId>()?;
+        let current_link = Verifier::linked_discord_user(conn, roblox_id)?;
+        if mode == VerifyMode::Link && current_link == Some(discord_id) {
+            return Ok(LinkPlan::Renew)
+        }
+        let roblox_link = match mode {
+            VerifyMode::Verify => check_discord.and_then(|x| x.0),
+            VerifyMode::Link => None,
+        };
+        let discord_link = current_link.filter(|&current_id| current_id != discord_id);
         if let Some(current_id) = discord_link {
             // TODO: Add some locking here in case the current_id is verifying currently.
-            if current_id != discord_id {
-                if !allow_reverify_roblox {
-                    return Ok(VerifyResult::RobloxAccountVerifiedTo {
-                        other_discord_id: current_id
-                    })
-                }
-
-                // TODO: Forcefully update this other person's roles somehow.
+            if !allow_reverify_roblox {
+                return Ok(LinkPlan::Rejected(VerifyResult::RobloxAccountVerifiedTo {
+                    other_discord_id: current_id, guild_policy: roblox_guild_policy,
+                }))
+            }
+        }
+        Ok(LinkPlan::Commit { roblox_link, discord_link })
+    }
+    /// Renews the verification of an account that is already linked to a user.
+    fn renew_link(
+        conn: &DatabaseConnection, discord_id: UserId, roblox_id: RobloxUserID, mode: VerifyMode,
+    ) -> Result<()> {
+        conn.transaction(|| {
+            if mode == VerifyMode::Verify {
                 conn.execute(
-                    "UPDATE discord_user_info SET roblox_user_id = NULL \
-                     WHERE roblox_user_id = ?1", roblox_id,
+                    "UPDATE discord_user_info SET last_updated = ?2 \
+                     WHERE discord_user_id = ?1", (discord_id, SystemTime::now()),
                 )?;
             }
-        }
+            conn.execute(
+                "UPDATE discord_linked_accounts SET last_updated = ?2 \
+                 WHERE roblox_user_id = ?1", (roblox_id, SystemTime::now()),
+            )?;
+            // Verifying an imported link confirms it.
+            conn.execute(
+                "UPDATE discord_user_info SET is_imported = 0 \
+                 WHERE discord_user_id = ?1 AND roblox_user_id = ?2", (discord_id, roblox_id),
+            )?;
+            Verifier::add_history(conn, discord_id, roblox_id, false, None, Some("renewed"))?;
+            Ok(())
+        })
+    }
 
+    /// Returns whether a Roblox account must be approved by a moderator before a user can be
+    /// given roles for it in a server.
+    fn needs_approval(
+        &self, conn: &DatabaseConnection, guild_id: Option<GuildId>, discord_id: UserId,
+        roblox_id: RobloxUserID,
+    ) -> Result<bool> {
+        let guild_id = match guild_id {
+            Some(guild_id) => guild_id,
+            None => return Ok(false),
+        };
+        if !self.0.config.get(Some(guild_id), ConfigKeys::RequireVerificationApproval)? {
+            return Ok(false)
+        }
+        Ok(conn.query(
+            "SELECT COUNT(*) FROM approved_verifications \
+             WHERE discord_guild_id = ?1 AND discord_user_id = ?2 AND roblox_user_id = ?3",
+            (guild_id, discord_id, roblox_id),
+        ).get::<u32>()? == 0)
+    }
+    /// Returns whether a user may be given roles for a Roblox account in a server. Servers that
+    /// require approval only allow accounts a moderator has approved, wherever the user
+    /// verified.
+    pub fn is_link_approved(
+        &self, guild_id: GuildId, discord_id: UserId, roblox_id: RobloxUserID,
+    ) -> Result<bool> {
+        let conn = self.0.database.connect()?;
+        Ok(!self.needs_approval(&conn, Some(guild_id), discord_id, roblox_id)?)
+    }
+    /// Writes a new link between a Discord user and a Roblox account, once every check has
+    /// passed.
+    fn commit_link(
+        conn: &DatabaseConnection, discord_id: UserId, roblox_id: RobloxUserID, mode: VerifyMode,
+        roblox_link: Option<RobloxUserID>, discord_link: Option<UserId>,
+        actor: Option<UserId>, note: Option<&str>,
+    ) -> Result<VerifyResult> {
         conn.transaction(|| {
+            // TODO: Forcefully update this other person's roles somehow.
+            if let Some(current_id) = discord_link {
+                Verifier::remove_link(conn, current_id, roblox_id)?;
+            }
+            // Reverifying replaces the primary account rather than keeping it linked.
+            if let Some(old_id) = roblox_link {
+                conn.execute(
+                    "DELETE FROM discord_linked_accounts WHERE roblox_user_id = ?1", old_id,
+                )?;
+            }
+            // Accounts verified without a token have no entry yet.
             conn.execute(
-                "REPLACE INTO discord_user_info (discord_user_id, roblox_user_id, last_updated) \
-                 VALUES (?1, ?2, ?3)", (discord_id, roblox_id, SystemTime::now()),
+                "INSERT OR IGNORE INTO roblox_user_info (roblox_user_id, last_updated) \
+                 VALUES (?1, ?2)", (roblox_id, SystemTime::now()),
             )?;
             conn.execute(
-                "INSERT INTO user_history (\
-                     discord_user_id, roblox_user_id, is_unverify, last_updated\
-                 ) VALUES (?1, ?2, ?3, ?4)", (discord_id, roblox_id, false, SystemTime::now()),
+                "REPLACE INTO discord_linked_accounts (\
+                     roblox_user_id, discord_user_id, last_updated\
+                 ) VALUES (?1, ?2, ?3)", (roblox_id, discord_id, SystemTime::now()),
             )?;
-            Ok(())
+            if mode == VerifyMode::Verify {
+                conn.execute(
+                    "REPLACE INTO discord_user_info (\
+                         discord_user_id, roblox_user_id, last_updated\
+                     ) VALUES (?1, ?2, ?3)", (discord_id, roblox_id, SystemTime::now()),
+                )?;
+            }
+            Verifier::add_history(conn, discord_id, roblox_id, false, actor, note)
         })?;
 
-        if roblox_link.is_some() || discord_link.is_some() {
+        if mode == VerifyMode::Link {
+            Ok(VerifyResult::LinkOk { discord_link })
+        } else if roblox_link.is_some() || discord_link.is_some() {
             Ok(VerifyResult::ReverifyOk { roblox_link, discord_link })
         } else {
             Ok(VerifyResult::VerificationOk)
@@ -444,19 +1390,21 @@ impl Verifier {
                         "Someone else is currently trying to verify as that Roblox account. \
                          Please wait for their attempt to finish.");
 
+            let linked = self.get_linked_accounts(discord_id)?;
             let conn = self.0.database.connect()?;
             conn.transaction(|| {
                 conn.execute(
                     "UPDATE discord_user_info \
-                     SET roblox_user_id = NULL \
+                     SET roblox_user_id = NULL, is_imported = 0 \
                      WHERE discord_user_id = ?1",
                     discord_id,
                 )?;
                 conn.execute(
-                    "INSERT INTO user_history (\
-                         discord_user_id, roblox_user_id, is_unverify, last_updated\
-                     ) VALUES (?1, ?2, ?3, ?4)", (discord_id, roblox_id, true, SystemTime::now()),
+                    "DELETE FROM discord_linked_accounts WHERE discord_user_id = ?1", discord_id,
                 )?;
+                for account in &linked {
+                    Verifier::add_history(&conn, discord_id, account.roblox_id, true, None, None)?;
+                }
                 Ok(())
             })?;
         } else {
@@ -465,11 +1413,338 @@ impl Verifier {
         Ok(())
     }
 
+    /// Links a Roblox account to a Discord user on a moderator's behalf, without requiring
+    /// proof of ownership. Returns the Roblox account and Discord user previously linked, if
+    /// any.
+    pub fn force_verify(
+        &self, discord_id: UserId, roblox_id: RobloxUserID, actor: UserId, reason: &str,
+    ) -> Result<(Option<RobloxUserID>, Option<UserId>)> {
+        debug!("Starting forced verification: discord id {} -> roblox id {}, forced by {}",
+               discord_id.0, roblox_id.0, actor.0);
+
+        let discord_lock = self.0.discord_lock.lock(discord_id);
+        cmd_ensure!(discord_lock.is_some(),
+                    "That user is currently verifying. Please try again in a moment.");
+        let roblox_lock = self.0.roblox_lock.lock(roblox_id);
+        cmd_ensure!(roblox_lock.is_some(),
+                    "Someone is currently verifying as that Roblox account. Please try again \
+                     in a moment.");
+
+        self.check_bans(None, discord_id, roblox_id, "That user is")?;
+
+        let conn = self.0.database.connect()?;
+        let current_id = conn.query(
+            "SELECT roblox_user_id FROM discord_user_info WHERE discord_user_id = ?1", discord_id,
+        ).get_opt::<Option<RobloxUserID>>()?.and_then(|x| x);
+        cmd_ensure!(current_id != Some(roblox_id),
+                    "That user is already verified as that account.");
+        let discord_link = Verifier::linked_discord_user(&conn, roblox_id)?
+            .filter(|&id| id != discord_id);
+
+        let note = format!("manual link: {}", reason);
+        Verifier::commit_link(&conn, discord_id, roblox_id, VerifyMode::Verify, current_id,
+                              discord_link, Some(actor), Some(&note))?;
+        Ok((current_id, discord_link))
+    }
+
+    /// Links accounts read from another bot's export. Existing links are only replaced if the
+    /// conflict policy allows it, and users who are currently verifying are skipped.
+    pub fn import_links(
+        &self, links: &[(UserId, RobloxUserID)], policy: ImportConflictPolicy,
+        actor: Option<UserId>,
+    ) -> Result<ImportReport> {
+        debug!("Importing {} links with policy {:?}", links.len(), policy);
+
+        let conn = self.0.database.connect()?;
+        let mut report = ImportReport::default();
+        for &(discord_id, roblox_id) in links {
+            let discord_lock = self.0.discord_lock.lock(discord_id);
+            let roblox_lock = self.0.roblox_lock.lock(roblox_id);
+            if discord_lock.is_none() || roblox_lock.is_none() {
+                report.busy += 1;
+                continue
+            }
+
+            let current_id = conn.query(
+                "SELECT roblox_user_id FROM discord_user_info WHERE discord_user_id = ?1",
+                discord_id,
+            ).get_opt::<Option<RobloxUserID>>()?.and_then(|x| x);
+            let discord_link = Verifier::linked_discord_user(&conn, roblox_id)?
+                .filter(|&id| id != discord_id);
+            if current_id == Some(roblox_id) && discord_link.is_none() {
+                report.unchanged += 1;
+            } else if (current_id.is_some() || discord_link.is_some()) &&
+                      policy == ImportConflictPolicy::Skip {
+                report.conflicts.push((discord_id, roblox_id));
+            } else {
+                conn.transaction(|| {
+                    Verifier::commit_link(&conn, discord_id, roblox_id, VerifyMode::Verify,
+                                          current_id, discord_link, actor, Some(IMPORTED_NOTE))?;
+                    conn.execute(
+                        "UPDATE discord_user_info SET is_imported = 1 WHERE discord_user_id = ?1",
+                        discord_id,
+                    )?;
+                    Ok(())
+                })?;
+                report.imported += 1;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Makes one of a user's linked Roblox accounts their primary account.
+    pub fn set_primary_account(&self, discord_id: UserId, roblox_id: RobloxUserID) -> Result<()> {
+        let discord_lock = self.0.discord_lock.lock(discord_id);
+        cmd_ensure!(discord_lock.is_some(),
+                    "Please wait for your last verification attempt to finish.");
+
+        let conn = self.0.database.connect()?;
+        cmd_ensure!(Verifier::linked_discord_user(&conn, roblox_id)? == Some(discord_id),
+                    "That Roblox account is not linked to your Discord account.");
+        conn.execute(
+            "UPDATE discord_user_info SET roblox_user_id = ?2, is_imported = 0 \
+             WHERE discord_user_id = ?1", (discord_id, roblox_id),
+        )?;
+        Ok(())
+    }
+    /// Removes an additional Roblox account from a user. Primary accounts must be removed by
+    /// unverifying instead.
+    pub fn unlink_account(&self, discord_id: UserId, roblox_id: RobloxUserID) -> Result<()> {
+        debug!("Unlinking account: discord id {} -> roblox id {}", discord_id.0, roblox_id.0);
+
+        let discord_lock = self.0.discord_lock.lock(discord_id);
+        cmd_ensure!(discord_lock.is_some(),
+                    "Please wait for your last verification attempt to finish.");
+
+        let conn = self.0.database.connect()?;
+        cmd_ensure!(Verifier::linked_discord_user(&conn, roblox_id)? == Some(discord_id),
+                    "That Roblox account is not linked to your Discord account.");
+        cmd_ensure!(self.get_verified_roblox_user(discord_id)? != Some(roblox_id),
+                    "That Roblox account is your primary account. Set another account as your \
+                     primary account first, or unverify instead.");
+        conn.transaction(|| {
+            conn.execute(
+                "DELETE FROM discord_linked_accounts WHERE roblox_user_id = ?1", roblox_id,
+            )?;
+            Verifier::add_history(&conn, discord_id, roblox_id, true, None, None)
+        })
+    }
+    /// Unverifies a user, and deletes their verification history, cooldowns, and pending and
+    /// approved verifications. Returns the Roblox accounts that were linked to them.
+    pub fn forget_user(&self, discord_id: UserId) -> Result<Vec<RobloxUserID>> {
+        debug!("Forgetting user: discord id {}", discord_id.0);
+
+        let discord_lock = self.0.discord_lock.lock(discord_id);
+        cmd_ensure!(discord_lock.is_some(),
+                    "Please wait for your last verification attempt to finish.");
+
+        let linked: Vec<_> =
+            self.get_linked_accounts(discord_id)?.into_iter().map(|x| x.roblox_id).collect();
+        let mut roblox_locks = Vec::new();
+        for &roblox_id in &linked {
+            let roblox_lock = self.0.roblox_lock.lock(roblox_id);
+            cmd_ensure!(roblox_lock.is_some(),
+                        "Someone else is currently trying to verify as that Roblox account. \
+                         Please wait for their attempt to finish.");
+            roblox_locks.push(roblox_lock);
+        }
+
+        let conn = self.0.database.connect()?;
+        conn.transaction(|| {
+            conn.execute("DELETE FROM discord_user_info WHERE discord_user_id = ?1", discord_id)?;
+            conn.execute(
+                "DELETE FROM discord_linked_accounts WHERE discord_user_id = ?1", discord_id,
+            )?;
+            conn.execute(
+                "DELETE FROM pending_verifications WHERE discord_user_id = ?1", discord_id,
+            )?;
+            conn.execute(
+                "DELETE FROM approved_verifications WHERE discord_user_id = ?1", discord_id,
+            )?;
+            conn.execute("DELETE FROM user_history WHERE discord_user_id = ?1", discord_id)?;
+            conn.execute(
+                "UPDATE user_history SET actor_discord_id = NULL WHERE actor_discord_id = ?1",
+                discord_id,
+            )?;
+            conn.execute(
+                "DELETE FROM verification_cooldown WHERE discord_user_id = ?1", discord_id,
+            )?;
+            Ok(())
+        })?;
+        Ok(linked)
+    }
+
+    pub fn get_pending_verification(
+        &self, guild_id: GuildId, discord_id: UserId,
+    ) -> Result<Option<PendingVerification>> {
+        Ok(self.0.database.connect()?.query(
+            &format!("SELECT {} FROM pending_verifications \
+                      WHERE discord_guild_id = ?1 AND discord_user_id = ?2 AND expires > ?3",
+                     PENDING_COLUMNS),
+            (guild_id, discord_id, SystemTime::now()),
+        ).get_opt::<PendingRow>()?.map(PendingVerification::from_row))
+    }
+    pub fn get_pending_verifications(&self, guild_id: GuildId) -> Result<Vec<PendingVerification>> {
+        Ok(self.0.database.connect()?.query(
+            &format!("SELECT {} FROM pending_verifications \
+                      WHERE discord_guild_id = ?1 AND expires > ?2 ORDER BY created",
+                     PENDING_COLUMNS),
+            (guild_id, SystemTime::now()),
+        ).get_all::<PendingRow>()?.into_iter().map(PendingVerification::from_row).collect())
+    }
+    /// Finds the pending verification a message in a review channel was posted for.
+    pub fn find_pending_by_message(
+        &self, channel_id: ChannelId, message_id: MessageId,
+    ) -> Result<Option<PendingVerification>> {
+        Ok(self.0.database.connect()?.query(
+            &format!("SELECT {} FROM pending_verifications \
+                      WHERE review_message_id = ?1 AND review_channel_id = ?2 AND expires > ?3",
+                     PENDING_COLUMNS),
+            (message_id, channel_id, SystemTime::now()),
+        ).get_opt::<PendingRow>()?.map(PendingVerification::from_row))
+    }
+    pub fn set_review_message(
+        &self, guild_id: GuildId, discord_id: UserId, channel_id: ChannelId, message_id: MessageId,
+    ) -> Result<()> {
+        self.0.database.connect()?.execute(
+            "UPDATE pending_verifications SET review_channel_id = ?3, review_message_id = ?4 \
+             WHERE discord_guild_id = ?1 AND discord_user_id = ?2",
+            (guild_id, discord_id, channel_id, message_id),
+        )?;
+        Ok(())
+    }
+
+    /// Approves a verification waiting for approval in a server, linking the account. The
+    /// checks made when the verification was requested are made again, as the user's accounts
+    /// or the server's settings may have changed since.
+    pub fn approve_verification(
+        &self, guild_id: GuildId, discord_id: UserId, actor: UserId,
+    ) -> Result<(PendingVerification, VerifyResult)> {
+        let pending = self.get_pending_verification(guild_id, discord_id)?
+            .to_cmd_err(|| "That user has no verification waiting for approval.")?;
+        let roblox_id = pending.roblox_id;
+        debug!("Approving verification: discord id {} -> roblox id {}, approved by {}",
+               discord_id.0, roblox_id.0, actor.0);
+
+        let discord_lock = self.0.discord_lock.lock(discord_id);
+        cmd_ensure!(discord_lock.is_some(),
+                    "That user is currently verifying. Please try again in a moment.");
+        let roblox_lock = self.0.roblox_lock.lock(roblox_id);
+        cmd_ensure!(roblox_lock.is_some(),
+                    "Someone is currently verifying as that Roblox account. Please try again \
+                     in a moment.");
+
+        let conn = self.0.database.connect()?;
+        self.check_bans(Some(guild_id), discord_id, roblox_id, "That user is")?;
+        let check_discord = conn.query(
+            "SELECT roblox_user_id, last_updated FROM discord_user_info \
+             WHERE discord_user_id = ?1", discord_id
+        ).get_opt::<(Option<RobloxUserID>, SystemTime)>()?;
+        let mode = if pending.is_link { VerifyMode::Link } else { VerifyMode::Verify };
+        if mode == VerifyMode::Link {
+            cmd_ensure!(check_discord.map_or(false, |x| x.0.is_some()),
+                        "That user is no longer verified, so the account cannot be linked.");
+            if Verifier::linked_discord_user(&conn, roblox_id)? != Some(discord_id) {
+                let max_links = self.0.config.get(None, ConfigKeys::MaxLinkedAccounts)?;
+                cmd_ensure!(Verifier::link_count(&conn, discord_id)? < max_links,
+                            "That user already has {} linked Roblox accounts.", max_links);
+            }
+        }
+
+        let plan =
+            self.plan_link(&conn, Some(guild_id), discord_id, roblox_id, mode, check_discord)?;
+        let result = conn.transaction(|| {
+            let result = match plan {
+                LinkPlan::Rejected(VerifyResult::SenderVerifiedAs { .. }) =>
+                    cmd_error!("That user has since verified as another Roblox account, and \
+                                this server does not allow them to change it."),
+                LinkPlan::Rejected(VerifyResult::RobloxAccountVerifiedTo { .. }) =>
+                    cmd_error!("That Roblox account has since been verified by someone else, \
+                                and this server does not allow it to be taken over."),
+                LinkPlan::Rejected(VerifyResult::ReverifyOnCooldown { cooldown_ends, .. }) =>
+                    cmd_error!("That user has reverified too recently. Please try again in {}.",
+                               util::english_time_diff(SystemTime::now(), cooldown_ends)),
+                LinkPlan::Rejected(_) =>
+                    cmd_error!("That verification can no longer be approved."),
+                LinkPlan::Renew => {
+                    Verifier::renew_link(&conn, discord_id, roblox_id, mode)?;
+                    VerifyResult::RefreshOk
+                }
+                LinkPlan::Commit { roblox_link, discord_link } =>
+                    Verifier::commit_link(&conn, discord_id, roblox_id, mode, roblox_link,
+                                          discord_link, Some(actor), Some("approved"))?,
+            };
+            conn.execute(
+                "REPLACE INTO approved_verifications (\
+                     discord_guild_id, discord_user_id, roblox_user_id, approved, \
+                     actor_discord_id\
+                 ) VALUES (?1, ?2, ?3, ?4, ?5)",
+                (guild_id, discord_id, roblox_id, SystemTime::now(), actor),
+            )?;
+            conn.execute(
+                "DELETE FROM pending_verifications \
+                 WHERE discord_guild_id = ?1 AND discord_user_id = ?2",
+                (guild_id, discord_id),
+            )?;
+            Ok(result)
+        })?;
+        Ok((pending, result))
+    }
+    /// Denies a verification waiting for approval in a server, and records the decision in
+    /// the user's history.
+    pub fn deny_verification(
+        &self, guild_id: GuildId, discord_id: UserId, actor: UserId,
+    ) -> Result<PendingVerification> {
+        let pending = self.get_pending_verification(guild_id, discord_id)?
+            .to_cmd_err(|| "That user has no verification waiting for approval.")?;
+        debug!("Denying verification: discord id {} -> roblox id {}, denied by {}",
+               discord_id.0, pending.roblox_id.0, actor.0);
+
+        let conn = self.0.database.connect()?;
+        conn.transaction(|| {
+            conn.execute(
+                "DELETE FROM pending_verifications \
+                 WHERE discord_guild_id = ?1 AND discord_user_id = ?2",
+                (guild_id, discord_id),
+            )?;
+            Verifier::add_history(&conn, discord_id, pending.roblox_id, true, Some(actor),
+                                  Some("verification denied"))
+        })?;
+        Ok(pending)
+    }
+    fn expire_pending_verifications(&self) -> Result<()> {
+        let conn = self.0.database.connect()?;
+        let expired = conn.transaction(|| {
+            let now = SystemTime::now();
+            let expired = conn.query(
+                "SELECT discord_user_id, roblox_user_id FROM pending_verifications \
+                 WHERE expires <= ?1", now,
+            ).get_all::<(UserId, RobloxUserID)>()?;
+            for &(discord_id, roblox_id) in &expired {
+                Verifier::add_history(&conn, discord_id, roblox_id, true, None,
+                                      Some("approval expired"))?;
+            }
+            conn.execute("DELETE FROM pending_verifications WHERE expires <= ?1", now)?;
+            Ok(expired.len())
+        })?;
+        if expired != 0 {
+            debug!("Removed {} expired verification requests.", expired);
+        }
+        Ok(())
+    }
+
     pub fn add_config<'a>(&self, config: &'a mut Vec<LuaConfigEntry>) {
-        self.0.token_ctx.read().current.add_config(config)
+        self.0.token_ctx.read().add_config(config);
+        config.push(LuaConfigEntry::new("pairing_code_length", false, PAIRING_CODE_LENGTH));
     }
 
     pub fn on_cleanup_tick(&self) {
+        let now = SystemTime::now();
+        self.0.profile_phrases.lock().retain(|_, phrase| phrase.expires > now);
+        self.0.pairing_codes.lock().retain(|_, pairing| pairing.expires > now);
+        if let Err(e) = self.expire_pending_verifications() {
+            warn!("Could not remove expired verification requests: {}", e);
+        }
         self.0.discord_lock.shrink_to_fit();
         self.0.roblox_lock.shrink_to_fit();
     }
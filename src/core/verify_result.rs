@@ -0,0 +1,460 @@
+use core::VerifierCore;
+use core::audit::*;
+use core::config::*;
+use core::roles::*;
+use core::verification_log::*;
+use core::verifier::*;
+use errors::*;
+use roblox::*;
+use serenity::model::prelude::*;
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::time::SystemTime;
+use util;
+
+pub fn verify_status_str(prefix: &str, result: SetRolesStatus) -> Cow<'static, str> {
+    match result {
+        SetRolesStatus::Success {
+            nickname_admin_error, determine_roles_error, set_roles_error, ..
+        } => {
+            if determine_roles_error || set_roles_error {
+                format!(
+                    "An error occurred while {}, and some of your roles may not have been set. \
+                     Please wait a while then use the '{}update' command.",
+                    if determine_roles_error {
+                        "looking up your Roblox account"
+                    } else {
+                        "setting your Discord roles"
+                    },
+                    prefix,
+                ).into()
+            } else {
+                format!(
+                    "Your roles have been updated.{}",
+                    if nickname_admin_error {
+                        " Your nickname was not set as this bot does not have the permissions \
+                          needed to edit it."
+                    } else { "" }
+                ).into()
+            }
+        }
+        SetRolesStatus::NotVerified =>
+            "Your roles were not updated as you are not verified.".into(),
+        SetRolesStatus::Banned =>
+            "Your roles were removed as you are banned from verifying in this server.".into(),
+        SetRolesStatus::OnHold =>
+            "Your roles will be assigned once a moderator has reviewed your verification.".into(),
+        SetRolesStatus::Expired =>
+            format!("Your roles were removed as your verification has expired. Please use the \
+                     '{}verify' command to verify again.", prefix).into(),
+        SetRolesStatus::Unconfirmed =>
+            format!("Your verification was imported from another bot, and this server requires \
+                     you to confirm it. Please use the '{}verify' command to verify again.",
+                    prefix).into(),
+        SetRolesStatus::Unapproved =>
+            format!("This server requires a moderator to approve your verification. Please use \
+                     the '{}verify' command in this server to request approval.", prefix).into(),
+    }
+}
+pub fn guild_name(guild_id: GuildId) -> String {
+    match guild_id.to_guild_cached() {
+        Some(guild) => guild.read().name.clone(),
+        None => format!("(server #{})", guild_id.0),
+    }
+}
+fn guild_status_str(result: &Result<SetRolesStatus>) -> Cow<'static, str> {
+    match result {
+        Ok(SetRolesStatus::Success { determine_roles_error: true, .. }) |
+        Ok(SetRolesStatus::Success { set_roles_error: true, .. }) =>
+            "An error occurred, and some of your roles may not have been set.".into(),
+        Ok(SetRolesStatus::Success { nickname_admin_error: true, .. }) =>
+            "Your roles have been updated, but your nickname could not be set.".into(),
+        Ok(SetRolesStatus::Success { .. }) => "Your roles have been updated.".into(),
+        Ok(SetRolesStatus::NotVerified) =>
+            "Your roles were not updated as you are not verified.".into(),
+        Ok(SetRolesStatus::Banned) =>
+            "Your roles were removed as you are banned from verifying in this server.".into(),
+        Ok(SetRolesStatus::OnHold) =>
+            "Your roles will be assigned once a moderator has reviewed your verification.".into(),
+        Ok(SetRolesStatus::Expired) =>
+            "Your roles were removed as your verification in this server has expired.".into(),
+        Ok(SetRolesStatus::Unconfirmed) =>
+            "Your roles were not assigned as this server requires you to verify again.".into(),
+        Ok(SetRolesStatus::Unapproved) =>
+            "Your roles were not assigned as this server requires a moderator to approve your \
+             verification. Please verify again in this server to request approval.".into(),
+        Err(e) => match &(e.0).0 {
+            ErrorKind::CommandError(err) => err.clone(),
+            _ => "An error occurred while updating your roles.".into(),
+        },
+    }
+}
+pub fn roles_summary(
+    prefix: &str, results: &[(GuildId, Result<SetRolesStatus>)],
+) -> Result<String> {
+    if results.is_empty() {
+        return Ok("You are not in any servers with this bot, so no roles were updated.".to_owned())
+    }
+    let mut summary = String::new();
+    let mut had_error = false;
+    for (guild_id, result) in results {
+        had_error |= match result {
+            Ok(SetRolesStatus::Success { determine_roles_error, set_roles_error, .. }) =>
+                *determine_roles_error || *set_roles_error,
+            Ok(SetRolesStatus::NotVerified) | Ok(SetRolesStatus::Banned) |
+            Ok(SetRolesStatus::OnHold) | Ok(SetRolesStatus::Expired) |
+            Ok(SetRolesStatus::Unconfirmed) | Ok(SetRolesStatus::Unapproved) => false,
+            Err(_) => true,
+        };
+        writeln!(summary, "• **{}**: {}", guild_name(*guild_id), guild_status_str(result))?;
+    }
+    if had_error {
+        writeln!(summary, "Please wait a while then use the '{}update' command to try again.",
+                 prefix)?;
+    }
+    Ok(summary)
+}
+fn reverify_help(
+    core: &VerifierCore, prefix: &str, discord_id: UserId, roblox_id: RobloxUserID,
+) -> Result<String> {
+    if core.verifier().get_verified_roblox_user(discord_id)? == Some(roblox_id) {
+        Ok(format!(" If you only want to update your roles, use the '{}update' command.",
+                   prefix))
+    } else {
+        Ok(String::new())
+    }
+}
+/// Updates a user's roles after their linked accounts change, in the current server and every
+/// other server they share with the bot.
+pub fn update_linked_roles(
+    core: &VerifierCore, prefix: &str, guild_id: Option<GuildId>, discord_id: UserId,
+    roblox_id: Option<RobloxUserID>, message: &str,
+) -> Result<String> {
+    match guild_id {
+        Some(guild_id) => {
+            let status = core.roles().assign_roles(guild_id, discord_id, roblox_id)?;
+            core.roles().propagate_roles(guild_id, discord_id, roblox_id);
+            let status = verify_status_str(prefix, status);
+            Ok(if message.is_empty() {
+                status.into_owned()
+            } else {
+                format!("{} {}", message, status)
+            })
+        }
+        None => {
+            let results = core.roles().assign_roles_all(discord_id, roblox_id, None)?;
+            Ok(format!("{}\n{}", message, roles_summary(prefix, &results)?))
+        }
+    }
+}
+/// Explains that a verification was blocked by the settings of the current server, rather than
+/// the bot's global settings.
+fn guild_policy_note(guild_policy: bool) -> &'static str {
+    if guild_policy {
+        " This server has stricter verification settings than other servers using this bot."
+    } else {
+        ""
+    }
+}
+fn policy_log(guild_id: Option<GuildId>, guild_policy: bool) -> String {
+    match guild_id {
+        Some(guild_id) if guild_policy =>
+            format!(" (blocked by the settings of {})", guild_name(guild_id)),
+        _ => String::new(),
+    }
+}
+/// Runs alt account detection in the background after a successful verification. Errors are
+/// only logged, as they should not cause the verification itself to fail.
+pub fn check_alts(core: &VerifierCore, discord_id: UserId, roblox_id: RobloxUserID) {
+    core.tasks().dispatch_task(move |core| {
+        let result = core.roles().mutual_guilds(discord_id).and_then(|guilds|
+            core.alt_detector().check_verification(&guilds, discord_id, roblox_id)
+        ).and_then(|held| {
+            // Roles may have been given before the check finished.
+            let primary_id = core.verifier().get_verified_roblox_user(discord_id)?;
+            for guild_id in held {
+                core.roles().assign_roles(guild_id, discord_id, primary_id)?;
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            warn!("Could not run alt account detection for {}: {}", discord_id, e);
+        }
+        Ok(())
+    })
+}
+
+pub fn format_discord_id(id: UserId) -> String {
+    match id.to_user() {
+        Ok(user) => format!("{} (`{}`)", user.tag(), id.0),
+        Err(_) => format!("*(non-existent Discord id #{})*", id.0),
+    }
+}
+pub fn format_roblox_id(id: RobloxUserID) -> String {
+    match id.lookup_username() {
+        Ok(username) => username,
+        Err(_) => format!("*(non-existent Roblox id #{})*", id.0),
+    }
+}
+
+pub fn get_discord_username(discord_id: UserId) -> String {
+    match discord_id.to_user_cached() {
+        Some(x) => x.read().tag(),
+        None => match discord_id.to_user() {
+            Ok(x) => x.tag(),
+            Err(_) => format!("(discord uid #{})", discord_id.0),
+        }
+    }
+}
+
+pub fn finish_verify(
+    core: &VerifierCore, prefix: &str, guild_id: Option<GuildId>, user: &User,
+    roblox_username: &str, roblox_id: RobloxUserID, result: VerifyResult, method: &str,
+) -> Result<String> {
+    let discord_username = user.tag();
+    let discord_id = user.id;
+
+    let discord_display = format!("{} (`{}`)", discord_username, discord_id.0);
+    let roblox_display = format!("`{}` (https://www.roblox.com/users/{}/profile)",
+                                 roblox_username, roblox_id.0);
+
+    macro_rules! verify_status {
+        ($event:expr, $($format:tt)*) => {
+            let buffer = format!($($format)*);
+            info!("{}", buffer);
+            core.verification_log().log(guild_id, $event, Some(discord_id), Some(roblox_id),
+                                        &buffer);
+        }
+    }
+    let audit = |event_type, payload| {
+        core.audit().record(event_type, guild_id, Some(discord_id), Some(discord_id),
+                            Some(roblox_id), payload)
+    };
+    let audit_failed = |reason: &str| {
+        audit(AuditEventType::VerifyFailed, json!({ "reason": reason, "method": method.trim() }))
+    };
+    match result {
+        VerifyResult::VerificationOk => {
+            verify_status!(LogEvent::Verify, "{} successfully verified as {}{}",
+                           discord_display, roblox_display, method);
+            audit(AuditEventType::Verify, json!({ "method": method.trim() }));
+            check_alts(core, discord_id, roblox_id);
+        }
+        VerifyResult::ReverifyOk { discord_link, roblox_link } => {
+            let discord_link_display = if let Some(discord_id) = discord_link {
+                let discord_username = discord_id.to_user()?.tag();
+                format!("Old Discord account: {} (`{}`)", discord_username, discord_id.0)
+            } else {
+                format!("No old Discord account")
+            };
+            let roblox_link_display = if let Some(roblox_id) = roblox_link {
+                let roblox_username = roblox_id.lookup_username()?;
+                format!("Old Roblox account: `{}` (https://www.roblox.com/users/{}/profile)",
+                        roblox_username, roblox_id.0)
+            } else {
+                format!("No old Roblox account")
+            };
+            verify_status!(LogEvent::Reverify, "{} successfully reverified as {}{}\n{}; {}",
+                           discord_display, roblox_display, method,
+                           discord_link_display, roblox_link_display);
+            audit(AuditEventType::Reverify, json!({
+                "method": method.trim(),
+                "old_discord_id": discord_link.map(|x| x.0),
+                "old_roblox_id": roblox_link.map(|x| x.0),
+            }));
+            check_alts(core, discord_id, roblox_id);
+        }
+        VerifyResult::TokenAlreadyUsed => {
+            verify_status!(LogEvent::Failure, "{} failed to verify as {}: Token already used.",
+                           discord_display, roblox_display);
+            audit_failed("token_already_used");
+            cmd_error!("Someone has already used that verification code. Please wait for a \
+                        new code to be generated, then try again.")
+        }
+        VerifyResult::VerificationPlaceOutdated => {
+            verify_status!(LogEvent::Failure,
+                           "{} failed to verify as {}: Outdated verification place.",
+                           discord_display, roblox_display);
+            audit_failed("place_outdated");
+            cmd_error!("The verification place is outdated, and has not been updated with the \
+                        verification bot. Please contact the bot owner.")
+        }
+        VerifyResult::InvalidToken => {
+            verify_status!(LogEvent::Failure, "{} failed to verify as {}: Invalid token.",
+                           discord_display, roblox_display);
+            audit_failed("invalid_token");
+            cmd_error!("The verification code you used is not valid. Please check the code \
+                        you entered and try again.")
+        }
+        VerifyResult::PhraseNotFound => {
+            verify_status!(LogEvent::Failure,
+                           "{} failed to verify as {}: Phrase not found in profile.",
+                           discord_display, roblox_display);
+            audit_failed("phrase_not_found");
+            cmd_error!("The verification phrase was not found in the profile description of {}. \
+                        Please check that you saved your profile, then try again.",
+                       roblox_username)
+        }
+        VerifyResult::TooManyAttempts { max_attempts, cooldown, cooldown_ends, guild_policy } => {
+            verify_status!(LogEvent::Failure, "{} failed to verify as {}: Too many attempts.{}",
+                           discord_display, roblox_display, policy_log(guild_id, guild_policy));
+            audit_failed("too_many_attempts");
+            cmd_error!("You can only try to verify {} times every {}. \
+                        Please try again in {}.{}{}",
+                       max_attempts, util::to_english_time(cooldown),
+                       util::english_time_diff(SystemTime::now(), cooldown_ends),
+                       guild_policy_note(guild_policy),
+                       reverify_help(core, prefix, discord_id, roblox_id)?)
+        }
+        VerifyResult::SenderVerifiedAs { other_roblox_id, guild_policy } => {
+            let other_roblox_username = other_roblox_id.lookup_username()?;
+            verify_status!(LogEvent::Failure,
+                           "{} failed to verify as {}: Already verified as {}.{}",
+                           discord_display, roblox_display, other_roblox_username,
+                           policy_log(guild_id, guild_policy));
+            audit_failed("already_verified");
+            cmd_error!("You are already verified as {}.{}{}",
+                       other_roblox_username,
+                       if guild_policy {
+                           " This server does not allow changing the Roblox account you are \
+                            verified with, although other servers using this bot do."
+                       } else { "" },
+                       reverify_help(core, prefix, discord_id, roblox_id)?)
+        }
+        VerifyResult::RobloxAccountVerifiedTo { other_discord_id, guild_policy } => {
+            let other_discord_username = get_discord_username(other_discord_id);
+            verify_status!(LogEvent::Failure,
+                           "{} failed to verify as {}: Roblox account already verified to {}.{}",
+                           discord_display, roblox_display, other_discord_username,
+                           policy_log(guild_id, guild_policy));
+            audit_failed("roblox_account_taken");
+            cmd_error!("{} has already verified as {}.{}",
+                       other_discord_username, roblox_username,
+                       if guild_policy {
+                           " This server does not allow verifying as a Roblox account that is \
+                            already verified, although other servers using this bot do."
+                       } else { "" })
+        }
+        VerifyResult::ReverifyOnCooldown { cooldown, cooldown_ends, guild_policy } => {
+            verify_status!(LogEvent::Failure, "{} failed to verify as {}: Reverified too soon.{}",
+                           discord_display, roblox_display, policy_log(guild_id, guild_policy));
+            audit_failed("reverify_cooldown");
+            cmd_error!("You can only reverify once every {}. Please try again in {}.{}{}",
+                       util::to_english_time(cooldown),
+                       util::english_time_diff(SystemTime::now(), cooldown_ends),
+                       guild_policy_note(guild_policy),
+                       reverify_help(core, prefix, discord_id, roblox_id)?)
+        }
+        VerifyResult::PendingApproval { expires } => {
+            verify_status!(LogEvent::Verify, "{} verified as {}{}, and is waiting for approval",
+                           discord_display, roblox_display, method);
+            audit(AuditEventType::VerifyPending, json!({ "method": method.trim() }));
+            if let Some(guild_id) = guild_id {
+                if let Err(e) = post_review_request(core, prefix, guild_id, discord_id) {
+                    warn!("Could not post verification review request: {}", e);
+                }
+            }
+            return Ok(format!("Your verification as {} has been sent to the moderators of this \
+                               server for approval. It will expire if it is not reviewed within \
+                               {}.",
+                              roblox_username,
+                              util::english_time_diff(SystemTime::now(), expires)))
+        }
+        VerifyResult::RefreshOk => {
+            verify_status!(LogEvent::Verify, "{} renewed their verification as {}{}",
+                           discord_display, roblox_display, method);
+            audit(AuditEventType::Renew, json!({ "method": method.trim() }));
+            return update_linked_roles(core, prefix, guild_id, discord_id, Some(roblox_id),
+                                       &format!("Your verification as {} has been renewed.",
+                                                roblox_username))
+        }
+        VerifyResult::LinkOk { discord_link } => {
+            let discord_link_display = if let Some(discord_id) = discord_link {
+                let discord_username = discord_id.to_user()?.tag();
+                format!("\nOld Discord account: {} (`{}`)", discord_username, discord_id.0)
+            } else {
+                String::new()
+            };
+            verify_status!(LogEvent::Verify,
+                           "{} successfully linked {} as an additional account{}{}",
+                           discord_display, roblox_display, method, discord_link_display);
+            audit(AuditEventType::Link, json!({
+                "method": method.trim(), "old_discord_id": discord_link.map(|x| x.0),
+            }));
+            check_alts(core, discord_id, roblox_id);
+            let primary_id = core.verifier().get_verified_roblox_user(discord_id)?;
+            return update_linked_roles(core, prefix, guild_id, discord_id, primary_id,
+                                       &format!("{} has been linked to your Discord account.",
+                                                roblox_username))
+        }
+    }
+
+    let message = match guild_id {
+        Some(_) => String::new(),
+        None => format!("You have been verified as {}.", roblox_username),
+    };
+    update_linked_roles(core, prefix, guild_id, discord_id, Some(roblox_id), &message)
+}
+
+/// Completes a verification that was finished outside of Discord, such as on the bot's web
+/// server, returning the message shown to the user. The user is also sent the result in a
+/// direct message.
+pub fn complete_verification(
+    core: &VerifierCore, guild_id: Option<GuildId>, discord_id: UserId, roblox_id: RobloxUserID,
+    method: &str, verify: impl FnOnce() -> Result<VerifyResult>,
+) -> Result<String> {
+    let result = (|| {
+        let user = discord_id.to_user()?;
+        let prefix = core.config().get(None, ConfigKeys::CommandPrefix)?;
+        let roblox_username = roblox_id.lookup_username()?;
+        let result = verify()?;
+        finish_verify(core, &prefix, guild_id, &user, &roblox_username, roblox_id, result, method)
+    })();
+    let message = match result {
+        Ok(ref message) => message.clone(),
+        Err(match_err!(ErrorKind::CommandError(ref err))) => err.to_string(),
+        Err(_) => "An error occurred while verifying your account. Please try again later, \
+                   or contact the bot owner if this continues.".to_owned(),
+    };
+    if let Ok(channel) = discord_id.create_dm_channel() {
+        channel.say(&message).ok();
+    }
+    result
+}
+
+pub const APPROVE_EMOJI: &str = "✅";
+pub const DENY_EMOJI: &str = "❌";
+
+/// Posts a verification waiting for approval to the server's review channel, if it has one.
+fn post_review_request(
+    core: &VerifierCore, prefix: &str, guild_id: GuildId, discord_id: UserId,
+) -> Result<()> {
+    let channel_id = match core.config().get(Some(guild_id),
+                                             ConfigKeys::VerificationReviewChannel)? {
+        Some(channel_id) => ChannelId(channel_id),
+        None => return Ok(()),
+    };
+    let pending = match core.verifier().get_pending_verification(guild_id, discord_id)? {
+        Some(pending) => pending,
+        None => return Ok(()),
+    };
+    let use_reactions = core.config().get(Some(guild_id),
+                                          ConfigKeys::VerificationApprovalReactions)?;
+    let message = channel_id.send_message(|m|
+        m.content(format_args!("{} ❔ {} wants to {} {} \
+                                (https://www.roblox.com/users/{}/profile).\n\
+                                Use `{}approve {}` or `{}deny {} [reason]`{} within {}.",
+                               util::discord_timestamp(SystemTime::now()),
+                               format_discord_id(discord_id),
+                               if pending.is_link { "link" } else { "verify as" },
+                               format_roblox_id(pending.roblox_id), pending.roblox_id.0,
+                               prefix, discord_id.0, prefix, discord_id.0,
+                               if use_reactions { ", or react to this message," } else { "" },
+                               util::english_time_diff(SystemTime::now(), pending.expires)))
+    )?;
+    if use_reactions {
+        message.react(APPROVE_EMOJI)?;
+        message.react(DENY_EMOJI)?;
+    }
+    core.verifier().set_review_message(guild_id, discord_id, channel_id, message.id)
+}
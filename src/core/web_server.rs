@@ -0,0 +1,132 @@
+use core::{CoreRef, VerifierCore};
+use core::config::*;
+use core::oauth::OAUTH_CALLBACK_PATH;
+use core::verify_result::complete_verification;
+use error_report;
+use errors::*;
+use hyper::header::ContentType;
+use hyper::method::Method;
+use hyper::server::{Server, Handler, Listening, Request, Response};
+use hyper::uri::RequestUri;
+use parking_lot::Mutex;
+use reqwest::Url;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::Arc;
+
+pub const PUSH_VERIFY_PATH: &str = "/verify/push";
+const PUSH_SIGNATURE_HEADER: &str = "X-Sylph-Signature";
+const MAX_BODY_SIZE: u64 = 4096;
+
+/// Returns the public URL of a path on the web server, if the web server is configured.
+crate fn public_url(config: &ConfigManager, path: &str) -> Result<Option<String>> {
+    if config.get(None, ConfigKeys::WebServerAddress)?.is_none() {
+        return Ok(None)
+    }
+    Ok(config.get(None, ConfigKeys::WebServerPublicUrl)?
+        .map(|url| format!("{}{}", url.trim_end_matches('/'), path)))
+}
+
+fn oauth_callback(core: &VerifierCore, query: &HashMap<String, String>) -> Result<String> {
+    if let Some(error) = query.get("error") {
+        cmd_error!("Logging in to Roblox failed: {}", error);
+    }
+    let state = query.get("state").to_cmd_err(|| "This login link is invalid.")?;
+    let code = query.get("code").to_cmd_err(|| "This login link is invalid.")?;
+    let (guild_id, discord_id, roblox_id) = core.oauth().finish_login(state, code)?;
+    let message = complete_verification(
+        core, guild_id, discord_id, roblox_id, " by logging in to Roblox",
+        || core.verifier().try_verify_oauth(guild_id, discord_id, roblox_id),
+    )?;
+    Ok(format!("{}\n\nYou may now close this page.", message))
+}
+
+fn push_verify(core: &VerifierCore, req: &mut Request) -> Result<String> {
+    let signature = match req.headers.get_raw(PUSH_SIGNATURE_HEADER) {
+        Some(values) if values.len() == 1 => String::from_utf8(values[0].clone())
+            .to_cmd_err(|| "Malformed request signature.")?,
+        _ => cmd_error!("Missing request signature."),
+    };
+    let mut body = Vec::new();
+    req.by_ref().take(MAX_BODY_SIZE + 1).read_to_end(&mut body)?;
+    cmd_ensure!(body.len() as u64 <= MAX_BODY_SIZE, "Request body is too large.");
+
+    let push = core.verifier().try_verify_push(&body, &signature)?;
+    complete_verification(core, push.guild_id, push.discord_id, push.roblox_id,
+                          " using a pairing code", || Ok(push.result))
+}
+
+fn route(core: &VerifierCore, req: &mut Request, path: &str) -> Result<(StatusCode, String)> {
+    let url = match Url::parse(&format!("http://localhost{}", path)) {
+        Ok(url) => url,
+        Err(_) => return Ok((StatusCode::BadRequest, "Bad request.".to_owned())),
+    };
+    let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    match url.path() {
+        OAUTH_CALLBACK_PATH => Ok((StatusCode::Ok, oauth_callback(core, &query)?)),
+        PUSH_VERIFY_PATH if req.method == Method::Post =>
+            Ok((StatusCode::Ok, push_verify(core, req)?)),
+        _ => Ok((StatusCode::NotFound, "Not found.".to_owned())),
+    }
+}
+
+struct RequestHandler(CoreRef);
+impl Handler for RequestHandler {
+    fn handle(&self, mut req: Request, mut res: Response) {
+        let uri = req.uri.clone();
+        let (status, body) = match (self.0.get_core(), &uri) {
+            (Some(core), RequestUri::AbsolutePath(path)) => {
+                debug!("Web request from {}: {} {}", req.remote_addr, req.method, path);
+                let result = error_report::catch_error(|| match route(&core, &mut req, path) {
+                    Err(match_err!(ErrorKind::CommandError(err))) =>
+                        Ok((StatusCode::BadRequest, err.into_owned())),
+                    result => result,
+                });
+                result.unwrap_or_else(|_| (StatusCode::InternalServerError,
+                                           "An internal error occurred.".to_owned()))
+            }
+            (Some(_), _) => (StatusCode::BadRequest, "Bad request.".to_owned()),
+            (None, _) => (StatusCode::ServiceUnavailable,
+                          "The bot is currently shutting down.".to_owned()),
+        };
+        *res.status_mut() = status;
+        res.headers_mut().set(ContentType::plaintext());
+        if let Err(e) = res.send(body.as_bytes()) {
+            debug!("Could not send web response: {}", e);
+        }
+    }
+}
+
+struct WebServerData {
+    config: ConfigManager,
+    core_ref: CoreRef,
+    listening: Mutex<Option<Listening>>,
+}
+
+#[derive(Clone)]
+pub struct WebServer(Arc<WebServerData>);
+impl WebServer {
+    pub(in ::core) fn new(config: ConfigManager, core_ref: CoreRef) -> WebServer {
+        WebServer(Arc::new(WebServerData {
+            config, core_ref, listening: Mutex::new(None),
+        }))
+    }
+
+    pub(in ::core) fn start(&self) -> Result<()> {
+        let mut listening = self.0.listening.lock();
+        ensure!(listening.is_none(), "Web server already started.");
+        if let Some(address) = self.0.config.get(None, ConfigKeys::WebServerAddress)? {
+            let server = Server::http(address.as_str())?
+                .handle(RequestHandler(self.0.core_ref.clone()))?;
+            info!("Web server listening on {}.", server.socket);
+            *listening = Some(server);
+        }
+        Ok(())
+    }
+    pub(in ::core) fn shutdown(&self) -> Result<()> {
+        if let Some(mut server) = self.0.listening.lock().take() {
+            server.close()?;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,8 @@
 use super::*;
 
+use serde_json;
 use serenity::model::prelude::*;
+use std::collections::BTreeMap;
 use std::time::SystemTime;
 use roblox::*;
 use util;
@@ -28,6 +30,13 @@ impl ToSqlArgs for () {
         f(&[])
     }
 }
+/// Allows queries built at runtime to bind a variable number of arguments.
+impl ToSqlArgs for Vec<Value> {
+    fn to_sql_args<R>(&self, f: impl FnOnce(&[&dyn RusqliteToSql]) -> Result<R>) -> Result<R> {
+        let args: Vec<&dyn RusqliteToSql> = self.iter().map(|x| x as &dyn RusqliteToSql).collect();
+        f(&args)
+    }
+}
 
 macro_rules! from_rusqlite {
     ($($ty:ty),* $(,)*) => {
@@ -103,6 +112,16 @@ impl FromSql for Value {
         Ok(value.into())
     }
 }
+impl FromSql for BTreeMap<String, String> {
+    fn from_sql(value: ValueRef) -> Result<Self> {
+        Ok(serde_json::from_str(&String::from_sql(value)?)?)
+    }
+}
+impl FromSql for serde_json::Value {
+    fn from_sql(value: ValueRef) -> Result<Self> {
+        Ok(serde_json::from_str(&String::from_sql(value)?)?)
+    }
+}
 
 macro_rules! to_rusqlite {
     ($($ty:ty),* $(,)*) => {
@@ -183,6 +202,16 @@ impl ToSql for Value {
         Ok(self.into())
     }
 }
+impl ToSql for BTreeMap<String, String> {
+    fn to_sql(&self) -> Result<ToSqlOutput> {
+        Ok(serde_json::to_string(self)?.into())
+    }
+}
+impl ToSql for serde_json::Value {
+    fn to_sql(&self) -> Result<ToSqlOutput> {
+        Ok(serde_json::to_string(self)?.into())
+    }
+}
 
 macro_rules! tuple_impls {
     (@one $i:ident) => { 1 };
@@ -0,0 +1,101 @@
+use errors::*;
+use openssl::symm::{Cipher, encrypt_aead, decrypt_aead};
+use rand::{RngCore, rngs::OsRng};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use util;
+
+pub const MASTER_KEY_ENV: &str = "SYLPH_VERIFIER_MASTER_KEY";
+pub const MASTER_KEY_FILE_ENV: &str = "SYLPH_VERIFIER_MASTER_KEY_FILE";
+const MASTER_KEY_FILE_NAME: &str = "Sylph-Verifier.key";
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// A key used to encrypt the verification keys stored in the database.
+pub struct MasterKey([u8; KEY_LEN]);
+impl MasterKey {
+    crate fn generate() -> MasterKey {
+        let mut key = [0u8; KEY_LEN];
+        OsRng.fill_bytes(&mut key);
+        MasterKey(key)
+    }
+    fn from_hex(hex: &str) -> Result<MasterKey> {
+        let data = util::from_hex(hex.trim());
+        match data {
+            Some(ref data) if data.len() == KEY_LEN => {
+                let mut key = [0u8; KEY_LEN];
+                key.copy_from_slice(data);
+                Ok(MasterKey(key))
+            }
+            _ => bail!("The master key must be {} hexadecimal digits.", KEY_LEN * 2),
+        }
+    }
+
+    /// Returns the path new master keys are written to.
+    crate fn key_file_path(root_path: &Path) -> PathBuf {
+        match env::var_os(MASTER_KEY_FILE_ENV) {
+            Some(path) => PathBuf::from(path),
+            None => root_path.join(MASTER_KEY_FILE_NAME),
+        }
+    }
+    /// Returns the path a new master key is written to while the database is re-encrypted.
+    crate fn pending_key_file_path(root_path: &Path) -> PathBuf {
+        MasterKey::key_file_path(root_path).with_extension("key.new")
+    }
+    /// Returns whether the master key is set by the environment rather than a key file.
+    crate fn is_from_env() -> bool {
+        env::var_os(MASTER_KEY_ENV).is_some()
+    }
+    crate fn read_from(path: &Path) -> Result<MasterKey> {
+        debug!("Loading master key from '{}'.", path.display());
+        let mut hex = String::new();
+        File::open(path)?.read_to_string(&mut hex)?;
+        MasterKey::from_hex(&hex)
+    }
+    crate fn load(root_path: &Path) -> Result<Option<MasterKey>> {
+        if let Ok(hex) = env::var(MASTER_KEY_ENV) {
+            debug!("Loading master key from the {} environment variable.", MASTER_KEY_ENV);
+            return Ok(Some(MasterKey::from_hex(&hex)?))
+        }
+        let path = MasterKey::key_file_path(root_path);
+        if path.exists() {
+            return Ok(Some(MasterKey::read_from(&path)?))
+        }
+        Ok(None)
+    }
+    crate fn write_to(&self, path: &Path) -> Result<()> {
+        let mut options = OpenOptions::new();
+        options.create(true).write(true).truncate(true);
+        #[cfg(unix)] {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut handle = options.open(path)?;
+        handle.write_all(util::to_hex(&self.0).as_bytes())?;
+        handle.sync_all()?;
+        Ok(())
+    }
+
+    /// Encrypts data, returning the ciphertext and the nonce used.
+    crate fn encrypt(&self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut nonce = vec![0u8; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let mut tag = [0u8; TAG_LEN];
+        let mut encrypted = encrypt_aead(Cipher::aes_256_gcm(), &self.0, Some(&nonce), &[],
+                                         data, &mut tag)?;
+        encrypted.extend_from_slice(&tag);
+        Ok((encrypted, nonce))
+    }
+    crate fn decrypt(&self, data: &[u8], nonce: &[u8]) -> Result<Vec<u8>> {
+        ensure!(data.len() >= TAG_LEN && nonce.len() == NONCE_LEN, "Malformed encrypted data.");
+        let (data, tag) = data.split_at(data.len() - TAG_LEN);
+        match decrypt_aead(Cipher::aes_256_gcm(), &self.0, Some(nonce), &[], data, tag) {
+            Ok(data) => Ok(data),
+            Err(_) => bail!("Could not decrypt data. Is the correct master key configured?"),
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use super::*;
+
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant, SystemTime};
+use util;
+
+enum MigrationSource {
+    /// A SQL script. These are not run in a transaction, so they may use `PRAGMA foreign_keys`,
+    /// and must handle their own transactions.
+    Sql(&'static str),
+    /// A function that migrates the database, and its source code. These are run in a
+    /// transaction, and are given the master key if one is configured.
+    Rust(fn(&DatabaseConnection, Option<&MasterKey>) -> Result<()>, &'static str),
+}
+
+struct Migration {
+    from: u32, to: u32, id: &'static str, description: &'static str, source: MigrationSource,
+}
+impl Migration {
+    /// The checksum of a migration covers its source, as written in the SQL file or as
+    /// captured by `rust_migration!`.
+    fn checksum(&self) -> String {
+        let source = match self.source {
+            MigrationSource::Sql(source) | MigrationSource::Rust(_, source) => source,
+        };
+        util::to_hex(Sha256::digest(source.as_bytes()).as_slice())
+    }
+}
+
+macro_rules! migration {
+    ($from:expr, $to:expr, $id:expr, $description:expr) => {
+        Migration {
+            from: $from, to: $to, id: $id, description: $description,
+            source: MigrationSource::Sql(include_str!(concat!($id, ".sql"))),
+        }
+    };
+    ($from:expr, $to:expr, $id:expr, $description:expr, $func:ident) => {
+        Migration {
+            from: $from, to: $to, id: $id, description: $description,
+            source: MigrationSource::Rust($func, $func::SOURCE),
+        }
+    };
+}
+/// Defines a function for a Rust migration, along with a module of the same name holding its
+/// source code, so that changes to the function change the migration's checksum.
+macro_rules! rust_migration {
+    ($(#[$meta:meta])* fn $name:ident($($args:tt)*) -> Result<()> $body:block) => {
+        $(#[$meta])* fn $name($($args)*) -> Result<()> $body
+        mod $name {
+            pub const SOURCE: &str = stringify!(fn $name($($args)*) -> Result<()> $body);
+        }
+    };
+}
+static MIGRATIONS: &'static [Migration] = &[
+    migration!(0, 2, "version_0_to_2", "Creates the initial database schema."),
+    migration!(2, 3, "version_2_to_3", "Adds verification history."),
+    migration!(3, 4, "version_3_to_4", "Stores the token format used by each key."),
+    migration!(4, 5, "version_4_to_5", "Adds scheduled key rotation."),
+    migration!(5, 6, "version_5_to_6", "Allows keys to be encrypted with a master key."),
+    migration!(6, 7, "version_6_to_7", "Encrypts token keys with the master key.",
+               encrypt_token_keys),
+    migration!(7, 8, "version_7_to_8", "Records every verified Roblox account."),
+    migration!(8, 9, "version_8_to_9", "Allows multiple Roblox accounts per Discord user."),
+    migration!(9, 10, "version_9_to_10", "Adds ban lists."),
+    migration!(10, 11, "version_10_to_11", "Adds alt account flags."),
+    migration!(11, 12, "version_11_to_12", "Adds verification approval and history notes."),
+    migration!(12, 13, "version_12_to_13", "Adds verification expiry notices."),
+    migration!(13, 14, "version_13_to_14", "Adds the audit log."),
+    migration!(14, 15, "version_14_to_15", "Adds data retention tracking."),
+    migration!(15, 16, "version_15_to_16", "Marks imported verifications."),
+    migration!(16, 17, "version_16_to_17", "Records previously applied migrations.",
+               create_migration_table),
+];
+pub(in ::database) const CURRENT_VERSION: u32 = 17;
+const FUTURE_VERSION_ERR: &str = "This database was created for a future version of this bot. \
+                                  Please restore an older version of the database from a backup.";
+
+/// The version that added `sylph_verifier_migrations`. Migrations to earlier versions are
+/// recorded by the migration that creates the table.
+const MIGRATION_TABLE_VERSION: u32 = 17;
+
+rust_migration! {
+    fn create_migration_table(conn: &DatabaseConnection, _: Option<&MasterKey>) -> Result<()> {
+        conn.execute_batch(
+            "CREATE TABLE sylph_verifier_migrations (\
+                 id TEXT PRIMARY KEY, version INTEGER NOT NULL, checksum TEXT NOT NULL, \
+                 applied TIMESTAMP NOT NULL\
+             ) WITHOUT ROWID;"
+        )?;
+        for migration in MIGRATIONS.iter().filter(|x| x.to < MIGRATION_TABLE_VERSION) {
+            record_migration(conn, migration)?;
+        }
+        Ok(())
+    }
+}
+rust_migration! {
+    /// Encrypts token keys stored before a master key was configured. If there is no master
+    /// key, they are left unencrypted until one is generated with `rotate_master_key`.
+    fn encrypt_token_keys(
+        conn: &DatabaseConnection, master_key: Option<&MasterKey>,
+    ) -> Result<()> {
+        let master_key = match master_key {
+            Some(master_key) => master_key,
+            None => return Ok(()),
+        };
+        let keys = conn.query(
+            "SELECT id, key FROM verification_keys WHERE key_nonce IS NULL", (),
+        ).get_all::<(u64, Vec<u8>)>()?;
+        if !keys.is_empty() {
+            info!("Encrypting {} token key(s) with the master key...", keys.len());
+        }
+        for (id, key) in keys {
+            let (key, nonce) = master_key.encrypt(&key)?;
+            conn.execute("UPDATE verification_keys SET key = ?1, key_nonce = ?2 WHERE id = ?3",
+                         (key, nonce, id))?;
+        }
+        Ok(())
+    }
+}
+fn record_migration(conn: &DatabaseConnection, migration: &Migration) -> Result<()> {
+    conn.execute(
+        "INSERT INTO sylph_verifier_migrations (id, version, checksum, applied) \
+         VALUES (?1, ?2, ?3, ?4)",
+        (migration.id, migration.to, migration.checksum(), SystemTime::now()),
+    )?;
+    Ok(())
+}
+
+/// The migrations that were, or in a dry run would be, applied to a database.
+#[derive(Clone, Debug, Default)]
+pub struct MigrationReport {
+    pub from_version: u32, pub to_version: u32,
+    pub applied: Vec<(&'static str, &'static str, Duration)>,
+    /// Migrations that were applied by an earlier version of the bot, but have since changed.
+    pub changed: Vec<&'static str>,
+    /// Tables, columns and indexes that a newly created database has, but the migrated
+    /// database is missing.
+    pub schema_problems: Vec<String>,
+    /// Tables, columns and indexes in the migrated database that a newly created database does
+    /// not have, such as ones added by hand.
+    pub unexpected_schema: Vec<String>,
+    /// A copy of the database from before it was migrated.
+    pub backup: Option<PathBuf>,
+}
+
+fn get_version(conn: &DatabaseConnection) -> Result<u32> {
+    conn.query("SELECT value FROM sylph_verifier_meta WHERE key = 'schema_version';", ())
+        .get::<u32>()
+}
+pub(in ::database) fn check_version(conn: &DatabaseConnection) -> Result<()> {
+    let version = get_version(conn)?;
+    ensure!(version == CURRENT_VERSION,
+            "The database is at schema version {}, but this version of the bot expects \
+             version {}. Start the bot once to migrate it.", version, CURRENT_VERSION);
+    Ok(())
+}
+
+fn init_meta(conn: &DatabaseConnection) -> Result<()> {
+    conn.transaction_exclusive(|| {
+        let meta_table_exists = conn.query(
+            "SELECT COUNT(*) FROM sqlite_master \
+             WHERE type='table' AND name='sylph_verifier_meta';", (),
+        ).get::<u32>()? != 0;
+        if !meta_table_exists {
+            debug!("Setting up Sylph-Verifier database metadata.");
+            conn.execute_batch(
+                "CREATE TABLE sylph_verifier_meta (\
+                     key TEXT PRIMARY KEY, value BLOB NOT NULL\
+                 ) WITHOUT ROWID;\
+                 INSERT INTO sylph_verifier_meta (key, value) VALUES ('meta_version', 1);\
+                 INSERT INTO sylph_verifier_meta (key, value) VALUES ('schema_version', 0);"
+            )?;
+        }
+        Ok(())
+    })?;
+
+    let meta_version = conn.query(
+        "SELECT value FROM sylph_verifier_meta WHERE key = 'meta_version';", ()
+    ).get::<u32>()?;
+    ensure!(meta_version == 1, FUTURE_VERSION_ERR);
+    Ok(())
+}
+
+fn find_changed(conn: &DatabaseConnection, current_version: u32) -> Result<Vec<&'static str>> {
+    if current_version < MIGRATION_TABLE_VERSION { return Ok(Vec::new()) }
+    let mut changed = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|x| x.to <= current_version) {
+        let checksum = conn.query(
+            "SELECT checksum FROM sylph_verifier_migrations WHERE id = ?1", migration.id,
+        ).get_opt::<String>()?;
+        if checksum.as_ref() != Some(&migration.checksum()) {
+            changed.push(migration.id);
+        }
+    }
+    Ok(changed)
+}
+
+/// Updates the schema version after a migration. This must run in the same transaction as a
+/// Rust migration.
+fn finish_migration(conn: &DatabaseConnection, migration: &Migration) -> Result<()> {
+    conn.execute(
+        "UPDATE sylph_verifier_meta SET value = ?1 WHERE key = \"schema_version\";",
+        migration.to,
+    )?;
+    if migration.to >= MIGRATION_TABLE_VERSION {
+        record_migration(conn, migration)?;
+    }
+    Ok(())
+}
+
+fn run_migrations(
+    conn: &DatabaseConnection, master_key: Option<&MasterKey>, backup: Option<&Path>,
+) -> Result<MigrationReport> {
+    init_meta(conn)?;
+
+    let from_version = get_version(conn)?;
+    ensure!(from_version <= CURRENT_VERSION, FUTURE_VERSION_ERR);
+    let mut report = MigrationReport {
+        from_version, to_version: CURRENT_VERSION,
+        changed: find_changed(conn, from_version)?,
+        ..MigrationReport::default()
+    };
+
+    let mut to_run = Vec::new();
+    let mut current_version = from_version;
+    for migration in MIGRATIONS {
+        if migration.from == current_version {
+            to_run.push(migration);
+            current_version = migration.to;
+        }
+    }
+    if current_version != CURRENT_VERSION {
+        bail!("No migration found from version {} -> {}. Maybe this database was created by \
+               a development version of the bot?", current_version, CURRENT_VERSION);
+    }
+
+    // New databases have nothing worth backing up.
+    if let (Some(backup), false) = (backup, to_run.is_empty() || from_version == 0) {
+        let mut path = backup.as_os_str().to_owned();
+        path.push(format!(".before-migration-{}", from_version));
+        let path = PathBuf::from(path);
+        info!("Backing up the database to '{}' before migrating it.", path.display());
+        if path.exists() {
+            fs::remove_file(&path)?;
+        }
+        conn.backup_to(&path)?;
+        report.backup = Some(path);
+    }
+
+    for migration in to_run {
+        debug!("Running migration '{}': {}", migration.id, migration.description);
+        let start = Instant::now();
+        match migration.source {
+            // We don't execute this in a transaction to allow the use of PRAGMA foreign_key
+            MigrationSource::Sql(source) => {
+                conn.execute_batch(source)?;
+                conn.transaction(|| finish_migration(conn, migration))?;
+            }
+            MigrationSource::Rust(func, _) => conn.transaction(|| {
+                func(conn, master_key)?;
+                finish_migration(conn, migration)
+            })?,
+        }
+        report.applied.push((migration.id, migration.description, start.elapsed()));
+    }
+
+    Ok(report)
+}
+
+/// Describes the tables, columns and indexes in a database, for comparing schemas.
+fn describe_schema(conn: &DatabaseConnection) -> Result<BTreeSet<String>> {
+    let mut schema = BTreeSet::new();
+    for (table, column, ty) in conn.query(
+        "SELECT m.name, p.name, p.type FROM sqlite_master m, pragma_table_info(m.name) p \
+         WHERE m.type = 'table' AND m.name NOT LIKE 'sqlite_%'", (),
+    ).get_all::<(String, String, String)>()? {
+        schema.insert(format!("table {}", table));
+        schema.insert(format!("column {}.{} {}", table, column, ty));
+    }
+    for (index, table) in conn.query(
+        "SELECT name, tbl_name FROM sqlite_master \
+         WHERE type = 'index' AND name NOT LIKE 'sqlite_%'", (),
+    ).get_all::<(String, String)>()? {
+        schema.insert(format!("index {} on {}", index, table));
+    }
+    Ok(schema)
+}
+
+/// Compares a database's schema against that of a new database created from scratch. Returns
+/// the missing and unexpected parts of its schema.
+fn verify_schema(conn: &DatabaseConnection) -> Result<(Vec<String>, Vec<String>)> {
+    let reference = Database::open_memory()?;
+    let reference_conn = reference.connect()?;
+    run_migrations(&reference_conn, None, None)?;
+    let expected = describe_schema(&reference_conn)?;
+    let actual = describe_schema(conn)?;
+
+    let missing = expected.difference(&actual).map(|x| format!("missing {}", x)).collect();
+    let unexpected = actual.difference(&expected).map(|x| format!("unexpected {}", x)).collect();
+    Ok((missing, unexpected))
+}
+
+/// Migrates a database to the current schema version, and checks that its schema is correct.
+/// If any migrations are run, the database is first copied to a file beside `backup`.
+pub(in ::database) fn migrate(
+    conn: &DatabaseConnection, master_key: Option<&MasterKey>, backup: Option<&Path>,
+) -> Result<MigrationReport> {
+    let mut report = run_migrations(conn, master_key, backup)?;
+    let (missing, unexpected) = verify_schema(conn)?;
+    report.schema_problems = missing;
+    report.unexpected_schema = unexpected;
+    Ok(report)
+}
@@ -1,10 +1,12 @@
 use errors::*;
 use r2d2::{Pool, ManageConnection, PooledConnection};
-use rusqlite::{Connection, OpenFlags, Rows, TransactionBehavior,
+use rusqlite::{Connection, OpenFlags, Rows, TransactionBehavior, NO_PARAMS,
                Row as RusqliteRow, Result as RusqliteResult, Error as RusqliteError};
+use rusqlite::backup::Backup;
 use rusqlite::types::{ToSql as RusqliteToSql, FromSql as RusqliteFromSql,
                       FromSqlResult, FromSqlError};
 use std::cell::Cell;
+use std::fs;
 use std::ops::{Deref, DerefMut};
 use std::path::{Path, PathBuf};
 use std::thread::panicking;
@@ -12,6 +14,16 @@ use std::time;
 use std::sync::Arc;
 
 mod impls;
+mod master_key;
+mod migrations;
+
+pub use self::master_key::{MasterKey, MASTER_KEY_ENV, MASTER_KEY_FILE_ENV};
+pub use self::migrations::MigrationReport;
+
+/// How many pages are copied at a time while backing up or restoring the database. Other
+/// connections may use the database between each step.
+const BACKUP_PAGES_PER_STEP: i32 = 256;
+const BACKUP_STEP_PAUSE: time::Duration = time::Duration::from_millis(10);
 
 pub use rusqlite::types::{ToSqlOutput, Value, ValueRef};
 
@@ -94,6 +106,15 @@ impl <'a, 'b, T: ToSqlArgs> QueryDSL<'a, 'b, T> {
     pub fn get<R: FromSqlRow>(&self) -> Result<R> {
         self.do_op(|mut x| x.get())
     }
+    /// Calls a function on each row as it is read, rather than collecting every row first.
+    pub fn for_each(&self, mut f: impl FnMut(Row) -> Result<()>) -> Result<()> {
+        self.do_op(|mut x| {
+            while let Some(r) = x.0.next()? {
+                f(Row(r))?;
+            }
+            Ok(())
+        })
+    }
 }
 
 pub struct Row<'a, 'b>(&'b RusqliteRow<'a>);
@@ -123,12 +144,12 @@ impl DerefMut for SqliteConnection {
 }
 
 struct ConnectionManager {
-    db_file: PathBuf,
+    db_file: PathBuf, read_only: bool,
 }
 impl ConnectionManager {
-    fn new(path: &Path) -> Result<ConnectionManager> {
+    fn new(path: &Path, read_only: bool) -> Result<ConnectionManager> {
         Ok(ConnectionManager {
-            db_file: path.to_owned(),
+            db_file: path.to_owned(), read_only,
         })
     }
 }
@@ -137,9 +158,11 @@ impl ManageConnection for ConnectionManager {
     type Error = RusqliteError;
 
     fn connect(&self) -> RusqliteResult<SqliteConnection> {
-        let conn = Connection::open_with_flags(&self.db_file,
-            OpenFlags::SQLITE_OPEN_READ_WRITE |
-            OpenFlags::SQLITE_OPEN_CREATE)?;
+        let conn = Connection::open_with_flags(&self.db_file, if self.read_only {
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+        } else {
+            OpenFlags::SQLITE_OPEN_READ_WRITE | OpenFlags::SQLITE_OPEN_CREATE
+        })?;
         conn.set_prepared_statement_cache_capacity(64);
         conn.execute_batch(include_str!("setup_connection.sql"))?;
         Ok(SqliteConnection { conn, is_poisoned: Cell::new(false) })
@@ -255,36 +278,64 @@ impl DatabaseConnection {
         self.conn.execute_batch("PRAGMA wal_checkpoint(RESTART)")?;
         Ok(())
     }
+
+    /// Copies the database into a new file with SQLite's online backup API. Writes from other
+    /// connections may continue while the copy is made. The copy does not use WAL mode, so it
+    /// is a single self-contained file.
+    pub fn backup_to(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut dest = Connection::open(path)?;
+        Backup::new(&self.conn, &mut dest)?
+            .run_to_completion(BACKUP_PAGES_PER_STEP, BACKUP_STEP_PAUSE, None)?;
+        dest.query_row("PRAGMA journal_mode = DELETE", NO_PARAMS, |_| Ok(()))?;
+        Ok(())
+    }
 }
 
-struct Migration {
-    from: u32, to: u32, name: &'static str, source: &'static str
+/// Runs SQLite's integrity check on a database file, such as a backup.
+pub fn check_integrity(path: impl AsRef<Path>) -> Result<()> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let result: String = conn.query_row("PRAGMA integrity_check", NO_PARAMS, |row| row.get(0))?;
+    ensure!(result == "ok", "Database integrity check failed: {}", result);
+    Ok(())
 }
-macro_rules! migration {
-    ($from:expr, $to:expr, $file:expr) => {
-        Migration { from: $from, to: $to, name: $file, source: include_str!($file) }
-    }
+
+/// Replaces the contents of a database file with a backup. This must not be used while the bot
+/// is running from that database.
+pub fn restore_backup(backup: impl AsRef<Path>, path: impl AsRef<Path>) -> Result<()> {
+    let source = Connection::open_with_flags(backup, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut dest = Connection::open(path)?;
+    Backup::new(&source, &mut dest)?
+        .run_to_completion(BACKUP_PAGES_PER_STEP, BACKUP_STEP_PAUSE, None)?;
+    dest.execute_batch("PRAGMA wal_checkpoint(TRUNCATE)")?;
+    Ok(())
 }
-static MIGRATIONS: &'static [Migration] = &[
-    migration!(0, 2, "version_0_to_2.sql"),
-    migration!(2, 3, "version_2_to_3.sql"),
-];
-const CURRENT_VERSION: u32 = 3;
-const FUTURE_VERSION_ERR: &str = "This database was created for a future version of this bot. \
-                                  Please restore an older version of the database from a backup.";
 
 #[derive(Clone)]
 pub struct Database {
     pool: Arc<Pool<ConnectionManager>>,
 }
 impl Database {
-    pub fn new(path: impl AsRef<Path>) -> Result<Database> {
+    /// Opens a database, migrating it to the current schema version. `master_key` is used by
+    /// migrations that encrypt data.
+    pub fn new(path: impl AsRef<Path>, master_key: Option<&MasterKey>) -> Result<Database> {
         let pool = Arc::new(Pool::builder()
             .max_size(40)
             .idle_timeout(Some(time::Duration::from_secs(60 * 5)))
-            .build(ConnectionManager::new(path.as_ref())?)?);
+            .build(ConnectionManager::new(path.as_ref(), false)?)?);
         let database = Database { pool };
-        database.init_db()?;
+        database.init_db(path.as_ref(), master_key)?;
+        Ok(database)
+    }
+    /// Opens a database read-only, such as while another process is running the bot. The
+    /// database must already be at the current schema version, as migrations cannot be run.
+    pub fn open_read_only(path: impl AsRef<Path>) -> Result<Database> {
+        ensure!(path.as_ref().exists(), "Database file '{}' does not exist.",
+                path.as_ref().display());
+        let pool = Arc::new(Pool::builder()
+            .max_size(4)
+            .build(ConnectionManager::new(path.as_ref(), true)?)?);
+        let database = Database { pool };
+        migrations::check_version(&database.connect()?)?;
         Ok(database)
     }
 
@@ -292,62 +343,61 @@ impl Database {
         Ok(DatabaseConnection::new(self.pool.get()?))
     }
 
-    fn init_db(&self) -> Result<()> {
-        let conn = self.connect()?;
-
-        conn.transaction_exclusive(|| {
-            let meta_table_exists = conn.query(
-                "SELECT COUNT(*) FROM sqlite_master \
-                 WHERE type='table' AND name='sylph_verifier_meta';", (),
-            ).get::<u32>()? != 0;
-            if !meta_table_exists {
-                debug!("Setting up Sylph-Verifier database metadata.");
-                conn.execute_batch(
-                    "CREATE TABLE sylph_verifier_meta (\
-                         key TEXT PRIMARY KEY, value BLOB NOT NULL\
-                     ) WITHOUT ROWID;\
-                     INSERT INTO sylph_verifier_meta (key, value) VALUES ('meta_version', 1);\
-                     INSERT INTO sylph_verifier_meta (key, value) VALUES ('schema_version', 0);"
-                )?;
-            }
-
-            Ok(())
-        })?;
-
-        let meta_version = conn.query(
-            "SELECT value FROM sylph_verifier_meta WHERE key = 'meta_version';", ()
-        ).get::<u32>()?;
-        ensure!(meta_version == 1, FUTURE_VERSION_ERR);
-
-        let mut to_run = Vec::new();
-        let mut current_version = conn.query(
-            "SELECT value FROM sylph_verifier_meta WHERE key = 'schema_version';", ()
-        ).get::<u32>()?;
-        ensure!(current_version <= CURRENT_VERSION, FUTURE_VERSION_ERR);
+    /// Opens a temporary in-memory database with no schema.
+    fn open_memory() -> Result<Database> {
+        let pool = Arc::new(Pool::builder()
+            .max_size(1)
+            .build(ConnectionManager::new(Path::new(":memory:"), false)?)?);
+        Ok(Database { pool })
+    }
 
-        // TODO: Backup old database.
-        for migration in MIGRATIONS {
-            if migration.from == current_version {
-                to_run.push(migration);
-                current_version = migration.to;
-            }
+    fn init_db(&self, path: &Path, master_key: Option<&MasterKey>) -> Result<()> {
+        let conn = self.connect()?;
+        let report = migrations::migrate(&conn, master_key, Some(path))?;
+        for id in &report.changed {
+            warn!("Migration '{}' has changed since it was applied to this database.", id);
         }
-        if current_version != CURRENT_VERSION {
-            bail!("No migration found from version {} -> {}. Maybe this database was created by \
-                   a development version of the bot?", current_version, CURRENT_VERSION);
+        for item in &report.unexpected_schema {
+            warn!("The database schema has an {}, which this version does not use.", item);
         }
-        for migration in to_run {
-            debug!("Running migration '{}'", migration.name);
-
-            // We don't execute this in a transaction to allow the use of PRAGMA foreign_key
-            conn.execute_batch(migration.source)?;
-            conn.execute(
-                "UPDATE sylph_verifier_meta SET value = ?1 WHERE key = \"schema_version\";",
-                migration.to,
-            )?;
+        if !report.schema_problems.is_empty() {
+            for problem in &report.schema_problems {
+                error!("Schema problem: {}", problem);
+            }
+            bail!("The database schema is missing items that were expected after migrating. \
+                   Restore the database from a backup, or run the bot with \
+                   `--migrate-dry-run` for details.");
         }
-
         conn.checkpoint()?;
         Ok(())
     }
+
+    /// Runs any pending migrations on a copy of the database at `path`, and reports what was
+    /// done. The database itself is left unchanged.
+    pub fn migrate_dry_run(
+        path: impl AsRef<Path>, master_key: Option<&MasterKey>,
+    ) -> Result<MigrationReport> {
+        let path = path.as_ref();
+        ensure!(path.exists(), "Database file '{}' does not exist.", path.display());
+
+        let mut copy = path.as_os_str().to_owned();
+        copy.push(".dry-run.tmp");
+        let copy = PathBuf::from(copy);
+        restore_backup(path, &copy)?;
+        let result = (|| {
+            let pool = Arc::new(Pool::builder()
+                .max_size(1)
+                .build(ConnectionManager::new(&copy, false)?)?);
+            migrations::migrate(&Database { pool }.connect()?, master_key, None)
+        })();
+        for suffix in &["", "-wal", "-shm"] {
+            let mut file = copy.as_os_str().to_owned();
+            file.push(suffix);
+            let file = PathBuf::from(file);
+            if file.exists() {
+                fs::remove_file(file)?;
+            }
+        }
+        result
+    }
 }
@@ -0,0 +1,9 @@
+BEGIN EXCLUSIVE;
+  -- Stores verifications flagged as possible alt accounts that have not been reviewed yet.
+  CREATE TABLE alt_flags (
+    discord_guild_id BIGINT NOT NULL, discord_user_id BIGINT NOT NULL,
+    roblox_user_id BIGINT NOT NULL, reasons TEXT NOT NULL, holds_roles BOOL NOT NULL,
+    created TIMESTAMP NOT NULL,
+    PRIMARY KEY (discord_guild_id, discord_user_id)
+  ) WITHOUT ROWID;
+COMMIT;
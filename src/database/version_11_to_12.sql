@@ -0,0 +1,23 @@
+BEGIN EXCLUSIVE;
+  -- Records who made changes to a user's verification on their behalf, and why.
+  ALTER TABLE user_history ADD COLUMN actor_discord_id BIGINT;
+  ALTER TABLE user_history ADD COLUMN note TEXT;
+
+  -- Stores verifications waiting for a moderator of a server to approve them.
+  CREATE TABLE pending_verifications (
+    discord_guild_id BIGINT NOT NULL, discord_user_id BIGINT NOT NULL,
+    roblox_user_id BIGINT NOT NULL, is_link BOOL NOT NULL,
+    created TIMESTAMP NOT NULL, expires TIMESTAMP NOT NULL,
+    review_channel_id BIGINT, review_message_id BIGINT,
+    PRIMARY KEY (discord_guild_id, discord_user_id)
+  ) WITHOUT ROWID;
+  CREATE INDEX pending_verifications_message_idx ON pending_verifications (review_message_id);
+
+  -- Records which Roblox accounts a moderator has approved for a user in servers that require
+  -- approval. Roles are only assigned in those servers for approved accounts.
+  CREATE TABLE approved_verifications (
+    discord_guild_id BIGINT, discord_user_id BIGINT, roblox_user_id BIGINT,
+    approved TIMESTAMP NOT NULL, actor_discord_id BIGINT,
+    PRIMARY KEY (discord_guild_id, discord_user_id, roblox_user_id)
+  ) WITHOUT ROWID;
+COMMIT;
@@ -0,0 +1,10 @@
+BEGIN EXCLUSIVE;
+  -- Tracks which expiry warnings and notices have been sent for a verification, so each is
+  -- only sent once. Notices for older verifications are ignored once a user reverifies.
+  CREATE TABLE verification_expiry_notices (
+    discord_guild_id BIGINT NOT NULL, discord_user_id BIGINT NOT NULL,
+    verified_at TIMESTAMP NOT NULL, is_expired BOOL NOT NULL,
+    PRIMARY KEY (discord_guild_id, discord_user_id)
+  ) WITHOUT ROWID;
+  CREATE INDEX discord_user_info_last_updated_idx ON discord_user_info (last_updated);
+COMMIT;
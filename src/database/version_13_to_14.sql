@@ -0,0 +1,15 @@
+BEGIN EXCLUSIVE;
+  -- Stores a structured log of verification, role and configuration events. `payload` contains
+  -- event specific details as a JSON object.
+  CREATE TABLE audit_events (
+    id INTEGER PRIMARY KEY AUTOINCREMENT, event_type TEXT NOT NULL,
+    discord_guild_id BIGINT, actor_discord_id BIGINT,
+    discord_user_id BIGINT, roblox_user_id BIGINT,
+    created TIMESTAMP NOT NULL, payload TEXT NOT NULL
+  );
+  CREATE INDEX audit_events_guild_idx ON audit_events (discord_guild_id, created);
+  CREATE INDEX audit_events_discord_idx ON audit_events (discord_user_id, created);
+  CREATE INDEX audit_events_roblox_idx ON audit_events (roblox_user_id, created);
+  CREATE INDEX audit_events_actor_idx ON audit_events (actor_discord_id, created);
+  CREATE INDEX audit_events_created_idx ON audit_events (created);
+COMMIT;
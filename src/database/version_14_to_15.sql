@@ -0,0 +1,12 @@
+BEGIN EXCLUSIVE;
+  -- Records when the bot was removed from a server, so its data can be deleted once the grace
+  -- period ends. Rows are removed if the bot is added back before then.
+  CREATE TABLE departed_guilds (
+    discord_guild_id BIGINT PRIMARY KEY, departed TIMESTAMP NOT NULL
+  ) WITHOUT ROWID;
+
+  -- Indexes used to find rows older than their retention period.
+  CREATE INDEX user_history_last_updated_idx ON user_history (last_updated);
+  CREATE INDEX verification_cooldown_last_attempt_idx ON verification_cooldown (last_attempt);
+  CREATE INDEX roles_last_updated_last_updated_idx ON roles_last_updated (last_updated);
+COMMIT;
@@ -0,0 +1,5 @@
+BEGIN EXCLUSIVE;
+  -- Marks primary accounts that were imported from another bot, and have not been verified
+  -- by the user since.
+  ALTER TABLE discord_user_info ADD COLUMN is_imported BOOL NOT NULL DEFAULT 0;
+COMMIT;
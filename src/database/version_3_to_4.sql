@@ -0,0 +1,13 @@
+BEGIN EXCLUSIVE;
+  -- Stores the token format used by each key. Existing keys all use the original format.
+  ALTER TABLE verification_keys ADD COLUMN token_length INT NOT NULL DEFAULT 6;
+  ALTER TABLE verification_keys ADD COLUMN token_chars TEXT NOT NULL
+    DEFAULT 'ABCDEFGHIJKLMNOPQRSTUVWXYZ';
+
+  -- New installs use longer tokens without easily confused letters. Existing installs keep the
+  -- original token format, as changing it would require republishing the verification place.
+  INSERT OR IGNORE INTO global_config (key, value)
+  SELECT 'TokenLength', 6 WHERE EXISTS (SELECT * FROM verification_keys);
+  INSERT OR IGNORE INTO global_config (key, value)
+  SELECT 'TokenExcludeConfusable', 0 WHERE EXISTS (SELECT * FROM verification_keys);
+COMMIT;
@@ -0,0 +1,7 @@
+BEGIN EXCLUSIVE;
+  -- Stores when each key becomes the current key. Keys with a time in the future are staged
+  -- in the place file ahead of time, and existing keys are treated as always active.
+  ALTER TABLE verification_keys ADD COLUMN activates_at TIMESTAMP NOT NULL DEFAULT 0;
+  -- Whether the key replaced the previous one on a schedule, allowing a grace period.
+  ALTER TABLE verification_keys ADD COLUMN is_scheduled BOOL NOT NULL DEFAULT 0;
+COMMIT;
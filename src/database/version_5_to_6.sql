@@ -0,0 +1,6 @@
+BEGIN EXCLUSIVE;
+  -- Stores the nonce used to encrypt each key with the master key, or NULL if the key is
+  -- stored unencrypted. Existing keys are encrypted by the next migration if a master key is
+  -- configured.
+  ALTER TABLE verification_keys ADD COLUMN key_nonce BLOB;
+COMMIT;
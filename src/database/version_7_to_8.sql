@@ -0,0 +1,16 @@
+PRAGMA foreign_keys = false;
+BEGIN EXCLUSIVE;
+  -- Every verified Roblox account now has an entry in `roblox_user_info`. Accounts verified
+  -- without a token, such as through their profile, have no key recorded.
+  CREATE TABLE roblox_user_info_new (
+    roblox_user_id BIGINT PRIMARY KEY,
+    last_key_id INTEGER, last_key_epoch BIGINT,
+    last_updated TIMESTAMP NOT NULL,
+    FOREIGN KEY (last_key_id) REFERENCES verification_keys (id)
+  ) WITHOUT ROWID;
+  INSERT INTO roblox_user_info_new (roblox_user_id, last_key_id, last_key_epoch, last_updated)
+  SELECT roblox_user_id, last_key_id, last_key_epoch, last_updated FROM roblox_user_info;
+  DROP TABLE roblox_user_info;
+  ALTER TABLE roblox_user_info_new RENAME TO roblox_user_info;
+COMMIT;
+PRAGMA foreign_keys = true;
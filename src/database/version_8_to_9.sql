@@ -0,0 +1,24 @@
+BEGIN EXCLUSIVE;
+  -- Stores every Roblox account linked to a Discord user, including their primary account.
+  CREATE TABLE discord_linked_accounts (
+    roblox_user_id BIGINT PRIMARY KEY, discord_user_id BIGINT NOT NULL,
+    last_updated TIMESTAMP NOT NULL
+  ) WITHOUT ROWID;
+  CREATE INDEX discord_linked_accounts_discord_idx ON discord_linked_accounts (discord_user_id);
+
+  INSERT INTO discord_linked_accounts (roblox_user_id, discord_user_id, last_updated)
+  SELECT roblox_user_id, discord_user_id, last_updated FROM discord_user_info
+  WHERE roblox_user_id NOT NULL;
+
+  -- `roblox_user_id` is now the primary account of the Discord user. Uniqueness is enforced by
+  -- `discord_linked_accounts`, which also tracks which accounts exist, so both constraints are
+  -- dropped.
+  CREATE TABLE discord_user_info_new (
+    discord_user_id BIGINT PRIMARY KEY, roblox_user_id BIGINT,
+    last_updated TIMESTAMP NOT NULL
+  ) WITHOUT ROWID;
+  INSERT INTO discord_user_info_new (discord_user_id, roblox_user_id, last_updated)
+  SELECT discord_user_id, roblox_user_id, last_updated FROM discord_user_info;
+  DROP TABLE discord_user_info;
+  ALTER TABLE discord_user_info_new RENAME TO discord_user_info;
+COMMIT;
@@ -0,0 +1,11 @@
+BEGIN EXCLUSIVE;
+  -- Stores accounts that are banned from verifying. A `discord_guild_id` of 0 is used for
+  -- entries in the global ban list.
+  CREATE TABLE ban_list (
+    discord_guild_id BIGINT NOT NULL, is_roblox BOOL NOT NULL, target_id BIGINT NOT NULL,
+    reason TEXT NOT NULL, author_discord_id BIGINT, created TIMESTAMP NOT NULL,
+    expires TIMESTAMP,
+    PRIMARY KEY (discord_guild_id, is_roblox, target_id)
+  ) WITHOUT ROWID;
+  CREATE INDEX ban_list_target_idx ON ban_list (is_roblox, target_id);
+COMMIT;
@@ -99,6 +99,7 @@ mod impls {
         std::fmt::Error, std::io::Error, std::num::ParseIntError, std::str::Utf8Error,
         std::string::FromUtf8Error, std::time::SystemTimeError, r2d2::Error, reqwest::Error,
         rusqlite::Error, rusqlite::types::FromSqlError, serde_json::Error, rand::Error,
+        openssl::error::ErrorStack, hyper::Error,
     }
 }
 
@@ -16,6 +16,7 @@ extern crate hmac;
 extern crate hyper;
 extern crate linefeed;
 extern crate num_cpus;
+extern crate openssl;
 extern crate parking_lot;
 extern crate percent_encoding;
 extern crate r2d2;
@@ -23,7 +24,6 @@ extern crate rand;
 extern crate regex;
 extern crate reqwest;
 extern crate rusqlite;
-extern crate serde_json;
 extern crate serenity;
 extern crate sha2;
 extern crate threadpool;
@@ -36,6 +36,7 @@ extern crate uuid;
 #[macro_use] extern crate lazy_static;
 #[macro_use] extern crate log;
 #[macro_use] extern crate serde_derive;
+#[macro_use] extern crate serde_json;
 extern crate scraper;
 
 #[macro_use] pub mod errors;
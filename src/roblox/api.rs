@@ -14,6 +14,11 @@ struct RobloxIDLookup {
     #[serde(rename = "Username")] name: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct RobloxUserLookup {
+    description: Option<String>,
+}
+
 #[derive(Deserialize)]
 struct RobloxDevForumUserLookup {
     trust_level: u32,
@@ -57,14 +62,16 @@ crate struct WebProfileInfo {
     crate has_premium: bool,
 }
 
-fn get_api_endpoint(uri: &str) -> Result<blocking::Response> {
+crate fn api_client() -> Result<blocking::Client> {
     let mut headers = header::HeaderMap::new();
     let agent = concat!(
         "SylphVerifierBot/", env!("CARGO_PKG_VERSION"), " (+https://github.com/SylphieBot/sylph-verifier)"
     );
     headers.insert(header::USER_AGENT, header::HeaderValue::from_static(agent));
-    let client = blocking::Client::builder().default_headers(headers).build()?;
-    Ok(client.get(uri).send()?)
+    Ok(blocking::Client::builder().default_headers(headers).build()?)
+}
+fn get_api_endpoint(uri: &str) -> Result<blocking::Response> {
+    Ok(api_client()?.get(uri).send()?)
 }
 
 crate fn get_web_profile(id: RobloxUserID) -> Result<WebProfileInfo> {
@@ -116,6 +123,16 @@ crate fn lookup_username(id: RobloxUserID) -> Result<Option<String>> {
     Ok(info.name)
 }
 
+crate fn get_profile_description(id: RobloxUserID) -> Result<Option<String>> {
+    let uri = format!("https://users.roblox.com/v1/users/{}", id.0);
+    let response = get_api_endpoint(&uri)?;
+    if response.status() == StatusCode::NOT_FOUND {
+        return Ok(None)
+    }
+    let json = response.error_for_status()?.text()?;
+    Ok(serde_json::from_str::<RobloxUserLookup>(&json)?.description)
+}
+
 crate fn get_dev_trust_level(name: &str) -> Result<Option<u32>> {
     let uri = format!("https://devforum.roblox.com/users/{}.json",
                       percent_encode(name.as_bytes(), QUERY_ENCODE_SET));
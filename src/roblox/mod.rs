@@ -1,11 +1,13 @@
 use errors::*;
 
 mod api;
-mod lz4;
+crate mod lz4;
+mod oauth;
 mod place;
 mod rules;
 
-pub use self::place::{create_place_file, LuaConfigEntry, LuaConfigValue};
+pub use self::oauth::OAuthEndpoints;
+pub use self::place::{create_place_file, LuaConfigEntry, LuaConfigValue, TEMPLATE_VERSION};
 pub use self::rules::{VerificationRule, VerificationSet, RuleResult};
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
@@ -25,4 +27,8 @@ impl RobloxUserID {
     pub fn lookup_username(&self) -> ::errors::Result<String> {
         Ok(self.lookup_username_opt()?.ok_or_else(Error::none)?)
     }
+
+    pub fn profile_description(&self) -> ::errors::Result<Option<String>> {
+        api::get_profile_description(*self)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,136 @@
+use errors::*;
+use openssl::base64;
+use reqwest::Url;
+use roblox::*;
+use roblox::api::api_client;
+use serde_json;
+use sha2::{Digest, Sha256};
+
+#[derive(Deserialize)]
+struct DiscoveryDocument {
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    id_token: String,
+}
+
+#[derive(Deserialize)]
+struct IdTokenClaims {
+    sub: String,
+    nonce: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct UserInfoResponse {
+    sub: String,
+}
+
+fn base64_url_encode(data: &[u8]) -> String {
+    base64::encode_block(data).trim_end_matches('=').replace('+', "-").replace('/', "_")
+}
+fn base64_url_decode(data: &str) -> Result<Vec<u8>> {
+    let mut data = data.replace('-', "+").replace('_', "/");
+    while data.len() % 4 != 0 {
+        data.push('=');
+    }
+    Ok(base64::decode_block(&data)?)
+}
+
+/// Returns the PKCE code challenge for a code verifier, using the `S256` method.
+fn pkce_challenge(code_verifier: &str) -> String {
+    base64_url_encode(Sha256::digest(code_verifier.as_bytes()).as_slice())
+}
+
+/// Reads the claims of an ID token. The signature is not checked, as the token is only ever
+/// received directly from the token endpoint over TLS.
+fn id_token_claims(id_token: &str) -> Result<IdTokenClaims> {
+    let payload = match id_token.split('.').nth(1) {
+        Some(payload) => payload,
+        None => bail!("Malformed ID token."),
+    };
+    Ok(serde_json::from_slice(&base64_url_decode(payload)?)?)
+}
+
+/// The endpoints of an OpenID Connect authorization server.
+#[derive(Clone, Debug)]
+pub struct OAuthEndpoints {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    userinfo_endpoint: String,
+}
+impl OAuthEndpoints {
+    /// Retrieves the endpoints of an authorization server using OpenID Connect discovery.
+    pub fn discover(issuer: &str) -> Result<OAuthEndpoints> {
+        let issuer = issuer.trim_end_matches('/');
+        let uri = format!("{}/.well-known/openid-configuration", issuer);
+        let json = api_client()?.get(&uri).send()?.error_for_status()?.text()?;
+        let document: DiscoveryDocument = serde_json::from_str(&json)?;
+        Ok(OAuthEndpoints {
+            issuer: issuer.to_owned(),
+            authorization_endpoint: document.authorization_endpoint,
+            token_endpoint: document.token_endpoint,
+            userinfo_endpoint: document.userinfo_endpoint,
+        })
+    }
+
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+
+    /// Returns the URL a user should be sent to in order to log in.
+    pub fn authorize_url(
+        &self, client_id: &str, redirect_uri: &str, state: &str, nonce: &str,
+        code_verifier: &str,
+    ) -> Result<String> {
+        match Url::parse_with_params(&self.authorization_endpoint, &[
+            ("client_id", client_id),
+            ("redirect_uri", redirect_uri),
+            ("response_type", "code"),
+            ("scope", "openid"),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", &pkce_challenge(code_verifier)),
+            ("code_challenge_method", "S256"),
+        ]) {
+            Ok(url) => Ok(url.into_string()),
+            Err(_) => bail!("Invalid authorization endpoint: {}", self.authorization_endpoint),
+        }
+    }
+
+    /// Exchanges an authorization code for the Roblox account of the user that logged in.
+    ///
+    /// The ID token returned must contain the nonce sent with the login, and must be for the
+    /// same account as the user info endpoint returns.
+    pub fn exchange_code(
+        &self, client_id: &str, client_secret: &str, redirect_uri: &str, code: &str,
+        code_verifier: &str, nonce: &str,
+    ) -> Result<RobloxUserID> {
+        let client = api_client()?;
+        let json = client.post(&self.token_endpoint)
+            .basic_auth(client_id, Some(client_secret))
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", redirect_uri),
+                ("code_verifier", code_verifier),
+            ])
+            .send()?.error_for_status()?.text()?;
+        let token: TokenResponse = serde_json::from_str(&json)?;
+        let claims = id_token_claims(&token.id_token)?;
+        cmd_ensure!(claims.nonce.as_ref().map(|x| x.as_str()) == Some(nonce),
+                    "This login link is invalid. Please request a new one from Discord.");
+
+        let json = client.get(&self.userinfo_endpoint)
+            .bearer_auth(&token.access_token)
+            .send()?.error_for_status()?.text()?;
+        let user_info: UserInfoResponse = serde_json::from_str(&json)?;
+        ensure!(user_info.sub == claims.sub, "ID token and user info are for different users.");
+        Ok(RobloxUserID(user_info.sub.parse()?))
+    }
+}
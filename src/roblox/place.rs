@@ -2,7 +2,7 @@ use byteorder::*;
 use errors::*;
 use roblox::lz4;
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{Display, Formatter, Write as FmtWrite, Result as FmtResult};
 use std::io::{Read, Write, Cursor};
 use uuid::Uuid;
@@ -231,6 +231,7 @@ fn write_rblx_container(mut w: impl Write, rblx: &RblxData) -> Result<()> {
 #[derive(Clone, Debug)]
 pub enum LuaConfigValue<'a> {
     Binary(Cow<'a, [u8]>), String(Cow<'a, str>), Double(f64), Nil,
+    Table(Vec<(Cow<'a, str>, LuaConfigValue<'a>)>),
 }
 impl <'a> Display for LuaConfigValue<'a> {
     fn fmt(&self, f: &mut Formatter) -> FmtResult {
@@ -246,6 +247,14 @@ impl <'a> Display for LuaConfigValue<'a> {
             LuaConfigValue::String(ref s) => write!(f, "[[{}]]", s.replace("]", "]]..']'..[[")),
             LuaConfigValue::Double(ref val) => val.fmt(f),
             LuaConfigValue::Nil => f.write_str("nil"),
+            LuaConfigValue::Table(ref entries) => {
+                write!(f, "{{")?;
+                for (key, value) in entries {
+                    write!(f, " [ {} ] = {},", LuaConfigValue::String(key.clone()), value)?;
+                }
+                write!(f, " }}")?;
+                Ok(())
+            }
         }
     }
 }
@@ -315,6 +324,11 @@ impl <'a> From<f64> for LuaConfigValue<'a> {
         LuaConfigValue::Double(d)
     }
 }
+impl <'a> From<BTreeMap<String, String>> for LuaConfigValue<'a> {
+    fn from(map: BTreeMap<String, String>) -> Self {
+        LuaConfigValue::Table(map.into_iter().map(|(k, v)| (Cow::from(k), v.into())).collect())
+    }
+}
 impl <'a, T : Into<LuaConfigValue<'a>>> From<Option<T>> for LuaConfigValue<'a> {
     fn from(o: Option<T>) -> Self {
         match o {
@@ -364,7 +378,7 @@ fn make_config(config: &[LuaConfigEntry], is_server: bool) -> Result<String> {
 }
 
 const PLACE_TEMPLATE: &[u8] = include_bytes!("place-template.rbxl");
-const TEMPLATE_VERSION: &str = "1";
+pub const TEMPLATE_VERSION: &str = "5";
 lazy_static! {
     static ref CONFIG_UUID_NAMESPACE: Uuid =
         "5314b09e-e38b-11e7-952b-5ef6654dc049".parse().unwrap();
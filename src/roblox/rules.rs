@@ -191,6 +191,7 @@ enum RuleOp {
     CheckDevTrustLevel(Condition),
     CheckIsBanned,
     CheckHasPremium,
+    CheckBanList,
 }
 impl RuleOp {
     fn stack_change(&self) -> isize {
@@ -209,6 +210,7 @@ impl RuleOp {
             RuleOp::CheckDevTrustLevel(_)      =>  1,
             RuleOp::CheckIsBanned              =>  1,
             RuleOp::CheckHasPremium =>  1,
+            RuleOp::CheckBanList               =>  1,
         }
     }
 }
@@ -256,6 +258,10 @@ fn parse_term(start: &str, body: &str) -> Result<RuleOp> {
             ensure!(body == "", "has_premium takes no parameters.");
             Ok(RuleOp::CheckHasPremium)
         }
+        "banned" => {
+            ensure!(body == "", "banned takes no parameters.");
+            Ok(RuleOp::CheckBanList)
+        }
         _ => cmd_error!("Unknown term {}({})", start, body),
     }
 }
@@ -674,7 +680,11 @@ impl VerificationSet {
         resolve_ctx.link()
     }
 
-    pub fn verify(&self, id: RobloxUserID) -> Result<HashMap<&str, RuleResult>> {
+    /// Evaluates the rules for a Roblox account. `is_ban_listed` is the result of the `banned()`
+    /// term, which checks the bot's own ban lists rather than Roblox's.
+    pub fn verify(
+        &self, id: RobloxUserID, is_ban_listed: bool,
+    ) -> Result<HashMap<&str, RuleResult>> {
         let mut state = State(vec![RuleResult::Error; self.mem_size], self.stack_base);
         let mut ctx = VerificationContext::new(id);
         let mut outputs = HashMap::new();
@@ -726,6 +736,7 @@ impl VerificationSet {
                 RuleOp::CheckDevTrustLevel(check) => state.push(ctx.check_has_trust_level(check)),
                 RuleOp::CheckIsBanned => state.push(ctx.check_is_banned()),
                 RuleOp::CheckHasPremium => state.push(ctx.check_has_premium()),
+                RuleOp::CheckBanList => state.push(is_ban_listed.into()),
             }
             ip += 1;
         }
@@ -1,5 +1,5 @@
 use core::*;
-use database::Database;
+use database::{Database, MasterKey, MigrationReport};
 use error_report;
 use errors::*;
 use fs2::*;
@@ -7,7 +7,7 @@ use logger;
 use std::env;
 use std::fs::{File, OpenOptions};
 use std::path::{Path, PathBuf};
-use std::process::abort;
+use std::process::{abort, exit};
 
 const LOCK_FILE_NAME: &str = "Sylph-Verifier.lock";
 const DB_FILE_NAME: &str = "Sylph-Verifier.db";
@@ -48,6 +48,95 @@ fn get_root_path() -> PathBuf {
     }
 }
 
+/// Runs the `export` command line mode. This does not take the lock file, as it only reads
+/// from the database, and can be used while the bot is running.
+fn run_export(db_path: PathBuf, args: &[String]) {
+    let args: Vec<_> = args.iter().map(|x| x.as_str()).collect();
+    let result = ExportOptions::parse(&args).and_then(|options| {
+        let database = Database::open_read_only(db_path)?;
+        export_data(&database, &options)
+    });
+    match result {
+        Ok(written) => for (path, rows) in written {
+            println!("Exported {} row(s) to {}", rows, path.display());
+        },
+        Err(e) => {
+            println!("Export failed: {}", e);
+            exit(1)
+        }
+    }
+}
+
+/// Runs the `restore` command line mode, replacing the database with a backup. This refuses to
+/// run while the bot is running.
+fn run_restore(db_path: PathBuf, lock_path: PathBuf, args: &[String]) {
+    let _lock = match check_lock(lock_path) {
+        Ok(lock) => lock,
+        Err(_) => {
+            println!("Sylph-Verifier is currently running. Stop it before restoring a backup.");
+            exit(1)
+        }
+    };
+    let backup = match args.first() {
+        Some(backup) => PathBuf::from(backup),
+        None => {
+            println!("Usage: restore <backup file>");
+            exit(1)
+        }
+    };
+    match restore_from_backup(&backup, &db_path) {
+        Ok(()) => println!("Restored the database from {}.", backup.display()),
+        Err(e) => {
+            println!("Restore failed: {}", e);
+            exit(1)
+        }
+    }
+}
+
+fn print_migration_report(report: &MigrationReport) {
+    if report.applied.is_empty() {
+        println!("The database is already at schema version {}.", report.to_version);
+    } else {
+        println!("Migrating from schema version {} to {}:", report.from_version, report.to_version);
+        for (id, description, time) in &report.applied {
+            println!("  {}: {} ({} ms)", id, description, time.as_millis());
+        }
+    }
+    for id in &report.changed {
+        println!("Warning: Migration '{}' has changed since it was applied.", id);
+    }
+    for item in &report.unexpected_schema {
+        println!("Warning: The migrated schema has an {}.", item);
+    }
+    if report.schema_problems.is_empty() {
+        println!("The migrated schema has everything the expected schema has.");
+    } else {
+        println!("The migrated schema does not match the expected schema:");
+        for problem in &report.schema_problems {
+            println!("  {}", problem);
+        }
+    }
+}
+
+/// Runs the `--migrate-dry-run` command line mode. Migrations are applied to a copy of the
+/// database, so this can be used while the bot is running.
+fn run_migrate_dry_run(db_path: PathBuf, root_path: &Path) {
+    let result = MasterKey::load(root_path)
+        .and_then(|master_key| Database::migrate_dry_run(&db_path, master_key.as_ref()));
+    match result {
+        Ok(report) => {
+            print_migration_report(&report);
+            if !report.schema_problems.is_empty() {
+                exit(1)
+            }
+        }
+        Err(e) => {
+            println!("Migration failed: {}", e);
+            exit(1)
+        }
+    }
+}
+
 pub fn start() {
     env::set_var("RUST_FAILURE_BACKTRACE", "1");
 
@@ -55,6 +144,16 @@ pub fn start() {
     let root_path = get_root_path();
     let db_path = in_path(&root_path, DB_FILE_NAME);
 
+    // Handle command line modes that do not start the bot.
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|x| x.as_str()) {
+        Some("export") => return run_export(db_path, &args[1..]),
+        Some("--migrate-dry-run") => return run_migrate_dry_run(db_path, &root_path),
+        Some("restore") =>
+            return run_restore(db_path, in_path(&root_path, LOCK_FILE_NAME), &args[1..]),
+        _ => { }
+    }
+
     // Acquire the lock file.
     let _lock = match check_lock(in_path(&root_path, LOCK_FILE_NAME)) {
         Ok(lock) => lock,
@@ -73,7 +172,8 @@ pub fn start() {
         debug!("Root directory: {}", root_path.display());
         debug!("Database path: {}", db_path.display());
 
-        let database = Database::new(db_path)?;
+        let master_key = MasterKey::load(&root_path)?;
+        let database = Database::new(db_path, master_key.as_ref())?;
         VerifierCore::new(root_path, database)?.start()?;
         Ok(())
     }).ok();
@@ -47,6 +47,28 @@ pub fn to_english_time_precise(secs: u64) -> String {
 pub fn english_time_diff(from: SystemTime, to: SystemTime) -> String {
     to_english_time(to.duration_since(from).map(|x| x.as_secs()).unwrap_or(0))
 }
+/// Formats a time as a Discord timestamp, which is shown in each user's own time zone.
+pub fn discord_timestamp(time: SystemTime) -> String {
+    format!("<t:{}:T>", time_to_i64(time))
+}
+
+pub fn to_hex(data: &[u8]) -> String {
+    let mut str = String::with_capacity(data.len() * 2);
+    for byte in data {
+        str.push_str(&format!("{:02x}", byte));
+    }
+    str
+}
+pub fn from_hex(str: &str) -> Option<Vec<u8>> {
+    if str.len() % 2 != 0 || !str.is_ascii() {
+        return None
+    }
+    let mut data = Vec::with_capacity(str.len() / 2);
+    for i in (0..str.len()).step_by(2) {
+        data.push(u8::from_str_radix(&str[i..i + 2], 16).ok()?);
+    }
+    Some(data)
+}
 
 // Time to i64
 pub fn time_from_i64(time: i64) -> SystemTime {